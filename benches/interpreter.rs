@@ -0,0 +1,125 @@
+// Plain interpreter vs the decode cache, in millions of instructions per second.
+// `cargo bench --bench interpreter`, no benchmark framework needed for numbers this coarse.
+// With `--features jit` the block compiler gets a column too.
+use chip8::emu::{ClockSpeed, Machine};
+use std::time::{Duration, Instant};
+
+/// Instructions per frame, high enough that the frame bookkeeping doesn't count
+const IPF: u32 = 100_000;
+const FRAMES: u32 = 50;
+/// Best of this many runs, the rest is noise
+const RUNS: usize = 5;
+
+struct Workload {
+    name: &'static str,
+    rom: Vec<u8>,
+    /// No RND, so both runs have to end up in exactly the same state
+    deterministic: bool,
+}
+
+fn workloads() -> Vec<Workload> {
+    vec![
+        Workload {
+            name: "alu loop",
+            // V0 counts, V1/V2 do some arithmetic, then back to 0x202
+            rom: vec![
+                0x60, 0x00, 0x70, 0x01, 0x81, 0x04, 0x82, 0x15, 0x83, 0x26, 0x84, 0x3E, 0x30, 0xFF,
+                0x12, 0x02, 0x12, 0x00,
+            ],
+            deterministic: true,
+        },
+        Workload {
+            name: "self-modifying",
+            // BCD of V0 written over the instruction at 0x20A on every pass
+            rom: vec![
+                0x70, 0x01, 0xA2, 0x0A, 0xF0, 0x33, 0x12, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x12, 0x00,
+            ],
+            deterministic: true,
+        },
+        Workload {
+            name: "ibm logo",
+            rom: include_bytes!("../roms/2-ibm-logo.ch8").to_vec(),
+            deterministic: true,
+        },
+        Workload {
+            name: "maze",
+            rom: include_bytes!("../roms/maze.ch8").to_vec(),
+            deterministic: false,
+        },
+    ]
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Plain,
+    Cached,
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+fn run(rom: &[u8], mode: Mode) -> (Machine, Duration) {
+    let mut machine = Machine::with_speed(ClockSpeed::Ipf(IPF));
+    match mode {
+        Mode::Plain => {}
+        Mode::Cached => machine.set_decode_cache(true),
+        #[cfg(feature = "jit")]
+        Mode::Jit => machine.set_jit(true),
+    }
+    machine.load_rom(rom).expect("rom fits");
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        machine.run_frame().expect("no faults");
+    }
+    (machine, start.elapsed())
+}
+
+fn best_of(rom: &[u8], mode: Mode) -> (Machine, Duration) {
+    (0..RUNS)
+        .map(|_| run(rom, mode))
+        .min_by_key(|(_, elapsed)| *elapsed)
+        .expect("at least one run")
+}
+
+fn mips(elapsed: Duration) -> f64 {
+    (IPF * FRAMES) as f64 / elapsed.as_secs_f64() / 1_000_000.0
+}
+
+fn main() {
+    let modes = [
+        ("plain", Mode::Plain),
+        ("cached", Mode::Cached),
+        #[cfg(feature = "jit")]
+        ("jit", Mode::Jit),
+    ];
+    print!("{:<16}", "");
+    for (name, _) in &modes {
+        print!(" {:>10}", name);
+    }
+    println!();
+    for workload in workloads() {
+        let runs: Vec<_> = modes
+            .iter()
+            .map(|&(_, mode)| best_of(&workload.rom, mode))
+            .collect();
+        let (plain, plain_time) = &runs[0];
+        print!("{:<16}", workload.name);
+        for (i, (machine, elapsed)) in runs.iter().enumerate() {
+            if workload.deterministic {
+                assert!(
+                    plain.snapshot() == machine.snapshot(),
+                    "{}: {} changed how the program ran",
+                    workload.name,
+                    modes[i].0
+                );
+            }
+            print!(" {:>5.1} MIPS", mips(*elapsed));
+        }
+        // how much faster the last column is than the plain interpreter
+        let (_, last_time) = runs.last().expect("at least one mode");
+        println!(
+            " {:>7.2}x",
+            plain_time.as_secs_f64() / last_time.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,643 @@
+// Command line parsing.
+// Small enough that it doesn't need a crate, `chip8 [options] <rom.ch8>` or `chip8 <command> ...`.
+use chip8::emu::{
+    clock::ClockSpeed,
+    disasm::Variant,
+    quirks::QuirksProfile,
+    rng::RngKind,
+    screenshot::{parse_palette, ImageFormat, ImageOptions, MAX_SCALE},
+};
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+
+pub const USAGE: &str = "\
+usage: chip8 [options] <rom.ch8>
+       chip8 run [options] [--headless [headless options]] <rom.ch8>
+       chip8 disasm [--variant <variant>] <rom.ch8>
+       chip8 asm [-o <rom.ch8>] <source>
+       chip8 dap
+       chip8 script <test.rhai> [options] <rom.ch8>
+
+options:
+  --ipf <n>   instructions executed per 60 Hz frame (default 11)
+  --hz <n>    cpu clock in instructions per second, overrides --ipf
+  --quirks <profile>
+              vip, chip48, schip, modern (default) or xochip. Without it the
+              quirks are read from <rom.ch8>.quirks when that file exists
+  --xochip    run as XO-CHIP with 64 KiB of memory, the default for .xo8 ROMs
+  --wav <file>
+              record the audio to a .wav file instead of playing it
+  --state <file>
+              start from a save state (a slot file or its .json export)
+  --seed <n>  seed for CXNN's random numbers, decimal or 0x hex. Headless runs
+              default to a fixed seed, the terminal UI picks a new one each time
+  --rng <rng> xorshift (default) or counter, a tiny streaky 8-bit generator
+  --record <file>
+              record the session's key presses to a replay file on exit.
+              Using the debugger starts the recording over from there, and
+              it doesn't go with --gdb
+  --replay <file>
+              play a replay back, the keypad follows it until it ends. The
+              replay's speed, quirks and seed replace the options above
+  --gdb <port>
+              serve the GDB remote protocol on 127.0.0.1:<port>, or on
+              <host:port>. The machine stops while a client is attached
+  --palette <colours>
+              screenshot colours, 2 or 4 rrggbb separated by commas, off
+              first (default 000000,00ffff,ff00ff,ffffff)
+  --scale <n> PNG and PBM screenshots are n times the screen's size, 1-16
+              (default 1). F12 in the terminal UI saves a PNG next to the ROM
+
+--headless runs without the terminal UI and writes the final screen and the
+machine's state (save state JSON) out. Anything not sent to a file goes to
+stdout, the screen first:
+  --frames <n>
+              how many 60 Hz frames to run (default 600, or the length of
+              the --replay)
+  --keys <file>
+              key presses to play, lines of <frame> <key> down|up
+  --screen <file>
+              write the screen here, as PNG, PBM or ANSI coloured text when
+              the name ends in .png, .pbm or .ans and as text otherwise
+  --format <format>
+              ascii, pbm, png or ansi, overrides the --screen extension
+  --json <file>
+              write the machine state here
+
+disasm prints a listing of the ROM with addresses, raw bytes and labels:
+  --variant <variant>
+              chip8, schip or xochip, which opcodes count as instructions
+              (default xochip for .xo8 ROMs, schip otherwise)
+
+asm turns classic (LD V0, 1) or Octo style (v0 := 1) source into a ROM:
+  -o <file>   where to write it (default the source with a .ch8 extension)
+
+dap speaks the Debug Adapter Protocol on stdin/stdout for editors. The ROM,
+its assembler source and the rest come with the editor's launch request.
+
+script runs a Rhai script against the ROM, without the terminal UI. The
+script drives the machine and checks on it, see src/emu/scripting.rs. Takes
+the options above, apart from --record, --replay and --gdb. Needs chip8 built
+with --features scripting
+";
+
+/// What the command line asked for.
+#[derive(Debug)]
+pub enum Command {
+    /// Play a ROM in the terminal
+    Run(Box<Options>),
+    /// Print a ROM's disassembly
+    Disasm { rom_path: String, variant: Variant },
+    /// Assemble a source file into a ROM
+    Asm {
+        source_path: String,
+        rom_path: String,
+    },
+    /// Debug Adapter Protocol over stdio
+    Dap,
+    /// Run a Rhai script against a ROM
+    Script {
+        script_path: String,
+        options: Box<Options>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub rom_path: String,
+    pub speed: ClockSpeed,
+    /// Overrides the ROM's quirks file
+    pub quirks: Option<QuirksProfile>,
+    pub xo_chip: bool,
+    /// Record audio here instead of playing it
+    pub wav_path: Option<String>,
+    /// Save state to load once the ROM is in
+    pub state_path: Option<String>,
+    /// CXNN's seed, picked by the frontend if not set
+    pub seed: Option<u64>,
+    pub rng: RngKind,
+    /// Write a replay of the session here on exit
+    pub record_path: Option<String>,
+    /// Replay file to play back
+    pub replay_path: Option<String>,
+    /// Address for the GDB stub to listen on
+    pub gdb: Option<String>,
+    /// Palette and scale for screenshots, F12's and --screen's
+    pub image: ImageOptions,
+    /// Run without the terminal UI
+    pub headless: Option<Headless>,
+}
+
+/// `--headless` and the options that go with it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Headless {
+    /// DEFAULT_HEADLESS_FRAMES, or the replay's length, if not set
+    pub frames: Option<u32>,
+    /// Key script, see emu/script.rs
+    pub keys_path: Option<String>,
+    /// Where the screen goes, stdout if not set
+    pub screen_path: Option<String>,
+    /// Picked from `screen_path` if not set
+    pub format: Option<ImageFormat>,
+    /// Where the state goes, stdout if not set
+    pub json_path: Option<String>,
+}
+
+/// Ten seconds worth
+pub const DEFAULT_HEADLESS_FRAMES: u32 = 600;
+
+/// Parses the arguments that follow the program name, subcommand and all.
+pub fn parse_command(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let mut args = args.into_iter().peekable();
+    match args.peek().map(String::as_str) {
+        Some("disasm") => {
+            args.next();
+            parse_disasm_args(args)
+        }
+        Some("asm") => {
+            args.next();
+            parse_asm_args(args)
+        }
+        Some("dap") => {
+            args.next();
+            match args.next() {
+                None => Ok(Command::Dap),
+                Some(arg) if arg == "-h" || arg == "--help" => bail!("{}", USAGE),
+                Some(arg) => bail!("dap takes no arguments, found {}\n\n{}", arg, USAGE),
+            }
+        }
+        Some("script") => {
+            args.next();
+            parse_script_args(args)
+        }
+        Some("run") => {
+            args.next();
+            parse_args(args).map(|options| Command::Run(Box::new(options)))
+        }
+        _ => parse_args(args).map(|options| Command::Run(Box::new(options))),
+    }
+}
+
+fn parse_disasm_args(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let mut rom_path = None;
+    let mut variant = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--variant" => {
+                let name = args.next().ok_or(eyre!("{} needs a value", arg))?;
+                variant = Some(name.parse().map_err(|e| eyre!("{}", e))?);
+            }
+            "-h" | "--help" => bail!("{}", USAGE),
+            flag if flag.starts_with('-') => bail!("unknown option {}\n\n{}", flag, USAGE),
+            _ if rom_path.is_some() => bail!("only one ROM can be disassembled\n\n{}", USAGE),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    let rom_path: String = rom_path.ok_or(eyre!("disasm needs a ROM file\n\n{}", USAGE))?;
+    let variant = variant.unwrap_or(match rom_path.ends_with(".xo8") {
+        true => Variant::XoChip,
+        false => Variant::SuperChip,
+    });
+    Ok(Command::Disasm { rom_path, variant })
+}
+
+fn parse_asm_args(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let mut source_path = None;
+    let mut rom_path = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                rom_path = Some(args.next().ok_or(eyre!("{} needs a value", arg))?)
+            }
+            "-h" | "--help" => bail!("{}", USAGE),
+            flag if flag.starts_with('-') => bail!("unknown option {}\n\n{}", flag, USAGE),
+            _ if source_path.is_some() => bail!("only one source file at a time\n\n{}", USAGE),
+            _ => source_path = Some(arg),
+        }
+    }
+
+    let source_path: String = source_path.ok_or(eyre!("asm needs a source file\n\n{}", USAGE))?;
+    let rom_path = match rom_path {
+        Some(path) => path,
+        None => {
+            let path = std::path::Path::new(&source_path).with_extension("ch8");
+            if path.as_os_str() == source_path.as_str() {
+                bail!(
+                    "{} would overwrite itself, pick an output with -o",
+                    source_path
+                );
+            }
+            path.to_string_lossy().into_owned()
+        }
+    };
+    Ok(Command::Asm {
+        source_path,
+        rom_path,
+    })
+}
+
+/// `chip8 script <test.rhai> [options] <rom.ch8>`, the options are run's.
+fn parse_script_args(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let mut args = args.into_iter();
+    let script_path = match args.next() {
+        Some(arg) if arg == "-h" || arg == "--help" => bail!("{}", USAGE),
+        Some(arg) if !arg.starts_with('-') => arg,
+        _ => bail!("script needs a script file before the options\n\n{}", USAGE),
+    };
+    let options = parse_args(args)?;
+    let conflicts = [
+        (options.headless.is_some(), "--headless"),
+        (options.record_path.is_some(), "--record"),
+        (options.replay_path.is_some(), "--replay"),
+        (options.gdb.is_some(), "--gdb"),
+    ];
+    if let Some((_, flag)) = conflicts.iter().find(|(set, _)| *set) {
+        bail!(
+            "{} doesn't work with script, the script drives the machine",
+            flag
+        );
+    }
+    Ok(Command::Script {
+        script_path,
+        options: Box::new(options),
+    })
+}
+
+/// Parses the options for running a ROM.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options> {
+    let mut rom_path = None;
+    let mut speed = ClockSpeed::default();
+    let mut quirks = None;
+    let mut xo_chip = false;
+    let mut wav_path = None;
+    let mut state_path = None;
+    let mut seed = None;
+    let mut rng = RngKind::default();
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut gdb = None;
+    let mut image = ImageOptions::default();
+    let mut headless = false;
+    let mut frames = None;
+    let mut keys_path = None;
+    let mut screen_path = None;
+    let mut format = None;
+    let mut json_path = None;
+    // the flags that mean nothing without --headless
+    let mut headless_flag = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ipf" => speed = ClockSpeed::Ipf(parse_number(&arg, args.next())?),
+            "--hz" => speed = ClockSpeed::Hz(parse_number(&arg, args.next())?),
+            "--quirks" => {
+                let name = args.next().ok_or(eyre!("{} needs a value", arg))?;
+                quirks = Some(name.parse().map_err(|e| eyre!("{}", e))?);
+            }
+            "--xochip" => xo_chip = true,
+            "--wav" => wav_path = Some(args.next().ok_or(eyre!("{} needs a value", arg))?),
+            "--state" => state_path = Some(args.next().ok_or(eyre!("{} needs a value", arg))?),
+            "--seed" => seed = Some(parse_seed(&arg, args.next())?),
+            "--rng" => {
+                let name = args.next().ok_or(eyre!("{} needs a value", arg))?;
+                rng = name.parse().map_err(|e| eyre!("{}", e))?;
+            }
+            "--record" => record_path = Some(args.next().ok_or(eyre!("{} needs a value", arg))?),
+            "--replay" => replay_path = Some(args.next().ok_or(eyre!("{} needs a value", arg))?),
+            "--gdb" => gdb = Some(parse_address(&arg, args.next())?),
+            "--palette" => {
+                let colours = args.next().ok_or(eyre!("{} needs a value", arg))?;
+                image.palette = parse_palette(&colours).map_err(|e| eyre!("{}: {}", arg, e))?;
+            }
+            "--scale" => {
+                image.scale = parse_number(&arg, args.next())? as usize;
+                if !(1..=MAX_SCALE).contains(&image.scale) {
+                    bail!("{} goes from 1 to {}", arg, MAX_SCALE);
+                }
+            }
+            "--headless" => headless = true,
+            "--frames" | "--keys" | "--screen" | "--format" | "--json" => {
+                let value = args.next().ok_or(eyre!("{} needs a value", arg))?;
+                match arg.as_str() {
+                    "--frames" => frames = Some(parse_number(&arg, Some(value))?),
+                    "--keys" => keys_path = Some(value),
+                    "--screen" => screen_path = Some(value),
+                    "--format" => format = Some(value.parse().map_err(|e| eyre!("{}", e))?),
+                    _ => json_path = Some(value),
+                }
+                headless_flag = Some(arg);
+            }
+            "-h" | "--help" => bail!("{}", USAGE),
+            flag if flag.starts_with('-') => bail!("unknown option {}\n\n{}", flag, USAGE),
+            _ if rom_path.is_some() => bail!("only one ROM can be loaded\n\n{}", USAGE),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    let rom_path = rom_path.ok_or(eyre!("Please provide a path to a ROM file\n\n{}", USAGE))?;
+    if let (false, Some(flag)) = (headless, headless_flag) {
+        bail!("{} only works with --headless\n\n{}", flag, USAGE);
+    }
+    if replay_path.is_some() {
+        if state_path.is_some() {
+            bail!("--replay starts where the recording did, it can't take a --state too");
+        }
+        if keys_path.is_some() {
+            bail!("--replay brings its own keys, it can't take --keys too");
+        }
+        if record_path.is_some() {
+            bail!("--record and --replay don't go together");
+        }
+    }
+    if headless && record_path.is_some() {
+        bail!("--record is for the terminal UI, use --keys to script a headless run");
+    }
+    if record_path.is_some() && gdb.is_some() {
+        bail!("--record can't follow what gdb does to the machine, use one or the other");
+    }
+    if headless && gdb.is_some() {
+        bail!("--gdb is for the terminal UI, a headless run doesn't wait for a debugger");
+    }
+    let headless = headless.then_some(Headless {
+        frames,
+        keys_path,
+        screen_path,
+        format,
+        json_path,
+    });
+    Ok(Options {
+        xo_chip: xo_chip || rom_path.ends_with(".xo8"),
+        rom_path,
+        speed,
+        quirks,
+        wav_path,
+        state_path,
+        seed,
+        rng,
+        record_path,
+        replay_path,
+        gdb,
+        image,
+        headless,
+    })
+}
+
+fn parse_number(flag: &str, value: Option<String>) -> Result<u32> {
+    let value = value.ok_or(eyre!("{} needs a value", flag))?;
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => bail!("{} expects a positive number, got {}", flag, value),
+    }
+}
+
+/// Any u64, in decimal or with a 0x in front in hex. Zero is a fine seed.
+fn parse_seed(flag: &str, value: Option<String>) -> Result<u64> {
+    let value = value.ok_or(eyre!("{} needs a value", flag))?;
+    let parsed = match value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|_| eyre!("{} expects a number, got {}", flag, value))
+}
+
+/// A bare port means localhost, anything with a colon is used as it is.
+fn parse_address(flag: &str, value: Option<String>) -> Result<String> {
+    let value = value.ok_or(eyre!("{} needs a value", flag))?;
+    if value.contains(':') {
+        return Ok(value);
+    }
+    match value.parse::<u16>() {
+        Ok(port) => Ok(format!("127.0.0.1:{}", port)),
+        Err(_) => bail!("{} expects a port or host:port, got {}", flag, value),
+    }
+}
+
+#[cfg(test)]
+mod clitests {
+    use super::{parse_args, parse_command, Command};
+    use chip8::emu::{
+        clock::ClockSpeed,
+        disasm::Variant,
+        quirks::QuirksProfile,
+        rng::RngKind,
+        screenshot::{ImageFormat, ImageOptions},
+    };
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_rom_only() {
+        let options = parse_args(args("roms/maze.ch8")).unwrap();
+        assert_eq!(options.rom_path, "roms/maze.ch8");
+        assert_eq!(options.speed, ClockSpeed::default());
+        assert_eq!(options.quirks, None);
+    }
+
+    #[test]
+    fn test_speed_flags() {
+        let options = parse_args(args("--hz 700 roms/maze.ch8")).unwrap();
+        assert_eq!(options.speed, ClockSpeed::Hz(700));
+        let options = parse_args(args("roms/maze.ch8 --ipf 20")).unwrap();
+        assert_eq!(options.speed, ClockSpeed::Ipf(20));
+    }
+
+    #[test]
+    fn test_quirks_flag() {
+        let options = parse_args(args("--quirks vip roms/maze.ch8")).unwrap();
+        assert_eq!(options.quirks, Some(QuirksProfile::CosmacVip));
+        assert!(parse_args(args("--quirks nes roms/maze.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_xochip() {
+        assert!(!parse_args(args("roms/maze.ch8")).unwrap().xo_chip);
+        assert!(parse_args(args("--xochip roms/game.ch8")).unwrap().xo_chip);
+        assert!(parse_args(args("roms/game.xo8")).unwrap().xo_chip);
+    }
+
+    #[test]
+    fn test_wav() {
+        assert_eq!(parse_args(args("roms/maze.ch8")).unwrap().wav_path, None);
+        let options = parse_args(args("--wav beep.wav roms/maze.ch8")).unwrap();
+        assert_eq!(options.wav_path.as_deref(), Some("beep.wav"));
+        assert!(parse_args(args("roms/maze.ch8 --wav")).is_err());
+    }
+
+    #[test]
+    fn test_state() {
+        let options = parse_args(args("--state roms/maze.ch8.state1 roms/maze.ch8")).unwrap();
+        assert_eq!(options.state_path.as_deref(), Some("roms/maze.ch8.state1"));
+        assert_eq!(options.rom_path, "roms/maze.ch8");
+    }
+
+    #[test]
+    fn test_seed() {
+        let options = parse_args(args("roms/maze.ch8")).unwrap();
+        assert_eq!(options.seed, None);
+        assert_eq!(options.rng, RngKind::Xorshift);
+        let options = parse_args(args("--seed 0 --rng counter roms/maze.ch8")).unwrap();
+        assert_eq!(options.seed, Some(0));
+        assert_eq!(options.rng, RngKind::Counter);
+        let options = parse_args(args("--seed 0xC8 roms/maze.ch8")).unwrap();
+        assert_eq!(options.seed, Some(0xC8));
+        assert!(parse_args(args("--seed -1 roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--rng dice roms/maze.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let options = parse_args(args("--record run.json roms/maze.ch8")).unwrap();
+        assert_eq!(options.record_path.as_deref(), Some("run.json"));
+        assert_eq!(options.replay_path, None);
+        let options = parse_args(args("--headless --replay run.json roms/maze.ch8")).unwrap();
+        assert_eq!(options.replay_path.as_deref(), Some("run.json"));
+        assert!(parse_args(args("--replay run.json --state s.json roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--replay a.json --record b.json roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--headless --record run.json roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--headless --replay a --keys k roms/maze.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_gdb() {
+        assert_eq!(parse_args(args("roms/maze.ch8")).unwrap().gdb, None);
+        let options = parse_args(args("--gdb 1234 roms/maze.ch8")).unwrap();
+        assert_eq!(options.gdb.as_deref(), Some("127.0.0.1:1234"));
+        let options = parse_args(args("--gdb 0.0.0.0:1234 roms/maze.ch8")).unwrap();
+        assert_eq!(options.gdb.as_deref(), Some("0.0.0.0:1234"));
+        assert!(parse_args(args("--gdb gdb roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--headless --gdb 1234 roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--record r.json --gdb 1234 roms/maze.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_image_options() {
+        let options = parse_args(args("roms/maze.ch8")).unwrap();
+        assert_eq!(options.image, ImageOptions::default());
+        let line = "--palette ffffff,000000 --scale 8 roms/maze.ch8";
+        let options = parse_args(args(line)).unwrap();
+        assert_eq!(options.image.palette[0], [255, 255, 255]);
+        assert_eq!(options.image.palette[3], [0, 0, 0]);
+        assert_eq!(options.image.scale, 8);
+        assert!(parse_args(args("--scale 0 roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--scale 17 roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--palette red,blue roms/maze.ch8")).is_err());
+        let options = parse_args(args("--headless --screen end.ans roms/maze.ch8")).unwrap();
+        assert_eq!(
+            options.headless.unwrap().screen_path.as_deref(),
+            Some("end.ans")
+        );
+        let options = parse_args(args("--headless --format ansi roms/maze.ch8")).unwrap();
+        assert_eq!(options.headless.unwrap().format, Some(ImageFormat::Ansi));
+    }
+
+    #[test]
+    fn test_disasm() {
+        match parse_command(args("disasm roms/maze.ch8")).unwrap() {
+            Command::Disasm { rom_path, variant } => {
+                assert_eq!(rom_path, "roms/maze.ch8");
+                assert_eq!(variant, Variant::SuperChip);
+            }
+            command => panic!("{:?}", command),
+        }
+        assert!(matches!(
+            parse_command(args("disasm --variant chip8 roms/game.xo8")).unwrap(),
+            Command::Disasm {
+                variant: Variant::Chip8,
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse_command(args("roms/maze.ch8")).unwrap(),
+            Command::Run(_)
+        ));
+        assert!(parse_command(args("disasm")).is_err());
+        assert!(parse_command(args("disasm --variant nes roms/maze.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_dap() {
+        assert!(matches!(parse_command(args("dap")).unwrap(), Command::Dap));
+        assert!(parse_command(args("dap roms/maze.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_script() {
+        match parse_command(args("script test.rhai --ipf 20 roms/maze.ch8")).unwrap() {
+            Command::Script {
+                script_path,
+                options,
+            } => {
+                assert_eq!(script_path, "test.rhai");
+                assert_eq!(options.rom_path, "roms/maze.ch8");
+                assert_eq!(options.speed, ClockSpeed::Ipf(20));
+            }
+            command => panic!("{:?}", command),
+        }
+        assert!(parse_command(args("script roms/maze.ch8")).is_err());
+        assert!(parse_command(args("script --ipf 20 test.rhai roms/maze.ch8")).is_err());
+        assert!(parse_command(args("script test.rhai --headless roms/maze.ch8")).is_err());
+        assert!(parse_command(args("script test.rhai --replay r.json roms/maze.ch8")).is_err());
+    }
+
+    #[test]
+    fn test_asm() {
+        match parse_command(args("asm roms/pong.8o")).unwrap() {
+            Command::Asm {
+                source_path,
+                rom_path,
+            } => {
+                assert_eq!(source_path, "roms/pong.8o");
+                assert_eq!(rom_path, "roms/pong.ch8");
+            }
+            command => panic!("{:?}", command),
+        }
+        assert!(matches!(
+            parse_command(args("asm -o out.bin pong.asm")).unwrap(),
+            Command::Asm { rom_path, .. } if rom_path == "out.bin"
+        ));
+        assert!(parse_command(args("asm pong.ch8")).is_err());
+        assert!(parse_command(args("asm")).is_err());
+    }
+
+    #[test]
+    fn test_headless() {
+        assert_eq!(parse_args(args("roms/maze.ch8")).unwrap().headless, None);
+        let line = "run --headless --frames 60 --keys keys.txt --screen out.png roms/maze.ch8";
+        let Command::Run(options) = parse_command(args(line)).unwrap() else {
+            panic!("not a run");
+        };
+        let headless = options.headless.unwrap();
+        assert_eq!(headless.frames, Some(60));
+        assert_eq!(headless.keys_path.as_deref(), Some("keys.txt"));
+        assert_eq!(headless.screen_path.as_deref(), Some("out.png"));
+        assert_eq!(headless.format, None);
+        assert_eq!(headless.json_path, None);
+
+        let options = parse_args(args("--headless --format pbm roms/maze.ch8")).unwrap();
+        let headless = options.headless.unwrap();
+        assert_eq!(headless.frames, None);
+        assert_eq!(headless.format, Some(ImageFormat::Pbm));
+
+        assert!(parse_args(args("--frames 60 roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--headless --format gif roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--headless roms/maze.ch8 --json")).is_err());
+    }
+
+    #[test]
+    fn test_bad_args() {
+        assert!(parse_args(args("")).is_err());
+        assert!(parse_args(args("--ipf roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--ipf 0 roms/maze.ch8")).is_err());
+        assert!(parse_args(args("--turbo roms/maze.ch8")).is_err());
+    }
+}
@@ -9,6 +9,8 @@ pub static EMOJIS: phf::Map<&'static str, &'static str> = phf_map! {
     "computer" => "🖥️",
     "runner" => "🏃",
     "handwave" => "👋",
+    "dice" => "🎲",
+    "bug" => "🐛",
 };
 
 // Old EMOJI[n] static
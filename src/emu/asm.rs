@@ -0,0 +1,975 @@
+// Assembler: text to a ROM, one statement per line. Speaks two dialects, mixed freely:
+//
+//   classic (what `chip8 disasm` prints)     Octo
+//   loop:                                    : loop
+//       LD V0, 0x01                              v0 := 1
+//       ADD V0, V1                               v0 += v1
+//       LD I, sprite                             i := sprite
+//       DRW V0, V1, 5                            sprite v0 v1 5
+//       SE V0, 0x10                              if v0 != 0x10 then jump loop
+//       JP loop
+//
+// `;` and `#` start comments, so Octo's `;` return is spelled `return`.
+// `:const NAME value` names a number, `DB`/`DW` or a line of bare numbers is data.
+use super::expr::parse_number;
+use super::mem::ROM_START_ADDRESS;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based
+    pub line: usize,
+    /// 1-based
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Which source line every instruction came from, for debuggers. Data isn't in it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// (address, length in bytes, 1-based line), in address order
+    entries: Vec<(u16, u16, usize)>,
+}
+
+impl SourceMap {
+    /// The first instruction on `line`, or on the next line that has one, along with that line.
+    /// That's where an editor's breakpoint on a comment or label ends up.
+    pub fn addr_of(&self, line: usize) -> Option<(u16, usize)> {
+        // lines only go forwards through the source, so the first one will do
+        self.entries
+            .iter()
+            .find(|entry| entry.2 >= line)
+            .map(|&(addr, _, line)| (addr, line))
+    }
+
+    /// The line of the instruction covering `addr`.
+    pub fn line_of(&self, addr: u16) -> Option<usize> {
+        let index = self.entries.partition_point(|entry| entry.0 <= addr);
+        let &(start, len, line) = self.entries.get(index.checked_sub(1)?)?;
+        (addr < start + len).then_some(line)
+    }
+}
+
+/// Assembles `source` for loading at ROM_START_ADDRESS.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble_with_map(source).map(|(rom, _)| rom)
+}
+
+/// `assemble`, along with where each instruction came from.
+pub fn assemble_with_map(source: &str) -> Result<(Vec<u8>, SourceMap), AsmError> {
+    let mut symbols: HashMap<String, Symbol> = HashMap::new();
+    let mut items = Vec::new();
+    let mut map = SourceMap::default();
+    let mut addr = ROM_START_ADDRESS as i64;
+
+    // first pass: parse everything and work out where the labels land
+    for (index, text) in source.lines().enumerate() {
+        let mut line = Line::new(text, index + 1)?;
+        while let Some((name, column)) = line.label() {
+            let symbol = Symbol {
+                value: addr,
+                line: index + 1,
+                column,
+            };
+            define(&mut symbols, name, symbol)?;
+        }
+        if line.eat_const() {
+            let (name, column) = line.name()?;
+            let value = line.operand()?;
+            line.end()?;
+            let value = match value.value {
+                Value::Number(n) => n,
+                Value::Name(_) => return Err(value.error("constants have to be numbers")),
+            };
+            let symbol = Symbol {
+                value,
+                line: index + 1,
+                column,
+            };
+            define(&mut symbols, name, symbol)?;
+            continue;
+        }
+        for item in line.statement()? {
+            if matches!(item, Item::Op(..) | Item::Long(_)) && addr <= u16::MAX as i64 {
+                map.entries
+                    .push((addr as u16, item.len() as u16, index + 1));
+            }
+            addr += item.len() as i64;
+            items.push(item);
+        }
+    }
+
+    // second pass: fill in the names
+    let mut rom = Vec::new();
+    for item in items {
+        item.encode(&symbols, &mut rom)?;
+    }
+    Ok((rom, map))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Symbol {
+    value: i64,
+    line: usize,
+    column: usize,
+}
+
+fn define(
+    symbols: &mut HashMap<String, Symbol>,
+    name: String,
+    symbol: Symbol,
+) -> Result<(), AsmError> {
+    if let Some(first) = symbols.get(&name) {
+        return Err(AsmError {
+            line: symbol.line,
+            column: symbol.column,
+            message: format!("{} is already defined on line {}", name, first.line),
+        });
+    }
+    symbols.insert(name, symbol);
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Number(i64),
+    Name(String),
+}
+
+/// A number or a name, remembered with where it was written for the error messages.
+#[derive(Debug, Clone)]
+struct Operand {
+    value: Value,
+    line: usize,
+    column: usize,
+}
+
+impl Operand {
+    fn error(&self, message: &str) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            message: message.to_string(),
+        }
+    }
+
+    fn resolve(
+        &self,
+        symbols: &HashMap<String, Symbol>,
+        max: i64,
+        what: &str,
+    ) -> Result<u16, AsmError> {
+        let value = match &self.value {
+            Value::Number(n) => *n,
+            Value::Name(name) => match symbols.get(name) {
+                Some(symbol) => symbol.value,
+                None => return Err(self.error(&format!("unknown label {}", name))),
+            },
+        };
+        if !(0..=max).contains(&value) {
+            return Err(self.error(&format!("{} doesn't fit in {}", value, what)));
+        }
+        Ok(value as u16)
+    }
+}
+
+/// Where an operand goes in the opcode.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    /// The last nibble, DXYN and the scrolls
+    Nibble,
+    /// The second nibble, FN01
+    X,
+    /// The low byte
+    Byte,
+    /// The low 12 bits
+    Addr,
+}
+
+#[derive(Debug)]
+enum Item {
+    /// An opcode with the registers already in, and maybe an operand still to place
+    Op(u16, Option<(Field, Operand)>),
+    /// F000 NNNN
+    Long(Operand),
+    Bytes(Vec<Operand>),
+    Words(Vec<Operand>),
+}
+
+impl Item {
+    fn len(&self) -> usize {
+        match self {
+            Item::Op(..) => 2,
+            Item::Long(_) => 4,
+            Item::Bytes(bytes) => bytes.len(),
+            Item::Words(words) => words.len() * 2,
+        }
+    }
+
+    fn encode(&self, symbols: &HashMap<String, Symbol>, rom: &mut Vec<u8>) -> Result<(), AsmError> {
+        match self {
+            Item::Op(op, operand) => {
+                let operand = match operand {
+                    None => 0,
+                    Some((Field::Nibble, o)) => o.resolve(symbols, 0xF, "a nibble")?,
+                    Some((Field::X, o)) => o.resolve(symbols, 0xF, "a nibble")? << 8,
+                    Some((Field::Byte, o)) => o.resolve(symbols, 0xFF, "a byte")?,
+                    Some((Field::Addr, o)) => o.resolve(symbols, 0xFFF, "12 bits")?,
+                };
+                rom.extend_from_slice(&(op | operand).to_be_bytes());
+            }
+            Item::Long(o) => {
+                rom.extend_from_slice(&[0xF0, 0x00]);
+                rom.extend_from_slice(&o.resolve(symbols, 0xFFFF, "16 bits")?.to_be_bytes());
+            }
+            Item::Bytes(bytes) => {
+                for o in bytes {
+                    rom.push(o.resolve(symbols, 0xFF, "a byte")? as u8);
+                }
+            }
+            Item::Words(words) => {
+                for o in words {
+                    rom.extend_from_slice(&o.resolve(symbols, 0xFFFF, "16 bits")?.to_be_bytes());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Punct(&'static str),
+}
+
+/// Longest first, so `>>=` isn't read as `>` `>=`
+const PUNCTUATION: [&str; 16] = [
+    ">>=", "<<=", ":=", "+=", "-=", "=-", "|=", "&=", "^=", "==", "!=", "[", "]", ",", "-", ":",
+];
+
+/// One line of source, as tokens with the column each starts at.
+struct Line {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    line: usize,
+    /// Where the line ends, for "expected ... at the end" errors
+    end_column: usize,
+}
+
+impl Line {
+    fn new(text: &str, line: usize) -> Result<Self, AsmError> {
+        let code = text.split([';', '#']).next().unwrap_or_default();
+        let mut tokens = Vec::new();
+        let mut rest = code;
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            rest = &rest[start..];
+            let column = code.len() - rest.len() + 1;
+            let word_len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.'))
+                .unwrap_or(rest.len());
+            if word_len > 0 {
+                tokens.push((column, Token::Word(rest[..word_len].to_string())));
+                rest = &rest[word_len..];
+                continue;
+            }
+            let Some(punct) = PUNCTUATION.iter().find(|p| rest.starts_with(**p)) else {
+                let c = rest.chars().next().unwrap_or_default();
+                return Err(AsmError {
+                    line,
+                    column,
+                    message: format!("unexpected {}", c),
+                });
+            };
+            tokens.push((column, Token::Punct(punct)));
+            rest = &rest[punct.len()..];
+        }
+        Ok(Self {
+            tokens,
+            pos: 0,
+            line,
+            end_column: code.trim_end().len() + 1,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|(_, token)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end_column, |(column, _)| *column)
+    }
+
+    fn error(&self, message: &str) -> AsmError {
+        let found = match self.peek() {
+            Some(Token::Word(word)) => format!(", found {}", word),
+            Some(Token::Punct(punct)) => format!(", found {}", punct),
+            None => String::new(),
+        };
+        AsmError {
+            line: self.line,
+            column: self.column(),
+            message: format!("{}{}", message, found),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn end(&self) -> Result<(), AsmError> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(self.error("expected the end of the line")),
+        }
+    }
+
+    /// Skips `punct` if it's next.
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Punct(p)) if *p == punct);
+        self.pos += found as usize;
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), AsmError> {
+        match self.eat(punct) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected {}", punct))),
+        }
+    }
+
+    /// Skips the keyword `word` (any case) if it's next.
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(word));
+        self.pos += found as usize;
+        found
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), AsmError> {
+        match self.eat_word(word) {
+            true => Ok(()),
+            false => Err(self.error(&format!("expected {}", word))),
+        }
+    }
+
+    /// `:const`, Octo's named numbers.
+    fn eat_const(&mut self) -> bool {
+        let found = matches!(
+            (self.peek(), self.peek_at(1)),
+            (Some(Token::Punct(":")), Some(Token::Word(w))) if w.eq_ignore_ascii_case("const")
+        );
+        self.pos += 2 * found as usize;
+        found
+    }
+
+    /// Takes a `name:` or `: name` label off the front of the line.
+    fn label(&mut self) -> Option<(String, usize)> {
+        let column = self.column();
+        match (self.peek(), self.peek_at(1)) {
+            (Some(Token::Word(name)), Some(Token::Punct(":"))) if is_name(name) => {
+                let name = name.clone();
+                self.pos += 2;
+                Some((name, column))
+            }
+            (Some(Token::Punct(":")), Some(Token::Word(name)))
+                if is_name(name) && !name.eq_ignore_ascii_case("const") =>
+            {
+                let name = name.clone();
+                let column = self.tokens[self.pos + 1].0;
+                self.pos += 2;
+                Some((name, column))
+            }
+            _ => None,
+        }
+    }
+
+    fn name(&mut self) -> Result<(String, usize), AsmError> {
+        let column = self.column();
+        match self.peek() {
+            Some(Token::Word(name)) if is_name(name) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok((name, column))
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    fn peek_register(&self) -> Option<u16> {
+        match self.peek() {
+            Some(Token::Word(word)) => register(word),
+            _ => None,
+        }
+    }
+
+    /// VX, as a number to shift into place.
+    fn register(&mut self) -> Result<u16, AsmError> {
+        let x = self
+            .peek_register()
+            .ok_or_else(|| self.error("expected a register"))?;
+        self.pos += 1;
+        Ok(x)
+    }
+
+    fn operand(&mut self) -> Result<Operand, AsmError> {
+        let (line, column) = (self.line, self.column());
+        let value = match self.peek() {
+            Some(Token::Word(word))
+                if word.starts_with(|c: char| c.is_ascii_digit() || c == '$') =>
+            {
+                Value::Number(
+                    parse_number(word)
+                        .ok_or_else(|| self.error(&format!("{} is not a number", word)))?,
+                )
+            }
+            Some(Token::Word(word)) if is_name(word) => Value::Name(word.clone()),
+            _ => return Err(self.error("expected a number or a label")),
+        };
+        self.pos += 1;
+        Ok(Operand {
+            value,
+            line,
+            column,
+        })
+    }
+
+    /// Whatever is left of the line, as one or more items.
+    fn statement(&mut self) -> Result<Vec<Item>, AsmError> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        // a line of bare numbers is data
+        if matches!(self.peek(), Some(Token::Word(w)) if w.starts_with(|c: char| c.is_ascii_digit() || c == '$'))
+        {
+            return self.data().map(|bytes| vec![Item::Bytes(bytes)]);
+        }
+        if let Some(x) = self.peek_register() {
+            self.pos += 1;
+            let item = self.octo_register(x)?;
+            self.end()?;
+            return Ok(vec![item]);
+        }
+        if self.eat_word("if") {
+            return self.octo_if();
+        }
+        let Some(Token::Word(word)) = self.peek().cloned() else {
+            return Err(self.error("expected an instruction"));
+        };
+        self.pos += 1;
+        let item = match word.to_ascii_lowercase().as_str() {
+            "db" => Item::Bytes(self.data()?),
+            "dw" => Item::Words(self.data()?),
+            mnemonic => match self.instruction(mnemonic)? {
+                Some(item) => item,
+                // Octo calls a subroutine by naming it
+                None if self.is_empty() && is_name(&word) => Item::Op(
+                    0x2000,
+                    Some((
+                        Field::Addr,
+                        Operand {
+                            value: Value::Name(word.clone()),
+                            line: self.line,
+                            column: self.tokens[self.pos - 1].0,
+                        },
+                    )),
+                ),
+                None => {
+                    self.pos -= 1;
+                    return Err(self.error("unknown instruction"));
+                }
+            },
+        };
+        self.end()?;
+        Ok(vec![item])
+    }
+
+    /// Numbers or names, commas optional.
+    fn data(&mut self) -> Result<Vec<Operand>, AsmError> {
+        let mut values = vec![self.operand()?];
+        while !self.is_empty() {
+            self.eat(",");
+            values.push(self.operand()?);
+        }
+        Ok(values)
+    }
+
+    /// Everything that starts with a keyword. None if `mnemonic` isn't one.
+    fn instruction(&mut self, mnemonic: &str) -> Result<Option<Item>, AsmError> {
+        let op = |op: u16| Ok(Some(Item::Op(op, None)));
+        match mnemonic {
+            "cls" | "clear" => op(0x00E0),
+            "ret" | "return" => op(0x00EE),
+            "scd" => self.with_operand(0x00C0, Field::Nibble),
+            "scu" => self.with_operand(0x00D0, Field::Nibble),
+            "scr" => op(0x00FB),
+            "scl" => op(0x00FC),
+            "exit" => op(0x00FD),
+            "low" | "lores" => op(0x00FE),
+            "high" | "hires" => op(0x00FF),
+            "scroll" => {
+                self.expect("-")?;
+                match self.peek() {
+                    Some(Token::Word(w)) if w.eq_ignore_ascii_case("down") => {
+                        self.pos += 1;
+                        self.with_operand(0x00C0, Field::Nibble)
+                    }
+                    Some(Token::Word(w)) if w.eq_ignore_ascii_case("up") => {
+                        self.pos += 1;
+                        self.with_operand(0x00D0, Field::Nibble)
+                    }
+                    Some(Token::Word(w)) if w.eq_ignore_ascii_case("right") => {
+                        self.pos += 1;
+                        op(0x00FB)
+                    }
+                    Some(Token::Word(w)) if w.eq_ignore_ascii_case("left") => {
+                        self.pos += 1;
+                        op(0x00FC)
+                    }
+                    _ => Err(self.error("expected down, up, left or right")),
+                }
+            }
+            "sys" | "native" => self.with_operand(0x0000, Field::Addr),
+            "jp" => {
+                if self.peek_register() == Some(0) && self.peek_at(1).is_some() {
+                    self.pos += 1;
+                    self.eat(",");
+                    self.with_operand(0xB000, Field::Addr)
+                } else {
+                    self.with_operand(0x1000, Field::Addr)
+                }
+            }
+            "jump" => self.with_operand(0x1000, Field::Addr),
+            "jump0" => self.with_operand(0xB000, Field::Addr),
+            "call" => self.with_operand(0x2000, Field::Addr),
+            "se" | "sne" => {
+                let x = self.register()?;
+                self.expect(",")?;
+                let equal = mnemonic == "se";
+                match self.peek_register() {
+                    Some(y) => {
+                        self.pos += 1;
+                        op(if equal { 0x5000 } else { 0x9000 } | x << 8 | y << 4)
+                    }
+                    None => {
+                        self.with_operand(if equal { 0x3000 } else { 0x4000 } | x << 8, Field::Byte)
+                    }
+                }
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let store = mnemonic == "save";
+                if self.eat("-") {
+                    let y = self.register()?;
+                    op(if store { 0x5002 } else { 0x5003 } | x << 8 | y << 4)
+                } else {
+                    op(if store { 0xF055 } else { 0xF065 } | x << 8)
+                }
+            }
+            "ld" => self.classic_load().map(Some),
+            "add" => {
+                if self.eat_word("i") {
+                    self.expect(",")?;
+                    let x = self.register()?;
+                    return op(0xF01E | x << 8);
+                }
+                let x = self.register()?;
+                self.expect(",")?;
+                match self.peek_register() {
+                    Some(y) => {
+                        self.pos += 1;
+                        op(0x8004 | x << 8 | y << 4)
+                    }
+                    None => self.with_operand(0x7000 | x << 8, Field::Byte),
+                }
+            }
+            "or" | "and" | "xor" | "sub" | "shr" | "subn" | "shl" => {
+                let n = match mnemonic {
+                    "or" => 0x1,
+                    "and" => 0x2,
+                    "xor" => 0x3,
+                    "sub" => 0x5,
+                    "shr" => 0x6,
+                    "subn" => 0x7,
+                    _ => 0xE,
+                };
+                let x = self.register()?;
+                self.expect(",")?;
+                let y = self.register()?;
+                op(0x8000 | x << 8 | y << 4 | n)
+            }
+            "rnd" => {
+                let x = self.register()?;
+                self.expect(",")?;
+                self.with_operand(0xC000 | x << 8, Field::Byte)
+            }
+            "drw" | "sprite" => {
+                let x = self.register()?;
+                self.eat(",");
+                let y = self.register()?;
+                self.eat(",");
+                self.with_operand(0xD000 | x << 8 | y << 4, Field::Nibble)
+            }
+            "skp" => Ok(Some(Item::Op(0xE09E | self.register()? << 8, None))),
+            "sknp" => Ok(Some(Item::Op(0xE0A1 | self.register()? << 8, None))),
+            "plane" => self.with_operand(0xF001, Field::X),
+            "audio" => op(0xF002),
+            "pitch" => {
+                self.eat(":=");
+                Ok(Some(Item::Op(0xF03A | self.register()? << 8, None)))
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                op(if mnemonic == "delay" { 0xF015 } else { 0xF018 } | x << 8)
+            }
+            "bcd" => Ok(Some(Item::Op(0xF033 | self.register()? << 8, None))),
+            "saveflags" => Ok(Some(Item::Op(0xF075 | self.register()? << 8, None))),
+            "loadflags" => Ok(Some(Item::Op(0xF085 | self.register()? << 8, None))),
+            "i" => self.octo_index().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn with_operand(&mut self, op: u16, field: Field) -> Result<Option<Item>, AsmError> {
+        Ok(Some(Item::Op(op, Some((field, self.operand()?)))))
+    }
+
+    /// The many faces of LD.
+    fn classic_load(&mut self) -> Result<Item, AsmError> {
+        let op = |op: u16| Ok(Item::Op(op, None));
+        if let Some(x) = self.peek_register() {
+            self.pos += 1;
+            self.expect(",")?;
+            if let Some(y) = self.peek_register() {
+                self.pos += 1;
+                return op(0x8000 | x << 8 | y << 4);
+            }
+            if self.eat_word("dt") {
+                return op(0xF007 | x << 8);
+            }
+            if self.eat_word("k") {
+                return op(0xF00A | x << 8);
+            }
+            if self.eat_word("r") {
+                return op(0xF085 | x << 8);
+            }
+            if self.eat("[") {
+                self.expect_word("i")?;
+                self.expect("]")?;
+                return op(0xF065 | x << 8);
+            }
+            return Ok(Item::Op(
+                0x6000 | x << 8,
+                Some((Field::Byte, self.operand()?)),
+            ));
+        }
+        if self.eat_word("i") {
+            self.expect(",")?;
+            if self.eat_word("long") {
+                return Ok(Item::Long(self.operand()?));
+            }
+            return Ok(Item::Op(0xA000, Some((Field::Addr, self.operand()?))));
+        }
+        if self.eat("[") {
+            self.expect_word("i")?;
+            self.expect("]")?;
+            self.expect(",")?;
+            return op(0xF055 | self.register()? << 8);
+        }
+        let targets = [
+            ("dt", 0xF015),
+            ("st", 0xF018),
+            ("f", 0xF029),
+            ("hf", 0xF030),
+            ("b", 0xF033),
+            ("r", 0xF075),
+        ];
+        for (name, base) in targets {
+            if self.eat_word(name) {
+                self.expect(",")?;
+                return op(base | self.register()? << 8);
+            }
+        }
+        Err(self.error("expected a register, I, [I], DT, ST, F, HF, B or R"))
+    }
+
+    /// `i := addr`, `i := long addr`, `i := hex vx`, `i := bighex vx`, `i += vx`
+    fn octo_index(&mut self) -> Result<Item, AsmError> {
+        if self.eat("+=") {
+            return Ok(Item::Op(0xF01E | self.register()? << 8, None));
+        }
+        self.expect(":=")?;
+        if self.eat_word("long") {
+            return Ok(Item::Long(self.operand()?));
+        }
+        if self.eat_word("hex") {
+            return Ok(Item::Op(0xF029 | self.register()? << 8, None));
+        }
+        if self.eat_word("bighex") {
+            return Ok(Item::Op(0xF030 | self.register()? << 8, None));
+        }
+        Ok(Item::Op(0xA000, Some((Field::Addr, self.operand()?))))
+    }
+
+    /// `vx := ...`, `vx += ...` and the other assignments.
+    fn octo_register(&mut self, x: u16) -> Result<Item, AsmError> {
+        let x = x << 8;
+        let op = |op: u16| Ok(Item::Op(op, None));
+        let alu = [
+            ("|=", 0x1),
+            ("&=", 0x2),
+            ("^=", 0x3),
+            ("-=", 0x5),
+            (">>=", 0x6),
+            ("=-", 0x7),
+            ("<<=", 0xE),
+        ];
+        for (punct, n) in alu {
+            if self.eat(punct) {
+                return op(0x8000 | x | self.register()? << 4 | n);
+            }
+        }
+        if self.eat("+=") {
+            return match self.peek_register() {
+                Some(y) => {
+                    self.pos += 1;
+                    op(0x8004 | x | y << 4)
+                }
+                None => Ok(Item::Op(0x7000 | x, Some((Field::Byte, self.operand()?)))),
+            };
+        }
+        self.expect(":=")?;
+        if let Some(y) = self.peek_register() {
+            self.pos += 1;
+            return op(0x8000 | x | y << 4);
+        }
+        if self.eat_word("delay") {
+            return op(0xF007 | x);
+        }
+        if self.eat_word("key") {
+            return op(0xF00A | x);
+        }
+        if self.eat_word("random") {
+            return Ok(Item::Op(0xC000 | x, Some((Field::Byte, self.operand()?))));
+        }
+        Ok(Item::Op(0x6000 | x, Some((Field::Byte, self.operand()?))))
+    }
+
+    /// `if vx == n then`, the statement after `then` (here or on the next line) runs only if
+    /// it holds. Assembles to the skip that jumps over it when it doesn't.
+    fn octo_if(&mut self) -> Result<Vec<Item>, AsmError> {
+        let x = self.register()? << 8;
+        let skip = if self.eat_word("key") {
+            Item::Op(0xE0A1 | x, None)
+        } else if self.eat("-") {
+            self.expect_word("key")?;
+            Item::Op(0xE09E | x, None)
+        } else {
+            let equal = if self.eat("==") {
+                true
+            } else if self.eat("!=") {
+                false
+            } else {
+                return Err(self.error("expected ==, !=, key or -key"));
+            };
+            match self.peek_register() {
+                Some(y) => {
+                    self.pos += 1;
+                    Item::Op(if equal { 0x9000 } else { 0x5000 } | x | y << 4, None)
+                }
+                None => Item::Op(
+                    if equal { 0x4000 } else { 0x3000 } | x,
+                    Some((Field::Byte, self.operand()?)),
+                ),
+            }
+        };
+        self.expect_word("then")?;
+        let mut items = vec![skip];
+        items.extend(self.statement()?);
+        Ok(items)
+    }
+}
+
+fn register(word: &str) -> Option<u16> {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|x| x as u16),
+        _ => None,
+    }
+}
+
+fn is_name(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && register(word).is_none()
+}
+
+#[cfg(test)]
+mod asmtests {
+    use super::{assemble, assemble_with_map, AsmError};
+    use crate::emu::disasm::{listing, Variant};
+
+    #[test]
+    fn test_classic_and_octo_agree() {
+        let classic = "
+            start:
+                LD V0, 0x01      ; comment
+                ADD V0, V1
+                LD I, sprite
+                DRW V0, V1, 5
+                SE V0, 0x10
+                JP start
+                LD [I], V3
+                LD B, V2
+                SHR VA, VB
+                CALL blit
+            blit:
+                RET
+            sprite:
+                DB 0xF0, 0x90 0b11110000
+        ";
+        let octo = "
+            : start
+                v0 := 1          # comment
+                v0 += v1
+                i := sprite
+                sprite v0 v1 5
+                if v0 != 0x10 then jump start
+                save v3
+                bcd v2
+                va >>= vb
+                blit
+            : blit return
+            :const TOP 0xF0
+            : sprite
+                240 0x90 TOP
+        ";
+        let rom = assemble(classic).unwrap();
+        assert_eq!(rom[..8], [0x60, 0x01, 0x80, 0x14, 0xA2, 0x16, 0xD0, 0x15]);
+        assert_eq!(rom[rom.len() - 3..], [0xF0, 0x90, 0xF0]);
+        assert_eq!(assemble(octo).unwrap(), rom);
+    }
+
+    #[test]
+    fn test_xochip() {
+        let source = "
+            LD I, long data
+            i := long 0x1234
+            plane 3
+            audio
+            pitch := v2
+            save v1 - v4
+            LOAD V1-V4
+            scroll-up 2
+            data: DW 0xBEEF
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [
+                0xF0, 0x00, 0x02, 0x14, 0xF0, 0x00, 0x12, 0x34, 0xF3, 0x01, 0xF0, 0x02, 0xF2, 0x3A,
+                0x51, 0x42, 0x51, 0x43, 0x00, 0xD2, 0xBE, 0xEF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassembly_round_trips() {
+        for variant in [Variant::Chip8, Variant::SuperChip, Variant::XoChip] {
+            for op in 0..=0xFFFFu16 {
+                // something after it for F000 NNNN to take along
+                let rom = [(op >> 8) as u8, op as u8, 0x12, 0x34];
+                let source = listing(&rom, 0x200, variant);
+                assert_eq!(assemble(&source), Ok(rom.to_vec()), "{}", source);
+            }
+        }
+        let rom = std::fs::read("roms/maze.ch8").unwrap();
+        let source = listing(&rom, 0x200, Variant::SuperChip);
+        assert_eq!(assemble(&source), Ok(rom));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |line, column, message: &str| AsmError {
+            line,
+            column,
+            message: message.to_string(),
+        };
+        assert_eq!(
+            assemble("CLS\n  LD VG, 1"),
+            Err(error(
+                2,
+                6,
+                "expected a register, I, [I], DT, ST, F, HF, B or R, found VG"
+            ))
+        );
+        assert_eq!(
+            assemble("JP nowhere"),
+            Err(error(1, 4, "unknown label nowhere"))
+        );
+        assert_eq!(
+            assemble("LD V0, 256"),
+            Err(error(1, 8, "256 doesn't fit in a byte"))
+        );
+        assert_eq!(
+            assemble("a: CLS\n: a"),
+            Err(error(2, 3, "a is already defined on line 1"))
+        );
+        assert_eq!(assemble("ADD V1"), Err(error(1, 7, "expected ,")));
+        assert_eq!(
+            assemble("MOV V1, V2"),
+            Err(error(1, 1, "unknown instruction, found MOV"))
+        );
+        assert_eq!(
+            assemble("CLS CLS"),
+            Err(error(1, 5, "expected the end of the line, found CLS"))
+        );
+    }
+
+    #[test]
+    fn test_source_map() {
+        let source = "
+            ; waits for a key
+            start:
+                LD V0, K
+                LD I, long 0xF000 ; four bytes
+                if v0 == 5 then jump start
+
+            sprite:
+                DB 0xF0, 0x90
+                JP start
+        ";
+        let (rom, map) = assemble_with_map(source).unwrap();
+        assert_eq!(rom, assemble(source).unwrap());
+        assert_eq!(map.addr_of(4), Some((0x200, 4)));
+        // comments and labels move on to the next instruction
+        assert_eq!(map.addr_of(1), Some((0x200, 4)));
+        assert_eq!(map.addr_of(6), Some((0x206, 6)));
+        // data lines too, the skip and jump share a line
+        assert_eq!(map.addr_of(9), Some((0x20C, 10)));
+        assert_eq!(map.addr_of(11), None);
+        assert_eq!(map.line_of(0x200), Some(4));
+        assert_eq!(map.line_of(0x205), Some(5));
+        assert_eq!(map.line_of(0x206), Some(6));
+        assert_eq!(map.line_of(0x209), Some(6));
+        assert_eq!(map.line_of(0x20A), None);
+        assert_eq!(map.line_of(0x20C), Some(10));
+        assert_eq!(map.line_of(0x100), None);
+    }
+}
@@ -0,0 +1,78 @@
+// What the buzzer plays while the sound timer is running.
+// Plain CHIP-8 only has a fixed tone, XO-CHIP lets programs load a 1-bit waveform
+// and change its playback rate.
+// http://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html#audio
+use serde::{Deserialize, Serialize};
+
+/// 16 bytes, 128 one-bit samples played MSB first and looped
+pub const PATTERN_SIZE: usize = 16;
+/// Pitch 64 plays the pattern at 4000 samples a second
+pub const DEFAULT_PITCH: u8 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Audio {
+    /// Loaded by F002. None until then, the frontend plays its usual beep instead
+    pub pattern: Option<[u8; PATTERN_SIZE]>,
+    /// Set by FX3A
+    pub pitch: u8,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Audio {
+    pub fn new() -> Self {
+        Self {
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+        }
+    }
+
+    /// Pattern samples per second for the current pitch: 4000 * 2^((pitch - 64) / 48)
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    /// Bit `index` (0-127) of the pattern, wrapping around. False without a pattern.
+    pub fn sample(&self, index: usize) -> bool {
+        match self.pattern {
+            Some(pattern) => {
+                let bit = index % (PATTERN_SIZE * 8);
+                (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod audiotests {
+    use super::Audio;
+
+    #[test]
+    fn test_playback_rate() {
+        let mut audio = Audio::new();
+        assert_eq!(audio.playback_rate(), 4000.0);
+        // 48 steps is an octave
+        audio.pitch = 112;
+        assert!((audio.playback_rate() - 8000.0).abs() < 1e-9);
+        audio.pitch = 16;
+        assert!((audio.playback_rate() - 2000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_sample_reads_msb_first_and_loops() {
+        let mut audio = Audio::new();
+        assert!(!audio.sample(0));
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1000_0001;
+        audio.pattern = Some(pattern);
+        assert!(audio.sample(0));
+        assert!(!audio.sample(1));
+        assert!(audio.sample(7));
+        assert!(audio.sample(128));
+    }
+}
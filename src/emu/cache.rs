@@ -0,0 +1,105 @@
+// Predecoded instructions, for running flat out. Every address gets decoded once and the
+// result is reused until something writes over it (FX33, FX55, 5XY2, a ROM load).
+use super::{error::Chip8Error, instruction::Instruction, iset::OpCode, mem::Memory};
+use std::ops::Range;
+
+/// One slot per byte of RAM, programs are free to jump to odd addresses.
+/// The opcode is kept next to its decoding so the cpu doesn't have to encode it again.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeCache {
+    slots: Vec<Option<(OpCode, Instruction)>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The instruction at `pc`, decoded from `memory` unless it's already known.
+    /// Fails just like a plain fetch would when `pc` runs off the end of RAM.
+    pub fn fetch(
+        &mut self,
+        memory: &mut Memory,
+        pc: u16,
+    ) -> Result<(OpCode, Instruction), Chip8Error> {
+        if self.slots.len() != memory.ram.len() {
+            // first use, or the RAM was swapped for one of a different size
+            self.slots = vec![None; memory.ram.len()];
+            memory.take_written();
+        } else if let Some(range) = memory.take_written() {
+            self.invalidate(range);
+        }
+        let pc = pc as usize;
+        if let Some(&Some(decoded)) = self.slots.get(pc) {
+            self.hits += 1;
+            return Ok(decoded);
+        }
+        self.misses += 1;
+        let bytes = memory.slice(pc, 2)?;
+        let op = OpCode((bytes[0] as u16) << 8 | bytes[1] as u16);
+        let decoded = (op, Instruction::decode_for(op.0, memory.is_xo_chip()));
+        self.slots[pc] = Some(decoded);
+        Ok(decoded)
+    }
+
+    /// Forgets what was decoded from `range`, and the instruction straddling its start.
+    pub fn invalidate(&mut self, range: Range<usize>) {
+        let start = range.start.saturating_sub(1);
+        let end = range.end.min(self.slots.len());
+        if start < end {
+            self.slots[start..end].fill(None);
+        }
+    }
+
+    /// Forgets everything, for when `ram` was changed behind the memory's back.
+    pub fn clear(&mut self) {
+        self.slots.fill(None);
+    }
+
+    /// (hits, misses) since the cache was made.
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+}
+
+#[cfg(test)]
+mod cachetests {
+    use super::DecodeCache;
+    use crate::emu::instruction::Instruction;
+    use crate::emu::mem::Memory;
+
+    #[test]
+    fn test_fetch_and_invalidate() {
+        let mut memory = Memory::new();
+        memory.load_rom(&[0x60, 0x05, 0x12, 0x00]).unwrap();
+        let mut cache = DecodeCache::new();
+        let load = Instruction::LoadImm { x: 0, nn: 5 };
+        assert_eq!(cache.fetch(&mut memory, 0x200).unwrap().1, load);
+        assert_eq!(cache.fetch(&mut memory, 0x200).unwrap().1, load);
+        assert_eq!(cache.stats(), (1, 1));
+
+        // writing the second byte of an instruction throws it away
+        memory.write(0x201, 1).unwrap()[0] = 0x07;
+        let load = Instruction::LoadImm { x: 0, nn: 7 };
+        assert_eq!(cache.fetch(&mut memory, 0x200).unwrap().1, load);
+        assert_eq!(cache.stats(), (1, 2));
+
+        // poking ram directly needs a clear
+        memory.ram[0x201] = 0x09;
+        assert_eq!(cache.fetch(&mut memory, 0x200).unwrap().1, load);
+        cache.clear();
+        let load = Instruction::LoadImm { x: 0, nn: 9 };
+        assert_eq!(cache.fetch(&mut memory, 0x200).unwrap().1, load);
+    }
+
+    #[test]
+    fn test_fetch_past_the_end() {
+        let mut memory = Memory::new();
+        let mut cache = DecodeCache::new();
+        assert!(cache.fetch(&mut memory, 0xFFF).is_err());
+        assert!(cache.fetch(&mut memory, 0x1000).is_err());
+        assert!(cache.fetch(&mut memory, 0xFFE).is_ok());
+    }
+}
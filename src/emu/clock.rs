@@ -0,0 +1,144 @@
+// Frame scheduling: how many cpu instructions run per 60 Hz frame, and when the next frame starts.
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Timers count down at 60 Hz, everything else is scheduled around that.
+pub const FRAME_RATE: u32 = 60;
+/// ~660 instructions a second, fast enough for most CHIP-8 games.
+pub const DEFAULT_IPF: u32 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockSpeed {
+    /// Fixed number of instructions per 60 Hz frame
+    Ipf(u32),
+    /// CPU clock in instructions per second, spread as evenly as possible over the frames
+    Hz(u32),
+}
+
+impl Default for ClockSpeed {
+    fn default() -> Self {
+        ClockSpeed::Ipf(DEFAULT_IPF)
+    }
+}
+
+/// Decides how many instructions each frame gets. Purely a counter, no wall clock involved,
+/// so headless runs are just as deterministic as the real-time ones.
+#[derive(Debug)]
+pub struct FrameClock {
+    speed: ClockSpeed,
+    /// Cycles left over when Hz isn't a multiple of FRAME_RATE, carried into the next frame
+    leftover_cycles: u32,
+}
+
+impl FrameClock {
+    pub fn new(speed: ClockSpeed) -> Self {
+        Self {
+            speed,
+            leftover_cycles: 0,
+        }
+    }
+
+    pub fn speed(&self) -> ClockSpeed {
+        self.speed
+    }
+
+    /// The cycles carried into the next frame, only ever non-zero with `ClockSpeed::Hz`.
+    pub fn leftover_cycles(&self) -> u32 {
+        self.leftover_cycles
+    }
+
+    pub fn set_leftover_cycles(&mut self, cycles: u32) {
+        self.leftover_cycles = cycles % FRAME_RATE;
+    }
+
+    /// Number of instructions to execute in the upcoming frame.
+    /// e.g. 500 Hz runs 8, 8, 9, 8, 8, 9, ... instructions so that 60 frames add up to 500.
+    pub fn instructions_for_frame(&mut self) -> u32 {
+        match self.speed {
+            ClockSpeed::Ipf(ipf) => ipf,
+            ClockSpeed::Hz(hz) => {
+                let cycles = hz + self.leftover_cycles;
+                self.leftover_cycles = cycles % FRAME_RATE;
+                cycles / FRAME_RATE
+            }
+        }
+    }
+}
+
+/// Keeps a real-time frontend at 60 frames a second using wall-clock deadlines.
+#[derive(Debug)]
+pub struct FramePacer {
+    frame_duration: Duration,
+    next_deadline: Option<Instant>,
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        Self {
+            frame_duration: Duration::from_secs(1) / FRAME_RATE,
+            next_deadline: None,
+        }
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        self.frame_duration
+    }
+
+    /// Sleeps until the current frame's wall-clock deadline and schedules the next one.
+    ///
+    /// Deadlines are advanced by exactly one frame so small oversleeps don't add up.
+    /// If we fall more than a frame behind (e.g. the terminal stalled) the schedule is
+    /// reset instead of running a burst of catch-up frames.
+    pub fn wait_for_next_frame(&mut self) {
+        let now = Instant::now();
+        let deadline = self.next_deadline.unwrap_or(now + self.frame_duration);
+        if deadline > now {
+            thread::sleep(deadline - now);
+        }
+        let next = deadline + self.frame_duration;
+        self.next_deadline = if next < Instant::now() {
+            trace!(target: "clock", "fell behind, resetting frame deadline");
+            Some(Instant::now() + self.frame_duration)
+        } else {
+            Some(next)
+        };
+    }
+}
+
+#[cfg(test)]
+mod clocktests {
+    use super::{ClockSpeed, FrameClock, FRAME_RATE};
+
+    #[test]
+    fn test_ipf_is_constant() {
+        let mut clock = FrameClock::new(ClockSpeed::Ipf(7));
+        for _ in 0..100 {
+            assert_eq!(clock.instructions_for_frame(), 7);
+        }
+    }
+
+    #[test]
+    fn test_hz_adds_up_over_a_second() {
+        let mut clock = FrameClock::new(ClockSpeed::Hz(500));
+        let per_frame: Vec<u32> = (0..FRAME_RATE)
+            .map(|_| clock.instructions_for_frame())
+            .collect();
+        assert_eq!(per_frame.iter().sum::<u32>(), 500);
+        // spread evenly, never more than one instruction apart
+        assert!(per_frame.iter().all(|&n| n == 8 || n == 9));
+    }
+
+    #[test]
+    fn test_hz_slower_than_frame_rate() {
+        let mut clock = FrameClock::new(ClockSpeed::Hz(30));
+        let per_frame: Vec<u32> = (0..4).map(|_| clock.instructions_for_frame()).collect();
+        assert_eq!(per_frame, vec![0, 1, 0, 1]);
+    }
+}
@@ -2,14 +2,21 @@
 #![allow(unused_variables)]
 // Contains the CPUs Registers, OpCodes, and their impls.
 use super::{
-    iset::{Chip8ISet, ExecutionResult, Nibbles, OpCode},
+    audio::Audio,
+    cache::DecodeCache,
+    error::{Chip8Error, Fault},
+    input::Keypad,
+    instruction::Instruction,
+    iset::{Chip8ISet, ExecutionResult, OpCode},
+    quirks::Quirks,
+    rng::Rng,
     timer::Timer,
 };
 use crate::emu::{gpu::Gpu, mem::Memory};
+use serde::{Deserialize, Serialize};
 
 /// https://github.com/mattmikolay/chip-8/wiki/CHIP%E2%80%908-Instruction-Set
-use color_eyre::Result;
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[allow(dead_code)] // REMOVE THIS WHEN DONE
 pub struct Cpu {
     pub current_opcode: OpCode,
@@ -23,6 +30,23 @@ pub struct Cpu {
     /// even tho u16, can only go to 12-bit mem addys b/c chip8 MAX RAM is 4096
     /// ex. 1111 1111 1111 -> 0xFFF -> 4095 -> memsize
     pub index_register: u16,
+    /// How the ambiguous opcodes behave, see quirks.rs
+    pub quirks: Quirks,
+    /// Raised by the machine at the start of every frame, DXYN waits on it with the
+    /// display_wait quirk
+    pub vblank: bool,
+    /// SUPER-CHIP's persistent RPL user flags, saved and restored by FX75/FX85
+    pub rpl_flags: [u8; 16],
+    /// Set once the program runs 00FD, the pc stays on that instruction from then on
+    pub exited: bool,
+    /// CXNN's numbers, see rng.rs
+    pub rng: Rng,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cpu {
@@ -34,6 +58,11 @@ impl Cpu {
             program_counter: 0x200,
             stack: [0; 16],
             stack_pointer: 0,
+            quirks: Quirks::default(),
+            vblank: false,
+            rpl_flags: [0; 16],
+            exited: false,
+            rng: Rng::default(),
         }
     }
 
@@ -42,11 +71,11 @@ impl Cpu {
     //}
 
     /// Retrieves opcode from memory into the cpu
-    pub fn fetch_opcode(&mut self, memory: &Memory) {
-        let opcode_high: u8 = memory.ram[self.program_counter as usize];
-        let opcode_low: u8 = memory.ram[self.program_counter as usize + 1];
-        let opcode: u16 = (opcode_high as u16) << 8 | opcode_low as u16;
+    pub fn fetch_opcode(&mut self, memory: &Memory) -> Result<(), Chip8Error> {
+        let bytes = memory.slice(self.program_counter as usize, 2)?;
+        let opcode: u16 = (bytes[0] as u16) << 8 | bytes[1] as u16;
         self.current_opcode = OpCode(opcode);
+        Ok(())
     }
 
     pub fn process(
@@ -54,69 +83,181 @@ impl Cpu {
         memory: &mut Memory,
         gpu: &mut Gpu,
         timers: &mut Timer,
-    ) -> Result<ExecutionResult> {
-        // Map the current OpCode to an actual function.
+        keypad: &mut Keypad,
+        audio: &mut Audio,
+    ) -> Result<ExecutionResult, Chip8Error> {
         // DECODE and Process
-        //self.program_counter += 0x02; // go to next instruction.
-        let result = match &self.current_opcode.into_tuple() {
-            (0, 0, 0xE, 0xE) => OpCode::_00ee(self),
-            (0, 0, 0xE, 0) => OpCode::_00e0(gpu),
-            (0, _, _, _) => OpCode::_0nnn(self),
-            (1, _, _, _) => OpCode::_1nnn(self), // jump
-            (2, _, _, _) => OpCode::_2nnn(self),
-            (3, _, _, _) => OpCode::_3xnn(self),
-            (4, _, _, _) => OpCode::_4xnn(self),
-            (5, _, _, 0) => OpCode::_5xy0(self),
-            (6, _, _, _) => OpCode::_6xnn(self),
-            (7, _, _, _) => OpCode::_7xnn(self),
-            (8, _, _, 0) => OpCode::_8xy0(self),
-            (8, _, _, 1) => OpCode::_8xy1(self),
-            (8, _, _, 2) => OpCode::_8xy2(self),
-            (8, _, _, 3) => OpCode::_8xy3(self),
-            (8, _, _, 4) => OpCode::_8xy4(self),
-            (8, _, _, 5) => OpCode::_8xy5(self),
-            (8, _, _, 6) => OpCode::_8xy6(self),
-            (8, _, _, 7) => OpCode::_8xy7(self),
-            (8, _, _, 0xE) => OpCode::_8xye(self),
-            (9, _, _, 0) => OpCode::_9xy0(self),
-            (0xA, _, _, _) => OpCode::annn(self),
-            (0xB, _, _, _) => OpCode::bnnn(self),
-            (0xC, _, _, _) => OpCode::cxnn(self),
-            (0xD, _, _, _) => OpCode::dxyn(self, memory, gpu),
-            (0xE, _, 9, 0xE) => OpCode::ex9e(self),
-            (0xE, _, 0xA, 1) => OpCode::exa1(self),
-            (0xF, _, 0, 7) => OpCode::fx07(self, timers),
-            (0xF, _, 0, 0xA) => OpCode::fx0a(self, gpu),
-            (0xF, _, 1, 5) => OpCode::fx15(self, timers),
-            (0xF, _, 1, 8) => OpCode::fx18(self, timers),
-            (0xF, _, 1, 0xE) => OpCode::fx1e(self),
-            (0xF, _, 2, 9) => OpCode::fx29(self),
-            (0xF, _, 3, 3) => OpCode::fx33(self, memory),
-            (0xF, _, 5, 5) => OpCode::fx55(self, memory),
-            (0xF, _, 6, 5) => OpCode::fx65(self, memory),
-            (a, b, c, d) => {
-                warn!(target: "cpu", "Instruction not implemented {:x?}", (a, b, c, d));
-                ExecutionResult::Advanced
-            }
-        };
-        Ok(result)
+        let instruction = Instruction::decode_for(self.current_opcode.0, memory.is_xo_chip());
+        self.execute(instruction, memory, gpu, timers, keypad, audio)
+    }
+
+    /// Runs an already decoded instruction, `current_opcode` is only used for error reports.
+    pub fn execute(
+        &mut self,
+        instruction: Instruction,
+        memory: &mut Memory,
+        gpu: &mut Gpu,
+        timers: &mut Timer,
+        keypad: &mut Keypad,
+        audio: &mut Audio,
+    ) -> Result<ExecutionResult, Chip8Error> {
+        use Instruction::*;
+        match instruction {
+            Ret => OpCode::_00ee(self),
+            Cls => OpCode::_00e0(gpu),
+            ScrollDown(n) => OpCode::_00cn(gpu, n),
+            ScrollRight => OpCode::_00fb(gpu),
+            ScrollLeft => OpCode::_00fc(gpu),
+            Exit => OpCode::_00fd(self),
+            LowRes => OpCode::_00fe(gpu),
+            HighRes => OpCode::_00ff(gpu),
+            ScrollUp(n) => OpCode::_00dn(gpu, n),
+            Sys(nnn) => OpCode::_0nnn(self, nnn),
+            Jump(nnn) => OpCode::_1nnn(self, nnn),
+            Call(nnn) => OpCode::_2nnn(self, nnn),
+            SkipEqImm { x, nn } => OpCode::_3xnn(self, x, nn),
+            SkipNeImm { x, nn } => OpCode::_4xnn(self, x, nn),
+            SkipEqReg { x, y } => OpCode::_5xy0(self, x, y),
+            SaveRange { x, y } => OpCode::_5xy2(self, memory, x, y),
+            LoadRange { x, y } => OpCode::_5xy3(self, memory, x, y),
+            LoadImm { x, nn } => OpCode::_6xnn(self, x, nn),
+            AddImm { x, nn } => OpCode::_7xnn(self, x, nn),
+            Move { x, y } => OpCode::_8xy0(self, x, y),
+            Or { x, y } => OpCode::_8xy1(self, x, y),
+            And { x, y } => OpCode::_8xy2(self, x, y),
+            Xor { x, y } => OpCode::_8xy3(self, x, y),
+            Add { x, y } => OpCode::_8xy4(self, x, y),
+            Sub { x, y } => OpCode::_8xy5(self, x, y),
+            ShiftRight { x, y } => OpCode::_8xy6(self, x, y),
+            SubN { x, y } => OpCode::_8xy7(self, x, y),
+            ShiftLeft { x, y } => OpCode::_8xye(self, x, y),
+            SkipNeReg { x, y } => OpCode::_9xy0(self, x, y),
+            LoadI(nnn) => OpCode::annn(self, nnn),
+            JumpOffset(nnn) => OpCode::bnnn(self, nnn),
+            Random { x, nn } => OpCode::cxnn(self, x, nn),
+            Draw { x, y, n } => OpCode::dxyn(self, memory, gpu, x, y, n),
+            SkipKey { x } => OpCode::ex9e(self, keypad, x),
+            SkipNotKey { x } => OpCode::exa1(self, keypad, x),
+            LoadILong => OpCode::f000(self, memory),
+            Plane(n) => OpCode::fn01(gpu, n),
+            Audio => OpCode::f002(self, memory, audio),
+            LoadDelay { x } => OpCode::fx07(self, timers, x),
+            WaitKey { x } => OpCode::fx0a(self, keypad, x),
+            SetDelay { x } => OpCode::fx15(self, timers, x),
+            SetSound { x } => OpCode::fx18(self, timers, x),
+            AddI { x } => OpCode::fx1e(self, x),
+            Font { x } => OpCode::fx29(self, x),
+            BigFont { x } => OpCode::fx30(self, x),
+            Bcd { x } => OpCode::fx33(self, memory, x),
+            Pitch { x } => OpCode::fx3a(self, audio, x),
+            Store { x } => OpCode::fx55(self, memory, x),
+            Load { x } => OpCode::fx65(self, memory, x),
+            SaveFlags { x } => OpCode::fx75(self, x),
+            LoadFlags { x } => OpCode::fx85(self, x),
+            Unknown(op) => Err(Chip8Error::UnknownOpcode {
+                op,
+                pc: self.program_counter,
+            }),
+        }
     }
 
     // main emulation loop tick - fetches & processes a single opcode
-    pub fn tick(&mut self, memory: &mut Memory, gpu: &mut Gpu, timers: &mut Timer) -> Result<()> {
+    // On error the pc stays on the faulting instruction so the state can be inspected.
+    pub fn tick(
+        &mut self,
+        memory: &mut Memory,
+        gpu: &mut Gpu,
+        timers: &mut Timer,
+        keypad: &mut Keypad,
+        audio: &mut Audio,
+    ) -> Result<(), Fault> {
+        let fetch = |cpu: &mut Cpu, memory: &mut Memory| {
+            cpu.fetch_opcode(memory)?;
+            Ok(Instruction::decode_for(
+                cpu.current_opcode.0,
+                memory.is_xo_chip(),
+            ))
+        };
+        self.tick_with(fetch, memory, gpu, timers, keypad, audio)
+    }
+
+    /// `tick`, with the instruction coming out of `cache` instead of being decoded again.
+    /// Leaves the cpu in exactly the same state as `tick` would.
+    pub fn tick_cached(
+        &mut self,
+        cache: &mut DecodeCache,
+        memory: &mut Memory,
+        gpu: &mut Gpu,
+        timers: &mut Timer,
+        keypad: &mut Keypad,
+        audio: &mut Audio,
+    ) -> Result<(), Fault> {
+        let fetch = |cpu: &mut Cpu, memory: &mut Memory| {
+            let (op, instruction) = cache.fetch(memory, cpu.program_counter)?;
+            cpu.current_opcode = op;
+            Ok(instruction)
+        };
+        self.tick_with(fetch, memory, gpu, timers, keypad, audio)
+    }
+
+    fn tick_with(
+        &mut self,
+        fetch: impl FnOnce(&mut Cpu, &mut Memory) -> Result<Instruction, Chip8Error>,
+        memory: &mut Memory,
+        gpu: &mut Gpu,
+        timers: &mut Timer,
+        keypad: &mut Keypad,
+        audio: &mut Audio,
+    ) -> Result<(), Fault> {
         info!(target: "cpu", "cpu.tick called");
-        let _ = self.fetch_opcode(memory);
-        info!(target: "cpu", "cpu.current_opcode: {:x?}", self.current_opcode);
-        debug!(target: "cpu", "cpu: {:x?}", self);
-        gpu.debug_screen_print_string();
-        if let Ok(result) = self.process(memory, gpu, timers) {
-            match result {
-                ExecutionResult::Advanced => self.program_counter += 2,
-                ExecutionResult::Skipped => self.program_counter += 4,
-                ExecutionResult::Jumped => { /* PC was set by the instruction; do nothing */ }
+        let pc = self.program_counter;
+        let result = fetch(self, memory).and_then(|instruction| {
+            let op = self.current_opcode;
+            info!(target: "cpu", "cpu.current_opcode: {:04x} {}", op.0, op);
+            debug!(target: "cpu", "cpu: {:x?}", self);
+            gpu.debug_screen_print_string();
+            self.execute(instruction, memory, gpu, timers, keypad, audio)
+        });
+        self.retire(pc, result, memory)
+    }
+
+    /// Moves the pc on once the instruction that was at `pc` has run, or turns its error into
+    /// a `Fault`. Shared with the block compiler so both agree on where execution goes next.
+    pub(crate) fn retire(
+        &mut self,
+        pc: u16,
+        result: Result<ExecutionResult, Chip8Error>,
+        memory: &Memory,
+    ) -> Result<(), Fault> {
+        let result = match result {
+            Ok(result) => result,
+            Err(error) => {
+                let fault = Fault {
+                    pc,
+                    opcode: self.current_opcode.0,
+                    error,
+                };
+                error!(target: "cpu", "cpu fault: {}", fault);
+                return Err(fault);
+            }
+        };
+        match result {
+            // not `pc`, 00EE has already moved the pc back to the call
+            ExecutionResult::Advanced => {
+                self.program_counter = self.program_counter.wrapping_add(2)
+            }
+            ExecutionResult::Skipped => {
+                // XO-CHIP's F000 NNNN is 4 bytes long, skips have to jump over all of it
+                let next = pc.wrapping_add(2);
+                let skip = match memory.slice(next as usize, 2) {
+                    Ok([0xF0, 0x00]) if memory.is_xo_chip() => 6,
+                    _ => 4,
+                };
+                self.program_counter = pc.wrapping_add(skip);
             }
-        } else {
-            eprintln!("failed to process.: {}", "errrrrrrrrr");
+            ExecutionResult::Jumped => { /* PC was set by the instruction; do nothing */ }
+            ExecutionResult::Waiting => { /* Run the same instruction again next tick */ }
+            ExecutionResult::Exited => self.exited = true,
         }
         memory.print_memory_bytes(self.program_counter.into(), 10); // print next 5 instructions
         Ok(())
@@ -125,8 +266,14 @@ impl Cpu {
 
 #[cfg(test)]
 mod cputests {
+    use crate::emu::audio::Audio;
     use crate::emu::cpu::Cpu;
-    use crate::emu::iset::{Chip8ISet, OpCode};
+    use crate::emu::error::{Chip8Error, Fault};
+    use crate::emu::input::Keypad;
+    use crate::emu::iset::{Chip8ISet, ExecutionResult, OpCode};
+    use crate::emu::mem::XO_RAM_SIZE;
+    use crate::emu::quirks::{Quirks, QuirksProfile};
+    use crate::emu::rng::{Rng, RngKind};
     use crate::emu::Gpu;
     use crate::emu::Memory;
     use crate::emu::Timer;
@@ -162,15 +309,13 @@ mod cputests {
     }
 
     fn test_init_gpu() -> Gpu {
-        let gpu = Gpu::new();
-
-        gpu
+        Gpu::new()
     }
 
     #[test]
     fn test_00e0() {
         let mut gpu = test_init_gpu();
-        OpCode::_00e0(&mut gpu);
+        OpCode::_00e0(&mut gpu).unwrap();
         assert!(gpu.screen.iter().all(|&pixel| !pixel));
     }
 
@@ -194,7 +339,7 @@ mod cputests {
         );
         // artifically set the stack pointer to be at second index
         cpu.stack_pointer = 1;
-        OpCode::_00ee(&mut cpu);
+        OpCode::_00ee(&mut cpu).unwrap();
         assert!(cpu.stack_pointer == 0, "sp: {:?}", cpu.stack_pointer);
         // Remember init cpu method will create our stack such that:
         // stack: [1, 2, 3, ..., 16]
@@ -207,18 +352,27 @@ mod cputests {
     }
 
     #[test]
-    #[should_panic(expected = "Stack underflow!")]
     fn test_00ee_underflow() {
         let mut cpu = test_init_cpu();
         cpu.stack_pointer = 0;
-        OpCode::_00ee(&mut cpu);
+        assert_eq!(OpCode::_00ee(&mut cpu), Err(Chip8Error::StackUnderflow));
+    }
+
+    #[test]
+    fn test_2nnn_overflow() {
+        let mut cpu = test_init_cpu();
+        cpu.stack_pointer = 16;
+        assert_eq!(
+            OpCode::_2nnn(&mut cpu, 0x400),
+            Err(Chip8Error::StackOverflow)
+        );
+        assert_eq!(cpu.program_counter, 0x200);
     }
 
     #[test]
     fn test_1nnn() {
         let mut cpu = test_init_cpu();
-        cpu.current_opcode = OpCode(0x1555);
-        OpCode::_1nnn(&mut cpu);
+        OpCode::_1nnn(&mut cpu, 0x555).unwrap();
         assert!(
             cpu.program_counter == 0x555,
             "pc: {:x?}",
@@ -230,9 +384,8 @@ mod cputests {
     fn test_6xnn() {
         let mut cpu = test_init_cpu();
         let mut register = 1;
-        cpu.current_opcode = OpCode(0x6155);
         println!("before registers: {:x?}", cpu.registers);
-        OpCode::_6xnn(&mut cpu);
+        OpCode::_6xnn(&mut cpu, 1, 0x55).unwrap();
         println!("after  registers: {:x?}", cpu.registers);
         assert!(
             cpu.registers[register] == 0x55,
@@ -241,8 +394,7 @@ mod cputests {
             cpu.registers[register],
         );
         register = 7;
-        cpu.current_opcode = OpCode(0x6755);
-        OpCode::_6xnn(&mut cpu);
+        OpCode::_6xnn(&mut cpu, 7, 0x55).unwrap();
         assert!(
             cpu.registers[register] == 0x55,
             "cpu.registers[{:?}]: {:x?}",
@@ -256,16 +408,15 @@ mod cputests {
         let mut cpu = test_init_cpu();
         let register = 1;
         let value_added = 0x55;
-        cpu.current_opcode = OpCode(0x7155);
         let before_vx = cpu.registers[register];
         println!("before registers: {:x?}", cpu.registers);
-        OpCode::_7xnn(&mut cpu);
+        OpCode::_7xnn(&mut cpu, 1, 0x55).unwrap();
         println!("after  registers: {:x?}", cpu.registers);
         let after_vx = cpu.registers[register];
         assert!(after_vx == before_vx + value_added, "did not add correctly");
         // at this point v[1] == 0x5A = 0x05 + 0x55
         // run again
-        OpCode::_7xnn(&mut cpu);
+        OpCode::_7xnn(&mut cpu, 1, 0x55).unwrap();
         assert!(
             cpu.registers[register] == 0xAF,
             "cpu.registers[{:?}]: {:x?}",
@@ -277,8 +428,7 @@ mod cputests {
     #[test]
     fn test_annn() {
         let mut cpu = test_init_cpu();
-        cpu.current_opcode = OpCode(0xa234);
-        OpCode::annn(&mut cpu);
+        OpCode::annn(&mut cpu, 0x234).unwrap();
         assert!(
             cpu.index_register == 0x234,
             "cpu.index_register: {:?}",
@@ -286,6 +436,19 @@ mod cputests {
         );
     }
 
+    #[test]
+    fn test_cxnn() {
+        let mut cpu = test_init_cpu();
+        cpu.rng = Rng::new(RngKind::Xorshift, 42);
+        let mut expected = cpu.rng;
+        OpCode::cxnn(&mut cpu, 3, 0xFF).unwrap();
+        assert_eq!(cpu.registers[3], expected.next_byte());
+        OpCode::cxnn(&mut cpu, 3, 0x0F).unwrap();
+        assert_eq!(cpu.registers[3], expected.next_byte() & 0x0F);
+        OpCode::cxnn(&mut cpu, 3, 0x00).unwrap();
+        assert_eq!(cpu.registers[3], 0);
+    }
+
     #[test]
     fn test_dxyn_draw_without_collision() {
         let mut cpu = test_init_cpu();
@@ -370,9 +533,8 @@ mod cputests {
         cpu.registers[4] = VX; // opcode D[4]yn
         cpu.registers[0xa] = VY; // opcode Dx[a]n
         const NUM_BYTES_TO_DRAW: u16 = 2; // opcode Dxy[2]
-        cpu.current_opcode = OpCode(0xD4A2);
-        // This should start drawing 2 bytes starting at the I index_register
-        // lets preview these bytes
+                                          // This should start drawing 2 bytes starting at the I index_register
+                                          // lets preview these bytes
         let start = cpu.index_register as usize;
         let end = (cpu.index_register + NUM_BYTES_TO_DRAW) as usize;
         println!("preview bytes to draw: {:x?}", &mem.ram[start..end]);
@@ -380,7 +542,7 @@ mod cputests {
         println!("screen (before writing to bottom-right of screen):");
         //gpu.debug_screen_print_string();
         //println!("{:x?}", gpu.screen.map(|bool| bool as u8));
-        OpCode::dxyn(&mut cpu, &mem, &mut gpu);
+        OpCode::dxyn(&mut cpu, &mem, &mut gpu, 4, 0xA, 2).unwrap();
         println!("screen (after writing to bottom-right of screen):");
         //gpu.debug_screen_print_string();
         // println!("{:x?}", gpu.screen.map(|bool| bool as u8));
@@ -414,9 +576,8 @@ mod cputests {
         // .. set these to register 8 and 10
         cpu.registers[8] = v_x as u8;
         cpu.registers[0xA] = v_y as u8;
-        cpu.current_opcode = OpCode(0xD8A2);
 
-        OpCode::dxyn(&mut cpu, &mem, &mut gpu);
+        OpCode::dxyn(&mut cpu, &mem, &mut gpu, 8, 0xA, 2).unwrap();
         println!("screen (after overwriting the second-rows set pixels):");
         //gpu.debug_screen_print_string();
         // println!("{:x?}", gpu.screen.map(|bool| bool as u8));
@@ -452,7 +613,6 @@ mod cputests {
     fn test_fx33() {
         let mut cpu = test_init_cpu();
         let mut mem = test_init_mem();
-        cpu.current_opcode = OpCode(0xF533);
         cpu.registers[5] = 105;
         // unnecessary but oh well...
         cpu.index_register = 0x200;
@@ -465,7 +625,7 @@ mod cputests {
         let idxr: usize = cpu.index_register as usize;
         println!("memory.data[ir..ir+3]: {:x?}", &mem.ram[(idxr)..(idxr + 3)]);
         // Test fx33
-        OpCode::fx33(&mut cpu, &mut mem);
+        OpCode::fx33(&mut cpu, &mut mem, 5).unwrap();
         println!("memory.data[ir..ir+3]: {:x?}", &mem.ram[(idxr)..(idxr + 3)]);
         assert_eq!(mem.ram[cpu.index_register as usize], 1);
         assert_eq!(mem.ram[(cpu.index_register + 1) as usize], 0);
@@ -478,7 +638,6 @@ mod cputests {
     fn test_fx55() {
         let mut cpu = test_init_cpu();
         let mut mem = test_init_mem();
-        cpu.current_opcode = OpCode(0xF555);
         // memory should be 1-7 at 0x200-206
         mem.ram[0x205] = 6;
         mem.ram[0x206] = 7;
@@ -489,7 +648,7 @@ mod cputests {
         assert_eq!(mem.ram[0x204], 5);
         assert_eq!(mem.ram[0x205], 6);
         assert_eq!(mem.ram[0x206], 7);
-        OpCode::fx55(&mut cpu, &mut mem);
+        OpCode::fx55(&mut cpu, &mut mem, 5).unwrap();
         // our x was 5, v0..vx needs to get set with I..I+x
         assert_eq!(mem.ram[0x200], cpu.registers[0]);
         assert_eq!(mem.ram[0x201], cpu.registers[1]);
@@ -506,10 +665,9 @@ mod cputests {
     fn test_fx65() {
         let mut cpu = test_init_cpu();
         let mut mem = test_init_mem();
-        cpu.current_opcode = OpCode(0xF565);
         // setting up data to check for out of bounds bugs
         (mem.ram[0x206], cpu.registers[6]) = (0xDE, 0xAD);
-        OpCode::fx65(&mut cpu, &mut mem);
+        OpCode::fx65(&mut cpu, &mem, 5).unwrap();
         // our x was 5, v0..vx needs to get set with I..I+x
         assert_eq!(mem.ram[0x200], cpu.registers[0]);
         assert_eq!(mem.ram[0x201], cpu.registers[1]);
@@ -526,47 +684,115 @@ mod cputests {
         let mut cpu = test_init_cpu();
         cpu.index_register = 0x200; // unnecessary but oh well...
                                     // save before
-        cpu.current_opcode = OpCode(0xF51E);
-        let old_i = cpu.index_register.clone();
+        let old_i = cpu.index_register;
         println!("old_i: {:?}", old_i);
         // test fx1e to see if vX = 0 works
         // b/c x=5 -> v[5] and b/c all registers are 0'd out now -> 0
-        OpCode::fx1e(&mut cpu); // add 5 to i
+        OpCode::fx1e(&mut cpu, 5).unwrap(); // add 5 to i
         assert_eq!(cpu.index_register, old_i);
         cpu.registers[5] = 3;
-        OpCode::fx1e(&mut cpu); // add 5 to i
+        OpCode::fx1e(&mut cpu, 5).unwrap(); // add 5 to i
         assert_eq!(cpu.index_register, old_i + 3);
     }
 
-    //#[test]
-    //fn test_fx0a_test() {
-    //    let mut cpu = test_init_cpu();
-    //    let old = cpu.registers[7].clone();
-    //    cpu.current_opcode = OpCode(0xF70A);
-    //    // presses x, == 13 in our keymap
-    //    OpCode::fx0a_test(&mut cpu);
-    //    // This opcode fx0a_test should have mutated our '7' register b/c fx0a -> x = 7 => f70a
-    //    let new = cpu.registers[7].clone();
-    //    assert_eq!(13, new);
-    //    assert_ne!(old, new);
-    //}
+    #[test]
+    fn test_ex9e() {
+        let mut cpu = test_init_cpu();
+        let mut keypad = Keypad::new();
+        cpu.registers[2] = 0xB;
+        // key B is up, dont skip
+        let result = OpCode::ex9e(&mut cpu, &keypad, 2);
+        assert!(matches!(result, Ok(ExecutionResult::Advanced)));
+        // key B is down, skip
+        keypad.press(0xB);
+        let result = OpCode::ex9e(&mut cpu, &keypad, 2);
+        assert!(matches!(result, Ok(ExecutionResult::Skipped)));
+        // some other key is down, dont skip
+        keypad.release(0xB);
+        keypad.press(0xC);
+        let result = OpCode::ex9e(&mut cpu, &keypad, 2);
+        assert!(matches!(result, Ok(ExecutionResult::Advanced)));
+    }
 
-    //#[test]
-    //fn test_fx0a() {
-    //    let mut cpu = test_init_cpu();
-    //    let old = cpu.registers[7].clone();
-    //    cpu.current_opcode = OpCode(0xF70A);
-    //
-    //    //if poll(Duration::from_millis(100))? {
-    //    OpCode::fx0a(&mut cpu);
-    //    let new = cpu.registers[7].clone();
-    //    // This opcode fx0a_test should have mutated our '7' register b/c fx0a -> x = 7 => f70a
-    //    assert_eq!(13, new);
-    //    assert_ne!(old, new);
-    //    //} else {
-    //    //    println!("hubbababa");
-    //    //}
-    //}
+    #[test]
+    fn test_exa1() {
+        let mut cpu = test_init_cpu();
+        let mut keypad = Keypad::new();
+        cpu.registers[2] = 0xB;
+        // key B is up, skip
+        let result = OpCode::exa1(&mut cpu, &keypad, 2);
+        assert!(matches!(result, Ok(ExecutionResult::Skipped)));
+        // key B is down, dont skip
+        keypad.press(0xB);
+        let result = OpCode::exa1(&mut cpu, &keypad, 2);
+        assert!(matches!(result, Ok(ExecutionResult::Advanced)));
+    }
+
+    #[test]
+    fn test_fx0a() {
+        let mut cpu = test_init_cpu();
+        let mut keypad = Keypad::new();
+        let old = cpu.registers[7];
+
+        // nothing pressed yet, keep waiting
+        let result = OpCode::fx0a(&mut cpu, &mut keypad, 7);
+        assert!(matches!(result, Ok(ExecutionResult::Waiting)));
+
+        // pressing is not enough, the key has to be released too
+        keypad.press(0xD);
+        let result = OpCode::fx0a(&mut cpu, &mut keypad, 7);
+        assert!(matches!(result, Ok(ExecutionResult::Waiting)));
+        assert_eq!(cpu.registers[7], old);
+
+        keypad.release(0xD);
+        let result = OpCode::fx0a(&mut cpu, &mut keypad, 7);
+        assert!(matches!(result, Ok(ExecutionResult::Advanced)));
+        assert_eq!(cpu.registers[7], 0xD);
+    }
+
+    #[test]
+    fn test_fx0a_ignores_keys_held_before_waiting() {
+        let mut cpu = test_init_cpu();
+        let mut keypad = Keypad::new();
+
+        // key 5 was already held down when fx0a started
+        keypad.press(5);
+        let result = OpCode::fx0a(&mut cpu, &mut keypad, 7);
+        assert!(matches!(result, Ok(ExecutionResult::Waiting)));
+        keypad.release(5);
+        let result = OpCode::fx0a(&mut cpu, &mut keypad, 7);
+        assert!(matches!(result, Ok(ExecutionResult::Waiting)));
+
+        // a quick tap in between two polls still counts
+        keypad.press(9);
+        keypad.release(9);
+        let result = OpCode::fx0a(&mut cpu, &mut keypad, 7);
+        assert!(matches!(result, Ok(ExecutionResult::Advanced)));
+        assert_eq!(cpu.registers[7], 9);
+    }
+
+    #[test]
+    fn test_tick_fx0a_blocks_pc() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut gpu = Gpu::new();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        memory.ram[0x200] = 0xF3;
+        memory.ram[0x201] = 0x0A;
+
+        cpu.tick(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+            .unwrap();
+        assert_eq!(cpu.program_counter, 0x200);
+
+        keypad.press(0xA);
+        keypad.release(0xA);
+        cpu.tick(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+            .unwrap();
+        assert_eq!(cpu.program_counter, 0x202);
+        assert_eq!(cpu.registers[3], 0xA);
+    }
 
     mod mock {
         // use crate::emu::{gpu::Gpu, mem::Memory, timer::Timer};
@@ -619,7 +845,7 @@ mod cputests {
         let mut memory = Memory::new();
         memory.ram[0x200] = 0x12;
         memory.ram[0x201] = 0x34;
-        cpu.fetch_opcode(&memory);
+        cpu.fetch_opcode(&memory).unwrap();
         assert_eq!(cpu.current_opcode.0, 0x1234);
     }
 
@@ -629,9 +855,452 @@ mod cputests {
         let mut memory = Memory::new();
         let mut gpu = Gpu::new();
         let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
         cpu.current_opcode = OpCode(0x00E0);
-        let result = cpu.process(&mut memory, &mut gpu, &mut timers);
+        let result = cpu.process(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio);
         assert!(result.is_ok());
         assert!(gpu.screen.iter().all(|&pixel| !pixel));
     }
+
+    #[test]
+    fn test_memory_out_of_bounds() {
+        let mut cpu = test_init_cpu();
+        let mut memory = Memory::new();
+        let mut gpu = Gpu::new();
+        cpu.index_register = 0xFFE;
+        let oob = Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 });
+
+        // a 5 row sprite runs off the end of RAM
+        assert_eq!(OpCode::dxyn(&mut cpu, &memory, &mut gpu, 0, 1, 5), oob);
+        // 3 BCD digits
+        assert_eq!(OpCode::fx33(&mut cpu, &mut memory, 0), oob);
+        // V0..=V2 is 3 bytes
+        assert_eq!(OpCode::fx55(&mut cpu, &mut memory, 2), oob);
+        assert_eq!(OpCode::fx65(&mut cpu, &memory, 2), oob);
+        // V0..=V1 still fits
+        assert!(OpCode::fx65(&mut cpu, &memory, 1).is_ok());
+    }
+
+    #[test]
+    fn test_7xnn_wraps() {
+        let mut cpu = test_init_cpu();
+        cpu.registers[0] = 0xFF;
+        OpCode::_7xnn(&mut cpu, 0, 0x02).unwrap();
+        assert_eq!(cpu.registers[0], 0x01);
+        // VF is untouched by 7XNN
+        assert_eq!(cpu.registers[0xF], 0);
+    }
+
+    #[test]
+    fn test_process_unknown_opcode() {
+        let mut cpu = test_init_cpu();
+        let mut memory = Memory::new();
+        let mut gpu = Gpu::new();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        cpu.current_opcode = OpCode(0xE0FF);
+        let result = cpu.process(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio);
+        assert_eq!(
+            result,
+            Err(Chip8Error::UnknownOpcode {
+                op: 0xE0FF,
+                pc: 0x200
+            })
+        );
+    }
+
+    #[test]
+    fn test_tick_reports_fault() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut gpu = Gpu::new();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        // 0x200: 00EE with an empty stack
+        memory.ram[0x200] = 0x00;
+        memory.ram[0x201] = 0xEE;
+        let fault = cpu
+            .tick(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+            .unwrap_err();
+        assert_eq!(
+            fault,
+            Fault {
+                pc: 0x200,
+                opcode: 0x00EE,
+                error: Chip8Error::StackUnderflow
+            }
+        );
+        // pc stays on the faulting instruction
+        assert_eq!(cpu.program_counter, 0x200);
+    }
+
+    #[test]
+    fn test_tick_call_returns_after_the_call() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut gpu = Gpu::new();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        // 0x200: 2300 -> call 0x300, 0x300: 00EE -> return
+        memory.load_rom(&[0x23, 0x00]).unwrap();
+        memory.ram[0x300] = 0x00;
+        memory.ram[0x301] = 0xEE;
+        for _ in 0..2 {
+            cpu.tick(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+                .unwrap();
+        }
+        assert_eq!(cpu.program_counter, 0x202);
+        assert_eq!(cpu.stack_pointer, 0);
+    }
+
+    #[test]
+    fn test_quirk_shift_uses_vy() {
+        let mut cpu = test_init_cpu();
+        // v1 = 5 (0b101), v2 = 14 (0b1110)
+        cpu.quirks.shift_uses_vy = true;
+        OpCode::_8xy6(&mut cpu, 1, 2).unwrap();
+        assert_eq!((cpu.registers[1], cpu.registers[0xF]), (7, 0));
+
+        cpu.registers[1] = 5;
+        cpu.quirks.shift_uses_vy = false;
+        OpCode::_8xy6(&mut cpu, 1, 2).unwrap();
+        assert_eq!((cpu.registers[1], cpu.registers[0xF]), (2, 1));
+
+        cpu.registers[1] = 0x81;
+        OpCode::_8xye(&mut cpu, 1, 2).unwrap();
+        assert_eq!((cpu.registers[1], cpu.registers[0xF]), (0x02, 1));
+    }
+
+    #[test]
+    fn test_quirk_shift_flag_wins_over_vf() {
+        // vF as the destination ends up holding the flag, not the result
+        let mut cpu = test_init_cpu();
+        cpu.registers[0xF] = 0b11;
+        OpCode::_8xy6(&mut cpu, 0xF, 0xF).unwrap();
+        assert_eq!(cpu.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_quirk_load_store_increments_i() {
+        let mut cpu = test_init_cpu();
+        let mut mem = test_init_mem();
+        OpCode::fx55(&mut cpu, &mut mem, 2).unwrap();
+        assert_eq!(cpu.index_register, 0x203);
+
+        cpu.quirks.load_store_increments_i = false;
+        OpCode::fx65(&mut cpu, &mem, 2).unwrap();
+        assert_eq!(cpu.index_register, 0x203);
+    }
+
+    #[test]
+    fn test_quirk_jump_uses_vx() {
+        let mut cpu = test_init_cpu();
+        // v0 = 105, v2 = 14
+        OpCode::bnnn(&mut cpu, 0x220).unwrap();
+        assert_eq!(cpu.program_counter, 0x220 + 105);
+
+        cpu.quirks.jump_uses_vx = true;
+        OpCode::bnnn(&mut cpu, 0x220).unwrap();
+        assert_eq!(cpu.program_counter, 0x220 + 14);
+    }
+
+    #[test]
+    fn test_quirk_vf_reset() {
+        let mut cpu = test_init_cpu();
+        cpu.quirks = QuirksProfile::CosmacVip.quirks();
+        for opcode in [0x8121, 0x8122, 0x8123] {
+            cpu.registers[0xF] = 0xAA;
+            match opcode & 0xF {
+                1 => OpCode::_8xy1(&mut cpu, 1, 2),
+                2 => OpCode::_8xy2(&mut cpu, 1, 2),
+                _ => OpCode::_8xy3(&mut cpu, 1, 2),
+            }
+            .unwrap();
+            assert_eq!(cpu.registers[0xF], 0, "{:04x}", opcode);
+        }
+        cpu.quirks.vf_reset = false;
+        cpu.registers[0xF] = 0xAA;
+        OpCode::_8xy1(&mut cpu, 1, 2).unwrap();
+        assert_eq!(cpu.registers[0xF], 0xAA);
+    }
+
+    #[test]
+    fn test_quirk_clip_sprites() {
+        let mut mem = test_init_mem();
+        // a 2 row, 8 pixel wide sprite in the bottom right corner
+        mem.ram[0x300] = 0xFF;
+        mem.ram[0x301] = 0xFF;
+        let draw = |quirks: Quirks| {
+            let mut cpu = test_init_cpu();
+            let mut gpu = test_init_gpu();
+            cpu.quirks = quirks;
+            cpu.index_register = 0x300;
+            cpu.registers[0] = 60;
+            cpu.registers[1] = 31;
+            OpCode::dxyn(&mut cpu, &mem, &mut gpu, 0, 1, 2).unwrap();
+            gpu.screen.iter().filter(|&&pixel| pixel).count()
+        };
+        assert_eq!(
+            draw(Quirks {
+                clip_sprites: false,
+                ..Quirks::default()
+            }),
+            16
+        );
+        // only the 4 pixels left of the edge on the last row survive
+        assert_eq!(
+            draw(Quirks {
+                clip_sprites: true,
+                ..Quirks::default()
+            }),
+            4
+        );
+    }
+
+    #[test]
+    fn test_quirk_hires_collision_rows() {
+        let mut mem = test_init_mem();
+        // 3 rows, the first two land on pixels that are already on
+        mem.ram[0x300..0x303].copy_from_slice(&[0x80, 0x80, 0x80]);
+        let draw = |quirks: Quirks, hires: bool, y: u8| {
+            let mut cpu = test_init_cpu();
+            let mut gpu = test_init_gpu();
+            gpu.set_hires(hires);
+            let width = gpu.width();
+            gpu.screen[y as usize * width] = true;
+            gpu.screen[(y as usize + 1) * width] = true;
+            cpu.quirks = quirks;
+            cpu.index_register = 0x300;
+            cpu.registers[0] = 0;
+            cpu.registers[1] = y;
+            OpCode::dxyn(&mut cpu, &mem, &mut gpu, 0, 1, 3).unwrap();
+            cpu.registers[0xF]
+        };
+        let schip = QuirksProfile::Schip.quirks();
+        assert_eq!(draw(schip, true, 0), 2);
+        // the third row is clipped off the bottom and counts too
+        assert_eq!(draw(schip, true, 62), 3);
+        assert_eq!(draw(schip, false, 0), 1);
+        let quirks = Quirks {
+            hires_collision_rows: false,
+            ..schip
+        };
+        assert_eq!(draw(quirks, true, 0), 1);
+    }
+
+    #[test]
+    fn test_quirk_display_wait() {
+        let mut cpu = test_init_cpu();
+        let mem = test_init_mem();
+        let mut gpu = test_init_gpu();
+        cpu.quirks.display_wait = true;
+        assert_eq!(
+            OpCode::dxyn(&mut cpu, &mem, &mut gpu, 0, 1, 1),
+            Ok(ExecutionResult::Waiting)
+        );
+        cpu.vblank = true;
+        assert_eq!(
+            OpCode::dxyn(&mut cpu, &mem, &mut gpu, 0, 1, 1),
+            Ok(ExecutionResult::Advanced)
+        );
+        // one sprite per frame
+        assert_eq!(
+            OpCode::dxyn(&mut cpu, &mem, &mut gpu, 0, 1, 1),
+            Ok(ExecutionResult::Waiting)
+        );
+    }
+
+    #[test]
+    fn test_fx29_fx30_fonts() {
+        let mut cpu = test_init_cpu();
+        let mem = Memory::new();
+        cpu.registers[3] = 0xA;
+        OpCode::fx29(&mut cpu, 3).unwrap();
+        assert_eq!(cpu.index_register, 0xA * 5);
+        assert_eq!(mem.ram[cpu.index_register as usize], 0xF0);
+
+        OpCode::fx30(&mut cpu, 3).unwrap();
+        assert_eq!(cpu.index_register, 0x50 + 0xA * 10);
+        // top row of the big A
+        assert_eq!(mem.ram[cpu.index_register as usize], 0x7E);
+    }
+
+    #[test]
+    fn test_fx75_fx85_rpl_flags() {
+        let mut cpu = test_init_cpu();
+        OpCode::fx75(&mut cpu, 2).unwrap();
+        assert_eq!(cpu.rpl_flags[..4], [105, 5, 14, 0]);
+        cpu.registers[..3].fill(0);
+        OpCode::fx85(&mut cpu, 1).unwrap();
+        assert_eq!(cpu.registers[..3], [105, 5, 0]);
+    }
+
+    #[test]
+    fn test_dxy0_hires_16x16() {
+        let mut cpu = test_init_cpu();
+        let mut memory = test_init_mem();
+        let mut gpu = test_init_gpu();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        memory.ram[0x300..0x320].fill(0xFF);
+        cpu.index_register = 0x300;
+        cpu.registers[0] = 100;
+        cpu.registers[1] = 40;
+
+        cpu.current_opcode = OpCode(0x00FF);
+        cpu.process(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+            .unwrap();
+        assert!(gpu.is_hires());
+
+        OpCode::dxyn(&mut cpu, &memory, &mut gpu, 0, 1, 0).unwrap();
+        let lit: Vec<usize> = (0..gpu.screen.len()).filter(|&i| gpu.screen[i]).collect();
+        assert_eq!(lit.len(), 16 * 16);
+        assert_eq!(lit[0], 40 * 128 + 100);
+        assert_eq!(lit[255], 55 * 128 + 115);
+        assert_eq!(cpu.registers[0xF], 0);
+
+        // drawing it again erases it and reports the collision
+        OpCode::dxyn(&mut cpu, &memory, &mut gpu, 0, 1, 0).unwrap();
+        assert!(gpu.screen.iter().all(|&pixel| !pixel));
+        assert_eq!(cpu.registers[0xF], 1);
+    }
+
+    #[test]
+    fn test_00cn_00fb_00fc_scroll() {
+        let mut cpu = test_init_cpu();
+        let mut memory = test_init_mem();
+        let mut gpu = test_init_gpu();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        gpu.screen[0] = true;
+        for opcode in [0x00C3, 0x00FB, 0x00FB, 0x00FC] {
+            cpu.current_opcode = OpCode(opcode);
+            cpu.process(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+                .unwrap();
+        }
+        // 3 down, 4 right
+        assert!(gpu.screen[3 * 64 + 4]);
+        assert_eq!(gpu.screen.iter().filter(|&&pixel| pixel).count(), 1);
+    }
+
+    #[test]
+    fn test_tick_00fd_exits() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::new();
+        let mut gpu = Gpu::new();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        memory.ram[0x200] = 0x00;
+        memory.ram[0x201] = 0xFD;
+        for _ in 0..3 {
+            cpu.tick(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+                .unwrap();
+        }
+        assert!(cpu.exited);
+        assert_eq!(cpu.program_counter, 0x200);
+    }
+
+    #[test]
+    fn test_5xy2_5xy3_register_ranges() {
+        let mut cpu = test_init_cpu();
+        let mut mem = test_init_mem();
+        cpu.index_register = 0x300;
+        // v0..=v2 forwards
+        OpCode::_5xy2(&mut cpu, &mut mem, 0, 2).unwrap();
+        assert_eq!(mem.ram[0x300..0x303], [105, 5, 14]);
+        assert_eq!(cpu.index_register, 0x300);
+        // v2..=v0 backwards
+        OpCode::_5xy2(&mut cpu, &mut mem, 2, 0).unwrap();
+        assert_eq!(mem.ram[0x300..0x303], [14, 5, 105]);
+
+        OpCode::_5xy3(&mut cpu, &mem, 8, 9).unwrap();
+        assert_eq!(cpu.registers[8..10], [14, 5]);
+    }
+
+    #[test]
+    fn test_tick_f000_long_load_and_skip() {
+        let mut cpu = Cpu::new();
+        let mut memory = Memory::with_size(XO_RAM_SIZE);
+        let mut gpu = Gpu::new();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        // 0x200: F000 ABCD, 0x204: 3000 -> skip (v0 == 0), 0x206: F000 1234, 0x20A: ...
+        memory
+            .load_rom(&[0xF0, 0x00, 0xAB, 0xCD, 0x30, 0x00, 0xF0, 0x00, 0x12, 0x34])
+            .unwrap();
+        cpu.tick(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+            .unwrap();
+        assert_eq!(cpu.index_register, 0xABCD);
+        assert_eq!(cpu.program_counter, 0x204);
+        cpu.tick(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+            .unwrap();
+        assert_eq!(cpu.program_counter, 0x20A);
+        assert_eq!(cpu.index_register, 0xABCD);
+    }
+
+    #[test]
+    fn test_fn01_draws_on_both_planes() {
+        let mut cpu = test_init_cpu();
+        let mut mem = test_init_mem();
+        let mut gpu = test_init_gpu();
+        // plane 1 sprite then plane 2 sprite, one row each
+        mem.ram[0x300] = 0b1000_0000;
+        mem.ram[0x301] = 0b1100_0000;
+        cpu.index_register = 0x300;
+        cpu.registers[0] = 0;
+        cpu.registers[1] = 0;
+        OpCode::fn01(&mut gpu, 3).unwrap();
+        assert_eq!(gpu.selected_planes(), 3);
+        OpCode::dxyn(&mut cpu, &mem, &mut gpu, 0, 1, 1).unwrap();
+        assert_eq!([gpu.colour(0), gpu.colour(1), gpu.colour(2)], [3, 2, 0]);
+
+        // clearing only the second plane keeps the first
+        gpu.select_planes(2);
+        OpCode::_00e0(&mut gpu).unwrap();
+        assert_eq!([gpu.colour(0), gpu.colour(1)], [1, 0]);
+
+        // no planes selected draws nothing
+        gpu.select_planes(0);
+        OpCode::dxyn(&mut cpu, &mem, &mut gpu, 0, 1, 1).unwrap();
+        assert_eq!([gpu.colour(0), gpu.colour(1)], [1, 0]);
+    }
+
+    #[test]
+    fn test_00dn_scroll_up() {
+        let mut gpu = test_init_gpu();
+        gpu.screen[5 * 64] = true;
+        OpCode::_00dn(&mut gpu, 2).unwrap();
+        assert!(gpu.screen[3 * 64]);
+        assert_eq!(gpu.screen.iter().filter(|&&pixel| pixel).count(), 1);
+    }
+
+    #[test]
+    fn test_f002_fx3a_audio() {
+        let mut cpu = test_init_cpu();
+        let mut memory = Memory::with_size(XO_RAM_SIZE);
+        let mut gpu = test_init_gpu();
+        let mut timers = Timer::new(1);
+        let mut keypad = Keypad::new();
+        let mut audio = Audio::new();
+        memory.ram[0x300..0x310].copy_from_slice(&[0xAA; 16]);
+        cpu.index_register = 0x300;
+        cpu.current_opcode = OpCode(0xF002);
+        cpu.process(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+            .unwrap();
+        assert_eq!(audio.pattern, Some([0xAA; 16]));
+
+        cpu.registers[4] = 112;
+        cpu.current_opcode = OpCode(0xF43A);
+        cpu.process(&mut memory, &mut gpu, &mut timers, &mut keypad, &mut audio)
+            .unwrap();
+        assert_eq!(audio.pitch, 112);
+    }
 }
@@ -0,0 +1,1066 @@
+// Debug Adapter Protocol server, so editors can debug ROMs: `chip8 dap` speaks it over stdio.
+// DapSession takes one message at a time and knows nothing about stdio, `serve` puts it on a
+// pair of streams with the Content-Length framing. Stepping and breakpoints are the Debugger's,
+// the same ones the terminal debugger and the GDB stub use.
+//
+// Launch arguments, all optional but program or source:
+//   program      the ROM
+//   source       assembler source for it (see asm.rs), for breakpoints and stack frames by
+//                line. Assembled to get the ROM if there's no program, has to match it if there is
+//   quirks       a quirks profile, like --quirks
+//   ipf          instructions per frame
+//   xochip       run as XO-CHIP, the default for .xo8 ROMs
+//   stopOnEntry  stop before the first instruction
+//
+// Besides the standard events there's `chip8/screen`, the framebuffer as text (screenshot::ascii)
+// after every stop and a few times a second while it changes.
+//
+// https://microsoft.github.io/debug-adapter-protocol/specification
+use super::{
+    asm::{assemble_with_map, SourceMap},
+    clock::{ClockSpeed, FramePacer},
+    debugger::{Debugger, StopReason},
+    disasm::{decode_at, Variant},
+    expr::Expr,
+    machine::Machine,
+    quirks::QuirksProfile,
+    screenshot,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+/// There's only the one cpu
+const THREAD_ID: u64 = 1;
+
+/// variablesReference of each scope, the same whichever frame is asked about
+const REGISTERS: u64 = 1;
+const STACK: u64 = 2;
+const MEMORY: u64 = 3;
+
+/// Bytes per row of the Memory scope
+const ROW: usize = 16;
+
+/// How often the screen goes out while running, in frames
+const SCREEN_INTERVAL: u32 = 6;
+
+/// What a launch request can say, see the top of the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchArguments {
+    program: Option<PathBuf>,
+    source: Option<PathBuf>,
+    quirks: Option<String>,
+    ipf: Option<u32>,
+    xochip: Option<bool>,
+    #[serde(default)]
+    stop_on_entry: bool,
+}
+
+/// The launched ROM's source, if it came with one.
+#[derive(Debug)]
+struct Source {
+    path: PathBuf,
+    map: SourceMap,
+}
+
+/// The protocol side: requests in, responses and events out.
+#[derive(Debug)]
+pub struct DapSession {
+    /// seq of the last message sent
+    seq: u64,
+    machine: Option<Machine>,
+    debugger: Debugger,
+    source: Option<Source>,
+    /// Set by setBreakpoints and setInstructionBreakpoints, each replaces its own list
+    line_breakpoints: Vec<u16>,
+    instruction_breakpoints: Vec<u16>,
+    /// For the `id` of every breakpoint handed back
+    breakpoint_ids: u64,
+    /// Launched, but the editor is still sending breakpoints
+    configuring: bool,
+    stop_on_entry: bool,
+    /// The editor thinks the machine is running and is owed a stopped event
+    running: bool,
+    /// Frames since the screen last went out, and what it was
+    screen_frames: u32,
+    screen: String,
+    /// The program ran 00FD and the editor has been told
+    terminated: bool,
+    /// disconnect came, serve can stop
+    finished: bool,
+}
+
+impl Default for DapSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DapSession {
+    pub fn new() -> Self {
+        Self {
+            seq: 0,
+            machine: None,
+            debugger: Debugger::new(),
+            source: None,
+            line_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            breakpoint_ids: 0,
+            configuring: false,
+            stop_on_entry: false,
+            running: false,
+            screen_frames: 0,
+            screen: String::new(),
+            terminated: false,
+            finished: false,
+        }
+    }
+
+    /// Whether frames should be run, `serve` waits for the editor otherwise.
+    pub fn is_running(&self) -> bool {
+        self.machine.is_some()
+            && !self.configuring
+            && !self.terminated
+            && !self.debugger.is_paused()
+    }
+
+    /// Whether the editor disconnected.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Handles one request, returns the response and whatever events it led to.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        if message["type"] != "request" {
+            return Vec::new();
+        }
+        let command = message["command"].as_str().unwrap_or_default();
+        let arguments = &message["arguments"];
+        let result = match command {
+            "initialize" => Ok(capabilities()),
+            "launch" => self.launch(arguments),
+            "attach" => Err("there's nothing to attach to, use launch".to_string()),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "CHIP-8"}]})),
+            "stackTrace" => self.with_machine(|s, m| Ok(s.stack_trace(m))),
+            "scopes" => self.with_machine(|_, m| Ok(scopes(m))),
+            "variables" => self.with_machine(|s, m| Ok(s.variables(m, arguments))),
+            "setVariable" => self.with_machine(|s, m| s.set_variable(m, arguments)),
+            "evaluate" => self.with_machine(|_, m| evaluate(m, arguments)),
+            "disassemble" => self.with_machine(|_, m| disassemble(m, arguments)),
+            "continue" => self.with_machine(|s, _| {
+                s.debugger.resume();
+                Ok(json!({"allThreadsContinued": true}))
+            }),
+            "next" => self.with_machine(|s, m| {
+                s.debugger.step_over(m);
+                Ok(json!({}))
+            }),
+            "stepIn" => self.with_machine(|s, m| {
+                s.debugger.step(m);
+                Ok(json!({}))
+            }),
+            "stepOut" => self.with_machine(|s, m| {
+                s.debugger.step_out(m);
+                Ok(json!({}))
+            }),
+            "pause" => {
+                self.debugger.pause();
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.finished = true;
+                Ok(json!({}))
+            }
+            _ => Err(format!("unsupported request {}", command)),
+        };
+        let resumes = matches!(command, "continue" | "next" | "stepIn" | "stepOut");
+        let mut out = vec![self.response(message, result)];
+        if resumes && self.machine.is_some() {
+            self.running = true;
+            self.screen_frames = 0;
+        }
+        if command == "launch" && self.machine.is_some() {
+            out.push(self.event("initialized", json!({})));
+        }
+        out.extend(self.stops());
+        out
+    }
+
+    /// Runs a frame if the machine is running, returns the events that came of it.
+    pub fn run_frame(&mut self) -> Vec<Value> {
+        if !self.is_running() {
+            return Vec::new();
+        }
+        let Some(machine) = &mut self.machine else {
+            return Vec::new();
+        };
+        self.debugger.run_frame(machine);
+        let mut out = self.stops();
+        self.screen_frames += 1;
+        if self.running && self.screen_frames >= SCREEN_INTERVAL {
+            self.screen_frames = 0;
+            out.extend(self.screen_event(false));
+        }
+        out
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        if self.machine.is_some() {
+            return Err("already launched".to_string());
+        }
+        let arguments: LaunchArguments =
+            serde_json::from_value(arguments.clone()).map_err(|e| e.to_string())?;
+        let assembled = match &arguments.source {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+                let (rom, map) =
+                    assemble_with_map(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+                Some((rom, path.clone(), map))
+            }
+            None => None,
+        };
+        let rom = match (&arguments.program, &assembled) {
+            (Some(path), assembled) => {
+                let rom = fs::read(path)
+                    .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+                if assembled
+                    .as_ref()
+                    .is_some_and(|(source, ..)| *source != rom)
+                {
+                    return Err(format!(
+                        "{} isn't what the source assembles to, rebuild it",
+                        path.display()
+                    ));
+                }
+                rom
+            }
+            (None, Some((rom, ..))) => rom.clone(),
+            (None, None) => return Err("launch needs a program or a source".to_string()),
+        };
+        let path = arguments
+            .program
+            .as_deref()
+            .or(arguments.source.as_deref())
+            .unwrap_or(Path::new(""));
+        let xo_chip = arguments
+            .xochip
+            .unwrap_or(path.extension().is_some_and(|ext| ext == "xo8"));
+        let profile = match &arguments.quirks {
+            Some(name) => name.parse::<QuirksProfile>()?,
+            None if xo_chip => QuirksProfile::XoChip,
+            None => QuirksProfile::default(),
+        };
+
+        let mut machine = Machine::new();
+        match arguments.ipf {
+            Some(0) => return Err("ipf has to be at least 1".to_string()),
+            Some(ipf) => machine.set_speed(ClockSpeed::Ipf(ipf)),
+            None => {}
+        }
+        machine.set_xo_chip(xo_chip);
+        machine.set_quirks(profile.quirks());
+        machine.load_rom(&rom).map_err(|e| e.to_string())?;
+        info!(target: "dap", "launched {}", path.display());
+
+        self.machine = Some(machine);
+        self.source = assembled.map(|(_, path, map)| Source { path, map });
+        self.debugger.pause();
+        self.configuring = true;
+        self.stop_on_entry = arguments.stop_on_entry;
+        Ok(json!({}))
+    }
+
+    fn configuration_done(&mut self) -> Result<Value, String> {
+        if !std::mem::take(&mut self.configuring) {
+            return Ok(json!({}));
+        }
+        if !self.stop_on_entry {
+            self.debugger.resume();
+            self.running = true;
+        }
+        Ok(json!({}))
+    }
+
+    /// Breakpoints by line of the launched source.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let lines: Vec<u64> = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                let lines = breakpoints.iter().map(|b| b["line"].as_u64().unwrap_or(0));
+                lines.collect()
+            })
+            .unwrap_or_default();
+        let path = arguments["source"]["path"].as_str().map(Path::new);
+        let map = self
+            .source
+            .as_ref()
+            .filter(|source| path.is_some_and(|path| same_file(path, &source.path)))
+            .map(|source| source.map.clone());
+
+        let mut addrs = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            let found = map.as_ref().and_then(|map| map.addr_of(line as usize));
+            let breakpoint = match (&map, found) {
+                (Some(_), Some((addr, line))) => {
+                    addrs.push(addr);
+                    json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("{:#06x}", addr),
+                    })
+                }
+                (Some(_), None) => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no instructions from here on",
+                }),
+                (None, _) => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "not the source this ROM was launched with",
+                }),
+            };
+            breakpoints.push(self.numbered(breakpoint));
+        }
+        self.unset_breakpoints();
+        self.line_breakpoints = addrs;
+        self.apply_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Breakpoints by address, `instructionReference` plus an optional byte `offset`.
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let mut addrs = Vec::new();
+        let mut breakpoints = Vec::new();
+        for requested in arguments["breakpoints"].as_array().into_iter().flatten() {
+            let addr = requested["instructionReference"]
+                .as_str()
+                .and_then(parse_addr)
+                .and_then(|addr| addr.checked_add(requested["offset"].as_i64().unwrap_or(0)))
+                .and_then(|addr| u16::try_from(addr).ok());
+            let breakpoint = match addr {
+                Some(addr) => {
+                    addrs.push(addr);
+                    let line = self.source.as_ref().and_then(|s| s.map.line_of(addr));
+                    json!({
+                        "verified": true,
+                        "instructionReference": format!("{:#06x}", addr),
+                        "line": line,
+                    })
+                }
+                None => json!({"verified": false, "message": "not an address"}),
+            };
+            breakpoints.push(self.numbered(breakpoint));
+        }
+        self.unset_breakpoints();
+        self.instruction_breakpoints = addrs;
+        self.apply_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn numbered(&mut self, mut breakpoint: Value) -> Value {
+        self.breakpoint_ids += 1;
+        breakpoint["id"] = json!(self.breakpoint_ids);
+        breakpoint
+    }
+
+    /// The debugger only has the one set, so both lists come out and go back in together.
+    fn unset_breakpoints(&mut self) {
+        let addrs = self
+            .line_breakpoints
+            .iter()
+            .chain(&self.instruction_breakpoints);
+        for &addr in addrs {
+            if self.debugger.has_breakpoint(addr) {
+                self.debugger.toggle_breakpoint(addr);
+            }
+        }
+    }
+
+    fn apply_breakpoints(&mut self) {
+        let addrs = self
+            .line_breakpoints
+            .iter()
+            .chain(&self.instruction_breakpoints);
+        for &addr in addrs {
+            if !self.debugger.has_breakpoint(addr) {
+                self.debugger.toggle_breakpoint(addr);
+            }
+        }
+    }
+
+    /// The pc, then every call on the stack, innermost first. The stack holds the address of
+    /// the CALL itself, RET moves on from there.
+    fn stack_trace(&self, machine: &Machine) -> Value {
+        let cpu = &machine.cpu;
+        let calls = cpu.stack[..cpu.stack_pointer.min(cpu.stack.len())]
+            .iter()
+            .rev()
+            .copied();
+        let frames: Vec<Value> = std::iter::once(cpu.program_counter)
+            .chain(calls)
+            .enumerate()
+            .map(|(id, addr)| {
+                let text = decode_at(&machine.memory.ram, addr, variant(machine)).text;
+                let mut frame = json!({
+                    "id": id,
+                    "name": format!("{:#05x}: {}", addr, text),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#06x}", addr),
+                });
+                if let Some(source) = &self.source {
+                    if let Some(line) = source.map.line_of(addr) {
+                        frame["source"] = source_json(&source.path);
+                        frame["line"] = json!(line);
+                        frame["column"] = json!(1);
+                    }
+                }
+                frame
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(&self, machine: &Machine, arguments: &Value) -> Value {
+        let cpu = &machine.cpu;
+        let variables: Vec<Value> = match arguments["variablesReference"].as_u64() {
+            Some(REGISTERS) => registers(machine)
+                .into_iter()
+                .map(|(name, value, width)| variable(&name, hex(value, width)))
+                .collect(),
+            Some(STACK) => cpu.stack[..cpu.stack_pointer.min(cpu.stack.len())]
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(depth, &call)| {
+                    let line = self.source.as_ref().and_then(|s| s.map.line_of(call));
+                    let value = match line {
+                        Some(line) => format!("{:#06x} (line {})", call, line),
+                        None => format!("{:#06x}", call),
+                    };
+                    variable(&depth.to_string(), value)
+                })
+                .collect(),
+            Some(MEMORY) => {
+                let ram = &machine.memory.ram;
+                let rows = ram.len().div_ceil(ROW);
+                let start = arguments["start"].as_u64().unwrap_or(0) as usize;
+                let count = match arguments["count"].as_u64() {
+                    Some(0) | None => rows,
+                    Some(count) => count as usize,
+                };
+                (start.min(rows)..(start.saturating_add(count)).min(rows))
+                    .map(|row| {
+                        let bytes = &ram[row * ROW..((row + 1) * ROW).min(ram.len())];
+                        let text: Vec<String> =
+                            bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                        variable(&format!("{:#06x}", row * ROW), text.join(" "))
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        json!({ "variables": variables })
+    }
+
+    /// Registers can be set to anything an expression comes out as.
+    fn set_variable(&mut self, machine: &mut Machine, arguments: &Value) -> Result<Value, String> {
+        if arguments["variablesReference"].as_u64() != Some(REGISTERS) {
+            return Err("only registers can be set".to_string());
+        }
+        let name = arguments["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let value = Expr::parse(arguments["value"].as_str().unwrap_or_default())?.eval(machine);
+        let width = match name.as_str() {
+            "I" | "PC" => 4,
+            "SP" => return Err("SP goes with the stack, step instead".to_string()),
+            _ => 2,
+        };
+        let fits = u16::try_from(value)
+            .ok()
+            .filter(|&v| width == 4 || v <= 0xFF)
+            .ok_or(format!("{} doesn't fit in {}", value, name))?;
+        let cpu = &mut machine.cpu;
+        match name.as_str() {
+            "I" => cpu.index_register = fits,
+            "PC" => cpu.program_counter = fits,
+            "DT" => machine.timers.delay_timer = fits as u8,
+            "ST" => machine.timers.sound_timer = fits as u8,
+            v => match v
+                .strip_prefix('V')
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+            {
+                Some(x) if x < 16 && v.len() == 2 => cpu.registers[x as usize] = fits as u8,
+                _ => return Err(format!("no register called {}", name)),
+            },
+        }
+        self.debugger.machine_changed();
+        Ok(json!({ "value": hex(fits, width) }))
+    }
+
+    /// Requests that only make sense once there's a machine.
+    fn with_machine(
+        &mut self,
+        handle: impl FnOnce(&mut Self, &mut Machine) -> Result<Value, String>,
+    ) -> Result<Value, String> {
+        let mut machine = self.machine.take().ok_or("nothing launched yet")?;
+        let result = handle(self, &mut machine);
+        self.machine = Some(machine);
+        result
+    }
+
+    /// The stopped event the editor is owed, and the end of the program.
+    fn stops(&mut self) -> Vec<Value> {
+        let mut out = Vec::new();
+        let Some(machine) = &self.machine else {
+            return out;
+        };
+        if !self.terminated && machine.has_exited() {
+            self.terminated = true;
+            self.running = false;
+            self.debugger.pause();
+            out.extend(self.screen_event(true));
+            out.push(self.event("exited", json!({"exitCode": 0})));
+            out.push(self.event("terminated", json!({})));
+            return out;
+        }
+        let entry = self.stop_on_entry && !self.configuring && !self.running;
+        if entry {
+            self.stop_on_entry = false;
+            let body = stopped("entry", None);
+            out.push(self.event("stopped", body));
+            out.extend(self.screen_event(true));
+        } else if self.running && self.debugger.is_paused() {
+            self.running = false;
+            let body = match self.debugger.last_stop() {
+                Some(StopReason::Fault(fault)) => stopped("exception", Some(fault.to_string())),
+                Some(StopReason::Breakpoint(_)) => stopped("breakpoint", None),
+                Some(StopReason::Watchpoint { index, .. }) => {
+                    let text = self
+                        .debugger
+                        .watchpoints()
+                        .get(index)
+                        .map(|w| w.to_string());
+                    stopped("data breakpoint", text)
+                }
+                Some(StopReason::Paused) => stopped("pause", None),
+                _ => stopped("step", None),
+            };
+            out.push(self.event("stopped", body));
+            out.extend(self.screen_event(true));
+        }
+        out
+    }
+
+    /// `chip8/screen`, unless it's the same as last time and `always` isn't set.
+    fn screen_event(&mut self, always: bool) -> Option<Value> {
+        let machine = self.machine.as_ref()?;
+        let text = screenshot::ascii(&machine.gpu);
+        if !always && text == self.screen {
+            return None;
+        }
+        let (width, height) = machine.screen_size();
+        self.screen.clone_from(&text);
+        Some(self.event(
+            "chip8/screen",
+            json!({"width": width, "height": height, "text": text}),
+        ))
+    }
+
+    fn response(&mut self, request: &Value, result: Result<Value, String>) -> Value {
+        self.seq += 1;
+        let mut response = json!({
+            "seq": self.seq,
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => {
+                debug!(target: "dap", "{} failed: {}", request["command"], message);
+                response["message"] = json!(message);
+            }
+        }
+        response
+    }
+
+    fn event(&mut self, name: &str, body: Value) -> Value {
+        self.seq += 1;
+        json!({"seq": self.seq, "type": "event", "event": name, "body": body})
+    }
+}
+
+/// Speaks DAP on `input` and `output` until the editor disconnects or goes away.
+/// Runs the machine at 60 frames a second in between.
+pub fn serve(input: impl Read + Send + 'static, mut output: impl Write) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        loop {
+            match read_message(&mut input) {
+                Ok(Some(message)) => {
+                    if tx.send(message).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                // a broken message is skipped, the framing still holds
+                Err(e) if e.kind() == ErrorKind::InvalidData => {
+                    warn!(target: "dap", "Skipping a message: {}", e)
+                }
+                Err(e) => {
+                    warn!(target: "dap", "Couldn't read from the editor: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+
+    let mut session = DapSession::new();
+    let mut pacer = FramePacer::new();
+    while !session.is_finished() {
+        if session.is_running() {
+            loop {
+                match rx.try_recv() {
+                    Ok(message) => write_messages(&mut output, session.handle(&message))?,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+            write_messages(&mut output, session.run_frame())?;
+            pacer.wait_for_next_frame();
+        } else {
+            // nothing to do but wait for the editor
+            let Ok(message) = rx.recv() else {
+                return Ok(());
+            };
+            write_messages(&mut output, session.handle(&message))?;
+        }
+    }
+    Ok(())
+}
+
+/// The next `Content-Length` framed message, None at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or(io::Error::new(
+        ErrorKind::InvalidData,
+        "no Content-Length header",
+    ))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn write_messages(output: &mut impl Write, messages: Vec<Value>) -> io::Result<()> {
+    messages
+        .iter()
+        .try_for_each(|message| write_message(output, message))
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsDisassembleRequest": true,
+        "supportsSetVariable": true,
+        "supportsEvaluateForHovers": true,
+        "supportsSteppingGranularity": true,
+        "supportsTerminateRequest": true,
+    })
+}
+
+fn scopes(machine: &Machine) -> Value {
+    let rows = machine.memory.ram.len().div_ceil(ROW);
+    json!({"scopes": [
+        {"name": "Registers", "variablesReference": REGISTERS, "expensive": false},
+        {"name": "Stack", "variablesReference": STACK, "expensive": false},
+        {
+            "name": "Memory",
+            "variablesReference": MEMORY,
+            "indexedVariables": rows,
+            "expensive": true,
+        },
+    ]})
+}
+
+/// (name, value, hex digits) of every register, in the GDB stub's order.
+fn registers(machine: &Machine) -> Vec<(String, u16, usize)> {
+    let cpu = &machine.cpu;
+    let mut registers: Vec<_> = (0..16)
+        .map(|x| (format!("V{:X}", x), cpu.registers[x] as u16, 2))
+        .collect();
+    registers.push(("I".to_string(), cpu.index_register, 4));
+    registers.push(("PC".to_string(), cpu.program_counter, 4));
+    registers.push(("SP".to_string(), cpu.stack_pointer as u16, 0));
+    registers.push(("DT".to_string(), machine.timers.delay_timer as u16, 2));
+    registers.push(("ST".to_string(), machine.timers.sound_timer as u16, 2));
+    registers
+}
+
+/// `0x2a`, or plain decimal for a width of 0.
+fn hex(value: u16, width: usize) -> String {
+    match width {
+        0 => value.to_string(),
+        width => format!("{:#0w$x}", value, w = width + 2),
+    }
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({"name": name, "value": value, "variablesReference": 0})
+}
+
+/// Watch and hover expressions are debugger conditions, see expr.rs.
+fn evaluate(machine: &Machine, arguments: &Value) -> Result<Value, String> {
+    let expr = Expr::parse(arguments["expression"].as_str().unwrap_or_default())?;
+    let value = expr.eval(machine);
+    Ok(json!({
+        "result": format!("{} ({:#x})", value, value),
+        "variablesReference": 0,
+    }))
+}
+
+/// `instructionCount` instructions starting `instructionOffset` instructions from
+/// `memoryReference` plus `offset`. Going backwards counts 2 bytes an instruction. Neither
+/// count goes past the size of RAM, more would only be "??" lines.
+fn disassemble(machine: &Machine, arguments: &Value) -> Result<Value, String> {
+    let ram = &machine.memory.ram;
+    let limit = ram.len() as i64;
+    let base = arguments["memoryReference"]
+        .as_str()
+        .and_then(parse_addr)
+        .ok_or("memoryReference isn't an address")?;
+    let offset = arguments["offset"].as_i64().unwrap_or(0);
+    let skip = arguments["instructionOffset"]
+        .as_i64()
+        .unwrap_or(0)
+        .clamp(-limit, limit);
+    let count = arguments["instructionCount"]
+        .as_u64()
+        .unwrap_or(0)
+        .min(limit as u64);
+    let out_of_range = || "offset is out of range".to_string();
+    let mut addr = base
+        .checked_add(offset)
+        .and_then(|addr| addr.checked_add(skip.min(0) * 2))
+        .ok_or_else(out_of_range)?;
+    let mut instructions = Vec::new();
+    let mut skipped = 0;
+    while (instructions.len() as u64) < count {
+        let line = u16::try_from(addr)
+            .ok()
+            .filter(|&addr| (addr as usize) < ram.len())
+            .map(|addr| decode_at(ram, addr, variant(machine)));
+        let len = line.as_ref().map_or(2, |line| line.bytes.len().max(2)) as i64;
+        if skipped < skip {
+            skipped += 1;
+            addr = addr.checked_add(len).ok_or_else(out_of_range)?;
+            continue;
+        }
+        let instruction = match line {
+            Some(line) => {
+                let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                json!({
+                    "address": format!("{:#06x}", addr),
+                    "instruction": line.text,
+                    "instructionBytes": bytes.join(" "),
+                })
+            }
+            None => json!({"address": format!("{:#06x}", addr), "instruction": "??"}),
+        };
+        instructions.push(instruction);
+        addr = addr.checked_add(len).ok_or_else(out_of_range)?;
+    }
+    Ok(json!({ "instructions": instructions }))
+}
+
+fn stopped(reason: &str, text: Option<String>) -> Value {
+    let mut body = json!({
+        "reason": reason,
+        "threadId": THREAD_ID,
+        "allThreadsStopped": true,
+    });
+    if let Some(text) = text {
+        body["text"] = json!(text);
+    }
+    body
+}
+
+fn source_json(path: &Path) -> Value {
+    json!({
+        "name": path.file_name().map(|name| name.to_string_lossy()),
+        "path": path,
+    })
+}
+
+fn variant(machine: &Machine) -> Variant {
+    match machine.is_xo_chip() {
+        true => Variant::XoChip,
+        false => Variant::SuperChip,
+    }
+}
+
+/// `0x200` or plain decimal, as editors send them back.
+fn parse_addr(text: &str) -> Option<i64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Editors don't always spell a path the way the launch arguments did.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod daptests {
+    use super::{read_message, write_message, DapSession};
+    use serde_json::{json, Value};
+    use std::io::Cursor;
+
+    fn request(session: &mut DapSession, command: &str, arguments: Value) -> Vec<Value> {
+        let message =
+            json!({"seq": 1, "type": "request", "command": command, "arguments": arguments});
+        session.handle(&message)
+    }
+
+    /// The body of the response, which has to have worked.
+    fn ask(session: &mut DapSession, command: &str, arguments: Value) -> Value {
+        let out = request(session, command, arguments);
+        assert_eq!(out[0]["success"], true, "{}: {}", command, out[0]);
+        out[0]["body"].clone()
+    }
+
+    /// Runs frames until the editor is told the machine stopped, returns why.
+    fn run_until_stopped(session: &mut DapSession) -> Value {
+        for _ in 0..600 {
+            if let Some(event) = session
+                .run_frame()
+                .into_iter()
+                .find(|m| m["event"] == "stopped")
+            {
+                return event["body"].clone();
+            }
+        }
+        panic!("never stopped");
+    }
+
+    fn pc(session: &mut DapSession) -> String {
+        let trace = ask(session, "stackTrace", json!({"threadId": 1}));
+        trace["stackFrames"][0]["instructionPointerReference"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn launch(session: &mut DapSession) {
+        ask(session, "initialize", json!({"adapterID": "chip8"}));
+        let out = request(
+            session,
+            "launch",
+            json!({"program": "roms/2-ibm-logo.ch8", "stopOnEntry": true}),
+        );
+        assert_eq!(out[0]["success"], true);
+        assert_eq!(out[1]["event"], "initialized");
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping() {
+        let mut session = DapSession::new();
+        launch(&mut session);
+        let body = ask(
+            &mut session,
+            "setInstructionBreakpoints",
+            json!({"breakpoints": [{"instructionReference": "0x20a"}, {"instructionReference": "x"}]}),
+        );
+        assert_eq!(body["breakpoints"][0]["verified"], true);
+        assert_eq!(body["breakpoints"][1]["verified"], false);
+
+        // nothing runs until configurationDone, then it stops on entry
+        assert!(session.run_frame().is_empty());
+        let out = request(&mut session, "configurationDone", json!({}));
+        assert_eq!(out[1]["body"]["reason"], "entry");
+        assert_eq!(out[2]["event"], "chip8/screen");
+        assert!(!session.is_running());
+        assert_eq!(pc(&mut session), "0x0200");
+
+        request(&mut session, "continue", json!({"threadId": 1}));
+        assert_eq!(run_until_stopped(&mut session)["reason"], "breakpoint");
+        assert_eq!(pc(&mut session), "0x020a");
+
+        let out = request(&mut session, "stepIn", json!({"threadId": 1}));
+        assert_eq!(out[1]["body"]["reason"], "step");
+        assert_eq!(pc(&mut session), "0x020c");
+
+        // replacing the list takes the old breakpoint out
+        ask(
+            &mut session,
+            "setInstructionBreakpoints",
+            json!({"breakpoints": []}),
+        );
+        request(&mut session, "continue", json!({"threadId": 1}));
+        for _ in 0..30 {
+            assert!(session.run_frame().iter().all(|m| m["event"] != "stopped"));
+        }
+        let out = request(&mut session, "pause", json!({"threadId": 1}));
+        assert_eq!(out[1]["body"]["reason"], "pause");
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut session = DapSession::new();
+        launch(&mut session);
+        request(&mut session, "configurationDone", json!({}));
+
+        let set = |session: &mut DapSession, name: &str, value: &str| {
+            let arguments = json!({"variablesReference": 1, "name": name, "value": value});
+            request(session, "setVariable", arguments)[0].clone()
+        };
+        assert_eq!(set(&mut session, "v3", "0x10 + 2")["body"]["value"], "0x12");
+        assert_eq!(set(&mut session, "I", "0x300")["body"]["value"], "0x0300");
+        assert_eq!(set(&mut session, "V3", "256")["success"], false);
+        assert_eq!(set(&mut session, "VG", "1")["success"], false);
+
+        let registers = ask(&mut session, "variables", json!({"variablesReference": 1}));
+        let registers = registers["variables"].as_array().unwrap().clone();
+        assert_eq!(registers.len(), 21);
+        assert_eq!(
+            registers[3],
+            json!({"name": "V3", "value": "0x12", "variablesReference": 0})
+        );
+        assert_eq!(registers[17]["value"], "0x0200");
+
+        let memory = ask(
+            &mut session,
+            "variables",
+            json!({"variablesReference": 3, "start": 32, "count": 1}),
+        );
+        assert_eq!(memory["variables"][0]["name"], "0x0200");
+        assert!(memory["variables"][0]["value"]
+            .as_str()
+            .unwrap()
+            .starts_with("00 e0 a2 2a"));
+
+        let result = ask(
+            &mut session,
+            "evaluate",
+            json!({"expression": "V3 + [0x201]"}),
+        );
+        assert_eq!(result["result"], "242 (0xf2)");
+
+        let code = ask(
+            &mut session,
+            "disassemble",
+            json!({"memoryReference": "0x200", "instructionOffset": 1, "instructionCount": 2}),
+        );
+        assert_eq!(code["instructions"][0]["address"], "0x0202");
+        assert_eq!(code["instructions"][0]["instruction"], "LD I, 0x22A");
+        assert_eq!(code["instructions"][1]["instructionBytes"], "60 0c");
+
+        // hostile counts and offsets get clamped or refused, not overflowed
+        let mut arguments = json!({"memoryReference": "0x0", "instructionOffset": i64::MIN});
+        arguments["instructionCount"] = json!(u64::MAX);
+        let code = ask(&mut session, "disassemble", arguments);
+        assert_eq!(code["instructions"].as_array().unwrap().len(), 4096);
+        let out = request(
+            &mut session,
+            "disassemble",
+            json!({"memoryReference": "0x200", "offset": i64::MAX, "instructionCount": 1}),
+        );
+        assert_eq!(out[0]["success"], false);
+        let body = ask(
+            &mut session,
+            "setInstructionBreakpoints",
+            json!({"breakpoints": [{"instructionReference": "0x200", "offset": i64::MAX}]}),
+        );
+        assert_eq!(body["breakpoints"][0]["verified"], false);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut session = DapSession::new();
+        let out = request(&mut session, "stackTrace", json!({"threadId": 1}));
+        assert_eq!(out[0]["success"], false);
+        assert_eq!(
+            request(&mut session, "launch", json!({}))[0]["success"],
+            false
+        );
+        let out = request(
+            &mut session,
+            "launch",
+            json!({"program": "roms/2-ibm-logo.ch8", "ipf": 0}),
+        );
+        assert_eq!(out[0]["success"], false);
+        let out = request(&mut session, "launch", json!({"program": "no/such.ch8"}));
+        assert_eq!(out[0]["success"], false);
+        assert_eq!(
+            request(&mut session, "readMemory", json!({}))[0]["success"],
+            false
+        );
+        // responses say what they answer
+        assert_eq!(out[0]["command"], "launch");
+        assert_eq!(out[0]["request_seq"], 1);
+
+        assert!(!session.is_finished());
+        ask(&mut session, "disconnect", json!({}));
+        assert!(session.is_finished());
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &json!({"seq": 1})).unwrap();
+        bytes.extend_from_slice(b"Content-Length: 3\r\n\r\n{{{");
+        write_message(&mut bytes, &json!({"seq": 2})).unwrap();
+        assert!(bytes.starts_with(b"Content-Length: 9\r\n\r\n{\"seq\":1}"));
+
+        let mut input = Cursor::new(bytes);
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({"seq": 1})));
+        assert!(read_message(&mut input).is_err());
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({"seq": 2})));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+}
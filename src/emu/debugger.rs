@@ -0,0 +1,633 @@
+// Pause, step, breakpoints and watchpoints on top of a Machine.
+// The debugger only decides when to stop, running is still Machine::run_frame_until,
+// so a machine under the debugger behaves exactly like one without it until it stops.
+// Watchpoints are checked between instructions, against what the last one did to the
+// registers and (through the memory bus log) to RAM.
+use super::{
+    error::Fault,
+    expr::{parse_number, Expr},
+    machine::Machine,
+    mem::{Access, AccessKind},
+};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// Why the debugger stopped the machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The user asked it to
+    Paused,
+    /// A step, step over or step out finished
+    Step,
+    /// About to execute the instruction at this breakpoint
+    Breakpoint(u16),
+    /// Run to cursor got there
+    RunTo(u16),
+    /// The instruction at `pc` set off watchpoint number `index`
+    Watchpoint { index: usize, pc: u16 },
+    /// An instruction failed
+    Fault(Fault),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Paused => write!(f, "paused"),
+            StopReason::Step => write!(f, "stepped"),
+            StopReason::Breakpoint(addr) => write!(f, "breakpoint at {:#05x}", addr),
+            StopReason::RunTo(addr) => write!(f, "reached {:#05x}", addr),
+            StopReason::Watchpoint { index, pc } => {
+                write!(f, "watchpoint {} hit by {:#05x}", index + 1, pc)
+            }
+            StopReason::Fault(fault) => write!(f, "{}", fault),
+        }
+    }
+}
+
+/// Something to keep an eye on. Ranges are inclusive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// An instruction reads any of these bytes
+    Read { start: u16, end: u16 },
+    /// An instruction writes any of these bytes
+    Write { start: u16, end: u16 },
+    /// VX changes value
+    Register(u8),
+    /// I moves into the range
+    Index { start: u16, end: u16 },
+    /// The stack gets this deep
+    Depth(usize),
+    /// The expression becomes true
+    Condition(Expr),
+}
+
+impl Watchpoint {
+    /// For the ones that fire on becoming true, whether they are now.
+    fn level(&self, machine: &Machine) -> bool {
+        let cpu = &machine.cpu;
+        match self {
+            Watchpoint::Index { start, end } => (*start..=*end).contains(&cpu.index_register),
+            Watchpoint::Depth(depth) => cpu.stack_pointer >= *depth,
+            Watchpoint::Condition(expr) => expr.is_true(machine),
+            _ => false,
+        }
+    }
+
+    fn is_on_bus(&self) -> bool {
+        matches!(self, Watchpoint::Read { .. } | Watchpoint::Write { .. })
+    }
+}
+
+/// Parses what Display prints: `read 0x300`, `write 0x300-0x30F`, `change V3`,
+/// `I in 0x300-0x3FF`, `depth 4`, and anything else as a condition like `V3 == 0x10 && I > 0x300`.
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (command, rest) = s.split_once(' ').unwrap_or((s, ""));
+        let rest = rest.trim();
+        match command.to_ascii_lowercase().as_str() {
+            "read" | "r" => parse_range(rest).map(|(start, end)| Watchpoint::Read { start, end }),
+            "write" | "w" => parse_range(rest).map(|(start, end)| Watchpoint::Write { start, end }),
+            "change" => match Expr::parse(rest) {
+                Ok(Expr::Var(super::expr::Var::Register(x))) => Ok(Watchpoint::Register(x)),
+                _ => Err(format!("{} is not a register", rest)),
+            },
+            "depth" => parse_number(rest)
+                .and_then(|n| usize::try_from(n).ok())
+                .map(Watchpoint::Depth)
+                .ok_or(format!("{} is not a depth", rest)),
+            "i" if rest.to_ascii_lowercase().starts_with("in ") => {
+                parse_range(&rest[3..]).map(|(start, end)| Watchpoint::Index { start, end })
+            }
+            _ => Expr::parse(s).map(Watchpoint::Condition),
+        }
+    }
+}
+
+/// `0x300` or `0x300-0x30F`
+fn parse_range(s: &str) -> Result<(u16, u16), String> {
+    let address = |s: &str| {
+        parse_number(s.trim())
+            .and_then(|n| u16::try_from(n).ok())
+            .ok_or(format!("{} is not an address", s.trim()))
+    };
+    let (start, end) = match s.split_once('-') {
+        Some((start, end)) => (address(start)?, address(end)?),
+        None => (address(s)?, address(s)?),
+    };
+    if start > end {
+        return Err(format!("{:#05x} is after {:#05x}", start, end));
+    }
+    Ok((start, end))
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = |f: &mut fmt::Formatter<'_>, start: &u16, end: &u16| match start == end {
+            true => write!(f, "{:#05x}", start),
+            false => write!(f, "{:#05x}-{:#05x}", start, end),
+        };
+        match self {
+            Watchpoint::Read { start, end } => {
+                write!(f, "read ")?;
+                range(f, start, end)
+            }
+            Watchpoint::Write { start, end } => {
+                write!(f, "write ")?;
+                range(f, start, end)
+            }
+            Watchpoint::Register(x) => write!(f, "change V{:X}", x),
+            Watchpoint::Index { start, end } => {
+                write!(f, "I in ")?;
+                range(f, start, end)
+            }
+            Watchpoint::Depth(depth) => write!(f, "depth {}", depth),
+            Watchpoint::Condition(expr) => write!(f, "{}", expr),
+        }
+    }
+}
+
+/// The machine as the watchpoints last saw it.
+#[derive(Debug)]
+struct Baseline {
+    pc: u16,
+    registers: [u8; 16],
+    /// Watchpoint::level for each watchpoint
+    levels: Vec<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Paused,
+    Running,
+    /// Until the call at the step returns: pc back at `return_pc` with the stack as it was
+    StepOver {
+        return_pc: u16,
+        stack_pointer: usize,
+    },
+    /// Until the stack is shallower than this
+    StepOut {
+        stack_pointer: usize,
+    },
+    RunTo(u16),
+}
+
+#[derive(Debug)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    mode: Mode,
+    /// Set when resuming, so the instruction we stopped on runs instead of stopping us again
+    resuming: bool,
+    last_stop: Option<StopReason>,
+    watchpoints: Vec<Watchpoint>,
+    /// None until the next check has had a look, then watchpoints compare against it
+    baseline: Option<Baseline>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    /// Starts out running, with no breakpoints.
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            mode: Mode::Running,
+            resuming: false,
+            last_stop: None,
+            watchpoints: Vec::new(),
+            baseline: None,
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn has_breakpoint(&self, addr: u16) -> bool {
+        self.breakpoints.contains(&addr)
+    }
+
+    /// Sets a breakpoint, or clears it if there already was one. Returns whether it's set now.
+    pub fn toggle_breakpoint(&mut self, addr: u16) -> bool {
+        if self.breakpoints.remove(&addr) {
+            false
+        } else {
+            self.breakpoints.insert(addr);
+            true
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+        self.baseline = None;
+    }
+
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        self.baseline = None;
+        (index < self.watchpoints.len()).then(|| self.watchpoints.remove(index))
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+        self.baseline = None;
+    }
+
+    /// Call after the machine jumps somewhere by itself (a state load, rewinding),
+    /// so the watchpoints don't take the jump for something an instruction did.
+    pub fn machine_changed(&mut self) {
+        self.baseline = None;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    /// Why it last stopped. Cleared when it runs again.
+    pub fn last_stop(&self) -> Option<StopReason> {
+        self.last_stop
+    }
+
+    pub fn pause(&mut self) {
+        if !self.is_paused() {
+            self.stop(StopReason::Paused);
+        }
+    }
+
+    /// Continues until the next breakpoint (or fault).
+    pub fn resume(&mut self) {
+        self.run(Mode::Running);
+    }
+
+    /// Executes exactly one instruction and stays paused.
+    /// Counts as a new frame for the display_wait quirk, otherwise stepping over a DXYN
+    /// would never get anywhere. The timers don't move.
+    pub fn step(&mut self, machine: &mut Machine) -> StopReason {
+        self.watch_bus(machine);
+        machine.memory.take_accesses();
+        self.baseline = Some(self.observe(machine));
+        machine.cpu.vblank = true;
+        let reason = match machine.step() {
+            Ok(()) => self.check_watchpoints(machine).unwrap_or(StopReason::Step),
+            Err(fault) => StopReason::Fault(fault),
+        };
+        self.stop(reason);
+        reason
+    }
+
+    /// Like `step`, but a 2NNN runs until the subroutine has returned.
+    pub fn step_over(&mut self, machine: &mut Machine) {
+        let pc = machine.cpu.program_counter;
+        let is_call = machine
+            .memory
+            .slice(pc as usize, 1)
+            .is_ok_and(|bytes| bytes[0] >> 4 == 0x2);
+        if is_call {
+            self.run(Mode::StepOver {
+                return_pc: pc.wrapping_add(2),
+                stack_pointer: machine.cpu.stack_pointer,
+            });
+        } else {
+            self.step(machine);
+        }
+    }
+
+    /// Runs until the current subroutine returns. Just a step outside of one.
+    pub fn step_out(&mut self, machine: &mut Machine) {
+        match machine.cpu.stack_pointer {
+            0 => {
+                self.step(machine);
+            }
+            stack_pointer => self.run(Mode::StepOut { stack_pointer }),
+        }
+    }
+
+    /// Runs until the pc gets to `addr` (or a breakpoint comes first).
+    pub fn run_to(&mut self, addr: u16) {
+        self.run(Mode::RunTo(addr));
+    }
+
+    /// Runs one frame unless paused. Returns why it stopped, if it did.
+    pub fn run_frame(&mut self, machine: &mut Machine) -> Option<StopReason> {
+        if self.is_paused() {
+            return None;
+        }
+        self.watch_bus(machine);
+        let mut reason = None;
+        let result = machine.run_frame_until(|machine| {
+            reason = self.check(machine);
+            reason.is_some()
+        });
+        if let Err(fault) = result {
+            reason = Some(StopReason::Fault(fault));
+        }
+        if let Some(reason) = reason {
+            self.stop(reason);
+        }
+        reason
+    }
+
+    fn run(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.resuming = true;
+        self.last_stop = None;
+        self.baseline = None;
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        self.mode = Mode::Paused;
+        self.last_stop = Some(reason);
+    }
+
+    /// Only log memory accesses when a watchpoint wants them.
+    fn watch_bus(&self, machine: &mut Machine) {
+        let on = self.watchpoints.iter().any(Watchpoint::is_on_bus);
+        machine.memory.watch(on);
+    }
+
+    fn observe(&self, machine: &Machine) -> Baseline {
+        Baseline {
+            pc: machine.cpu.program_counter,
+            registers: machine.cpu.registers,
+            levels: self.watchpoints.iter().map(|w| w.level(machine)).collect(),
+        }
+    }
+
+    /// What the instruction since the last look set off, if anything. Always takes a new look.
+    fn check_watchpoints(&mut self, machine: &Machine) -> Option<StopReason> {
+        let accesses = machine.memory.take_accesses();
+        let now = self.observe(machine);
+        let before = self.baseline.replace(now)?;
+        let now = self.baseline.as_ref()?;
+        let touched = |kind: AccessKind, start: u16, end: u16| {
+            accesses
+                .iter()
+                .any(|a: &Access| a.kind == kind && a.overlaps(start as usize, end as usize))
+        };
+        let index = self
+            .watchpoints
+            .iter()
+            .enumerate()
+            .position(|(i, w)| match w {
+                Watchpoint::Read { start, end } => touched(AccessKind::Read, *start, *end),
+                Watchpoint::Write { start, end } => touched(AccessKind::Write, *start, *end),
+                Watchpoint::Register(x) => {
+                    before.registers[*x as usize] != now.registers[*x as usize]
+                }
+                _ => now.levels[i] && !before.levels[i],
+            })?;
+        Some(StopReason::Watchpoint {
+            index,
+            pc: before.pc,
+        })
+    }
+
+    /// Whether to stop before the instruction at the pc.
+    fn check(&mut self, machine: &Machine) -> Option<StopReason> {
+        let watchpoint = self.check_watchpoints(machine);
+        if std::mem::take(&mut self.resuming) {
+            return None;
+        }
+        if watchpoint.is_some() {
+            return watchpoint;
+        }
+        let pc = machine.cpu.program_counter;
+        let stack_pointer = machine.cpu.stack_pointer;
+        if self.breakpoints.contains(&pc) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+        match self.mode {
+            Mode::StepOver {
+                return_pc,
+                stack_pointer: depth,
+            } if pc == return_pc && stack_pointer == depth => Some(StopReason::Step),
+            Mode::StepOut {
+                stack_pointer: depth,
+            } if stack_pointer < depth => Some(StopReason::Step),
+            Mode::RunTo(addr) if pc == addr => Some(StopReason::RunTo(addr)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod debuggertests {
+    use super::{Debugger, StopReason, Watchpoint};
+    use crate::emu::{
+        clock::ClockSpeed,
+        error::{Chip8Error, Fault},
+    };
+    use crate::Machine;
+
+    /// 0x200: 2206 -> call 0x206, 0x202: 7101 -> V1 += 1, 0x204: 1202 -> loop
+    /// 0x206: 7001 -> V0 += 1, 0x208: 00EE -> return
+    fn machine() -> Machine {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(10));
+        machine
+            .load_rom(&[0x22, 0x06, 0x71, 0x01, 0x12, 0x02, 0x70, 0x01, 0x00, 0xEE])
+            .unwrap();
+        machine
+    }
+
+    #[test]
+    fn test_breakpoint_stops_before_the_instruction() {
+        let mut machine = machine();
+        let mut debugger = Debugger::new();
+        assert!(debugger.toggle_breakpoint(0x206));
+        assert_eq!(
+            debugger.run_frame(&mut machine),
+            Some(StopReason::Breakpoint(0x206))
+        );
+        assert_eq!(machine.cpu.program_counter, 0x206);
+        assert_eq!(machine.cpu.registers[0], 0);
+        // paused frames don't run anything
+        assert_eq!(debugger.run_frame(&mut machine), None);
+        assert_eq!(machine.cpu.program_counter, 0x206);
+
+        // resuming runs the instruction under the breakpoint, and stops there next time round
+        debugger.toggle_breakpoint(0x206);
+        debugger.toggle_breakpoint(0x202);
+        debugger.resume();
+        assert_eq!(
+            debugger.run_frame(&mut machine),
+            Some(StopReason::Breakpoint(0x202))
+        );
+        assert_eq!(machine.cpu.registers[0], 1);
+        debugger.resume();
+        assert_eq!(
+            debugger.run_frame(&mut machine),
+            Some(StopReason::Breakpoint(0x202))
+        );
+        assert_eq!(machine.cpu.registers[1], 1);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let mut machine = machine();
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        assert_eq!(debugger.last_stop(), Some(StopReason::Paused));
+
+        debugger.step_over(&mut machine);
+        assert_eq!(debugger.run_frame(&mut machine), Some(StopReason::Step));
+        assert_eq!(machine.cpu.program_counter, 0x202);
+        assert_eq!(machine.cpu.registers[0], 1);
+
+        // step over anything else is a plain step
+        debugger.step_over(&mut machine);
+        assert_eq!(machine.cpu.program_counter, 0x204);
+        assert!(debugger.is_paused());
+
+        machine.cpu.program_counter = 0x200;
+        assert_eq!(debugger.step(&mut machine), StopReason::Step);
+        assert_eq!(machine.cpu.program_counter, 0x206);
+        debugger.step_out(&mut machine);
+        assert_eq!(debugger.run_frame(&mut machine), Some(StopReason::Step));
+        assert_eq!(machine.cpu.program_counter, 0x202);
+        assert_eq!(machine.cpu.stack_pointer, 0);
+    }
+
+    #[test]
+    fn test_run_to() {
+        let mut machine = machine();
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        debugger.run_to(0x208);
+        assert_eq!(
+            debugger.run_frame(&mut machine),
+            Some(StopReason::RunTo(0x208))
+        );
+        assert_eq!(debugger.last_stop(), Some(StopReason::RunTo(0x208)));
+    }
+
+    #[test]
+    fn test_fault_pauses() {
+        let mut machine = Machine::new();
+        // 0x200: 00EE with an empty stack
+        machine.load_rom(&[0x00, 0xEE]).unwrap();
+        let mut debugger = Debugger::new();
+        let fault = Fault {
+            pc: 0x200,
+            opcode: 0x00EE,
+            error: Chip8Error::StackUnderflow,
+        };
+        assert_eq!(
+            debugger.run_frame(&mut machine),
+            Some(StopReason::Fault(fault))
+        );
+        assert!(debugger.is_paused());
+        assert_eq!(
+            StopReason::Fault(fault).to_string(),
+            "stack underflow (pc 0x200, opcode 00ee)"
+        );
+    }
+
+    /// 0x200: 6310 -> V3 = 0x10, 0x202: A300 -> I = 0x300, 0x204: F355 -> save V0-V3 at I, I += 4
+    /// 0x206: 220A -> call 0x20A, 0x208: 1208 -> loop
+    /// 0x20A: F365 -> load V0-V3 from I, 0x20C: 00EE -> return
+    fn watched() -> Machine {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(10));
+        machine
+            .load_rom(&[
+                0x63, 0x10, 0xA3, 0x00, 0xF3, 0x55, 0x22, 0x0A, 0x12, 0x08, 0xF3, 0x65, 0x00, 0xEE,
+            ])
+            .unwrap();
+        machine
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let cases = [
+            ("write 0x302", 0x204),
+            ("read 0x2F0-0x304", 0x20A),
+            ("change V3", 0x200),
+            ("I in 0x300-0x3FF", 0x202),
+            ("depth 1", 0x206),
+            ("V3 == 0x10 && I > 0x2FF", 0x202),
+        ];
+        for (watchpoint, pc) in cases {
+            let mut machine = watched();
+            let mut debugger = Debugger::new();
+            debugger.add_watchpoint(watchpoint.parse().unwrap());
+            assert_eq!(
+                debugger.run_frame(&mut machine),
+                Some(StopReason::Watchpoint { index: 0, pc }),
+                "{}",
+                watchpoint
+            );
+            // stopped right after the instruction that did it
+            assert_ne!(machine.cpu.program_counter, pc);
+        }
+    }
+
+    #[test]
+    fn test_watchpoint_fires_once_per_change() {
+        let mut machine = watched();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::Depth(1));
+        assert!(debugger.run_frame(&mut machine).is_some());
+        // still deep enough after resuming, but it has to get shallower and back first
+        debugger.resume();
+        assert_eq!(debugger.run_frame(&mut machine), None);
+        assert_eq!(machine.cpu.program_counter, 0x208);
+    }
+
+    #[test]
+    fn test_step_onto_a_watchpoint() {
+        let mut machine = watched();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::Write {
+            start: 0x300,
+            end: 0x300,
+        });
+        debugger.pause();
+        assert_eq!(debugger.step(&mut machine), StopReason::Step);
+        assert_eq!(debugger.step(&mut machine), StopReason::Step);
+        assert_eq!(
+            debugger.step(&mut machine),
+            StopReason::Watchpoint {
+                index: 0,
+                pc: 0x204
+            }
+        );
+        assert!(machine.memory.is_watched());
+        debugger.clear_watchpoints();
+        debugger.step(&mut machine);
+        assert!(!machine.memory.is_watched());
+    }
+
+    #[test]
+    fn test_parse_watchpoints() {
+        for text in [
+            "read 0x300",
+            "write 0x300-0x30f",
+            "change VA",
+            "I in 0x300-0x3ff",
+            "depth 4",
+            "((V3 == 0x10) && (I > 0x300))",
+        ] {
+            let watchpoint: Watchpoint = text.parse().unwrap();
+            assert_eq!(watchpoint.to_string(), text);
+        }
+        assert_eq!(
+            "r $300".parse(),
+            Ok(Watchpoint::Read {
+                start: 0x300,
+                end: 0x300
+            })
+        );
+        assert!("write 0x310-0x300".parse::<Watchpoint>().is_err());
+        assert!("change I".parse::<Watchpoint>().is_err());
+        assert!("V3 = 1".parse::<Watchpoint>().is_err());
+    }
+}
@@ -0,0 +1,319 @@
+// Disassembler: opcodes to Cowgod style mnemonics, `DRW V4, VA, 2`, `LD I, 0x234`, `SE V3, 0x10`.
+// Shared by the logs (OpCode's Display), the TUI debugger and `chip8 disasm`.
+use super::instruction::Instruction;
+use super::iset::OpCode;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which instruction set to read the bytes as. Opcodes a variant doesn't have come out as data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    Chip8,
+    /// SUPER-CHIP 1.1: scrolling, hires, big font and the RPL flags
+    SuperChip,
+    /// Octo's XO-CHIP, everything this interpreter runs
+    #[default]
+    XoChip,
+}
+
+impl Variant {
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Chip8 => "chip8",
+            Variant::SuperChip => "schip",
+            Variant::XoChip => "xochip",
+        }
+    }
+
+    fn has_schip(self) -> bool {
+        self != Variant::Chip8
+    }
+
+    fn has_xochip(self) -> bool {
+        self == Variant::XoChip
+    }
+
+    /// Whether this variant has `instruction`, the plain CHIP-8 ones are everywhere.
+    pub fn supports(self, instruction: Instruction) -> bool {
+        use Instruction::*;
+        match instruction {
+            ScrollDown(_)
+            | ScrollRight
+            | ScrollLeft
+            | Exit
+            | LowRes
+            | HighRes
+            | BigFont { .. }
+            | SaveFlags { .. }
+            | LoadFlags { .. } => self.has_schip(),
+            Unknown(_) => false,
+            _ if instruction.is_xo_chip() => self.has_xochip(),
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Variant::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Variant::SuperChip),
+            "xochip" | "xo-chip" => Ok(Variant::XoChip),
+            _ => Err(format!(
+                "unknown variant {}, expected chip8, schip or xochip",
+                s
+            )),
+        }
+    }
+}
+
+/// Which addresses get a name in a listing, and what it is.
+type Labels<'a> = &'a dyn Fn(u16) -> Option<String>;
+
+/// The mnemonic for `op`, None if `variant` doesn't have it.
+/// `long` is the word after an XO-CHIP F000, `label` names jump and call targets.
+fn render(op: u16, long: Option<u16>, variant: Variant, label: Labels) -> Option<String> {
+    use Instruction::*;
+    let addr = |addr: u16| label(addr).unwrap_or(format!("0x{:03X}", addr));
+    let instruction = match Instruction::decode(op) {
+        instruction if variant.supports(instruction) => instruction,
+        // what later variants put in the 0 block was a machine code call before them
+        _ if op >> 12 == 0 => Sys(op & 0xFFF),
+        _ => return None,
+    };
+    let text = match instruction {
+        Cls => "CLS".to_string(),
+        Ret => "RET".to_string(),
+        ScrollDown(n) => format!("SCD {}", n),
+        ScrollUp(n) => format!("SCU {}", n),
+        ScrollRight => "SCR".to_string(),
+        ScrollLeft => "SCL".to_string(),
+        Exit => "EXIT".to_string(),
+        LowRes => "LOW".to_string(),
+        HighRes => "HIGH".to_string(),
+        Sys(nnn) => format!("SYS {}", addr(nnn)),
+        Jump(nnn) => format!("JP {}", addr(nnn)),
+        Call(nnn) => format!("CALL {}", addr(nnn)),
+        SkipEqImm { x, nn } => format!("SE V{:X}, 0x{:02X}", x, nn),
+        SkipNeImm { x, nn } => format!("SNE V{:X}, 0x{:02X}", x, nn),
+        SkipEqReg { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        SaveRange { x, y } => format!("SAVE V{:X}-V{:X}", x, y),
+        LoadRange { x, y } => format!("LOAD V{:X}-V{:X}", x, y),
+        LoadImm { x, nn } => format!("LD V{:X}, 0x{:02X}", x, nn),
+        AddImm { x, nn } => format!("ADD V{:X}, 0x{:02X}", x, nn),
+        Move { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        SubN { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        SkipNeReg { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        LoadI(nnn) => format!("LD I, {}", addr(nnn)),
+        JumpOffset(nnn) => format!("JP V0, {}", addr(nnn)),
+        Random { x, nn } => format!("RND V{:X}, 0x{:02X}", x, nn),
+        Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        SkipKey { x } => format!("SKP V{:X}", x),
+        SkipNotKey { x } => format!("SKNP V{:X}", x),
+        LoadILong => match long {
+            Some(long) => format!(
+                "LD I, long {}",
+                label(long).unwrap_or(format!("0x{:04X}", long))
+            ),
+            None => "LD I, long".to_string(),
+        },
+        Plane(n) => format!("PLANE {}", n),
+        Audio => "AUDIO".to_string(),
+        LoadDelay { x } => format!("LD V{:X}, DT", x),
+        WaitKey { x } => format!("LD V{:X}, K", x),
+        SetDelay { x } => format!("LD DT, V{:X}", x),
+        SetSound { x } => format!("LD ST, V{:X}", x),
+        AddI { x } => format!("ADD I, V{:X}", x),
+        Font { x } => format!("LD F, V{:X}", x),
+        BigFont { x } => format!("LD HF, V{:X}", x),
+        Bcd { x } => format!("LD B, V{:X}", x),
+        Pitch { x } => format!("PITCH V{:X}", x),
+        Store { x } => format!("LD [I], V{:X}", x),
+        Load { x } => format!("LD V{:X}, [I]", x),
+        SaveFlags { x } => format!("LD R, V{:X}", x),
+        LoadFlags { x } => format!("LD V{:X}, R", x),
+        Unknown(_) => return None,
+    };
+    Some(text)
+}
+
+/// The mnemonic for a single opcode, `DW 0x1234` for ones `variant` doesn't have.
+pub fn mnemonic(op: u16, variant: Variant) -> String {
+    render(op, None, variant, &|_| None).unwrap_or(format!("DW 0x{:04X}", op))
+}
+
+/// Logs print opcodes as what they do, e.g. `DRW V4, VA, 2`.
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", mnemonic(self.0, Variant::default()))
+    }
+}
+
+/// One line of a listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disassembled {
+    pub addr: u16,
+    /// 2 bytes, 4 for XO-CHIP's F000 NNNN, 1 for an odd byte at the end
+    pub bytes: Vec<u8>,
+    pub text: String,
+    /// Set when something jumps or calls here
+    pub label: Option<String>,
+}
+
+/// Decodes whatever is at `addr` in `ram`, without labels. Handy for walking memory live.
+pub fn decode_at(ram: &[u8], addr: u16, variant: Variant) -> Disassembled {
+    decode(ram, addr as usize, addr, variant, &|_| None)
+}
+
+/// `code[offset..]` sitting at address `addr`.
+fn decode(code: &[u8], offset: usize, addr: u16, variant: Variant, label: Labels) -> Disassembled {
+    let word = |at: usize| {
+        code.get(at..at + 2)
+            .map(|bytes| (bytes[0] as u16) << 8 | bytes[1] as u16)
+    };
+    let Some(op) = word(offset) else {
+        let bytes = code.get(offset..offset + 1).unwrap_or_default().to_vec();
+        let text = bytes
+            .first()
+            .map_or(String::new(), |b| format!("DB 0x{:02X}", b));
+        return Disassembled {
+            addr,
+            bytes,
+            text,
+            label: None,
+        };
+    };
+    let long = (op == 0xF000 && variant.has_xochip())
+        .then(|| word(offset + 2))
+        .flatten();
+    let len = if long.is_some() { 4 } else { 2 };
+    let text = render(op, long, variant, label).unwrap_or(format!("DW 0x{:04X}", op));
+    Disassembled {
+        addr,
+        bytes: code[offset..offset + len].to_vec(),
+        text,
+        label: None,
+    }
+}
+
+/// Reads `rom` as loaded at `origin` from start to end. Jump and call targets that land on an
+/// instruction get a label (`L2A4`), and the instructions going there use it.
+pub fn disassemble(rom: &[u8], origin: u16, variant: Variant) -> Vec<Disassembled> {
+    // first pass finds where instructions start, second one names the targets
+    let plain: Vec<Disassembled> = walk(rom, origin, variant, &|_| None);
+    let starts: BTreeSet<u16> = plain.iter().map(|line| line.addr).collect();
+    let targets: BTreeSet<u16> = plain
+        .iter()
+        .filter(|line| line.bytes.len() >= 2)
+        .filter_map(|line| {
+            Instruction::decode((line.bytes[0] as u16) << 8 | line.bytes[1] as u16).target()
+        })
+        .filter(|addr| starts.contains(addr))
+        .collect();
+    let name = |addr: u16| targets.contains(&addr).then(|| format!("L{:03X}", addr));
+    let mut lines = walk(rom, origin, variant, &name);
+    for line in &mut lines {
+        line.label = name(line.addr);
+    }
+    lines
+}
+
+fn walk(rom: &[u8], origin: u16, variant: Variant, label: Labels) -> Vec<Disassembled> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = origin.wrapping_add(offset as u16);
+        let line = decode(rom, offset, addr, variant, label);
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+    lines
+}
+
+/// `disassemble` as text: labels on their own line, then the mnemonic with the address and
+/// raw bytes in a comment. Feeding it back to the assembler gives the same ROM.
+pub fn listing(rom: &[u8], origin: u16, variant: Variant) -> String {
+    let mut out = String::new();
+    for line in disassemble(rom, origin, variant) {
+        if let Some(label) = &line.label {
+            out.push_str(&format!("{}:\n", label));
+        }
+        let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        out.push_str(&format!(
+            "    {:<20} ; {:03X}  {}\n",
+            line.text,
+            line.addr,
+            bytes.join(" ")
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod disasmtests {
+    use super::{decode_at, disassemble, listing, mnemonic, Variant};
+    use crate::emu::iset::OpCode;
+
+    #[test]
+    fn test_mnemonics() {
+        let variant = Variant::default();
+        assert_eq!(mnemonic(0xD4A2, variant), "DRW V4, VA, 2");
+        assert_eq!(mnemonic(0xA234, variant), "LD I, 0x234");
+        assert_eq!(mnemonic(0x3310, variant), "SE V3, 0x10");
+        assert_eq!(mnemonic(0x8AB6, variant), "SHR VA, VB");
+        assert_eq!(mnemonic(0xF265, variant), "LD V2, [I]");
+        assert_eq!(mnemonic(0xE000, variant), "DW 0xE000");
+        assert_eq!(OpCode(0x00EE).to_string(), "RET");
+    }
+
+    #[test]
+    fn test_variants() {
+        assert_eq!(mnemonic(0x00FF, Variant::Chip8), "SYS 0x0FF");
+        assert_eq!(mnemonic(0x00FF, Variant::SuperChip), "HIGH");
+        assert_eq!(mnemonic(0xF130, Variant::Chip8), "DW 0xF130");
+        assert_eq!(mnemonic(0xF130, Variant::SuperChip), "LD HF, V1");
+        assert_eq!(mnemonic(0x5122, Variant::SuperChip), "DW 0x5122");
+        assert_eq!(mnemonic(0x5122, Variant::XoChip), "SAVE V1-V2");
+        assert_eq!(mnemonic(0xF201, Variant::XoChip), "PLANE 2");
+        assert_eq!("SCHIP".parse(), Ok(Variant::SuperChip));
+        assert!("nes".parse::<Variant>().is_err());
+
+        // F000 NNNN takes the next word along with it on XO-CHIP only
+        let ram = [0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0];
+        let long = decode_at(&ram, 0, Variant::XoChip);
+        assert_eq!(long.text, "LD I, long 0x1234");
+        assert_eq!(long.bytes.len(), 4);
+        assert_eq!(decode_at(&ram, 0, Variant::SuperChip).bytes.len(), 2);
+    }
+
+    #[test]
+    fn test_listing_labels() {
+        // 0x200: 2206 -> call 0x206, 0x202: 1202 -> loop, 0x204: 1FFF -> outside the ROM
+        // 0x206: 00EE -> return, then an odd byte
+        let rom = [0x22, 0x06, 0x12, 0x02, 0x1F, 0xFF, 0x00, 0xEE, 0xAB];
+        let lines = disassemble(&rom, 0x200, Variant::Chip8);
+        let labels: Vec<_> = lines.iter().filter_map(|l| l.label.as_deref()).collect();
+        assert_eq!(labels, ["L202", "L206"]);
+        assert_eq!(lines[0].text, "CALL L206");
+        assert_eq!(lines[2].text, "JP 0xFFF");
+        assert_eq!(lines[4].text, "DB 0xAB");
+        assert_eq!(
+            listing(&rom, 0x200, Variant::Chip8),
+            "    CALL L206            ; 200  22 06\n\
+             L202:\n    JP L202              ; 202  12 02\n\
+             \x20   JP 0xFFF             ; 204  1F FF\n\
+             L206:\n    RET                  ; 206  00 EE\n\
+             \x20   DB 0xAB              ; 208  AB\n"
+        );
+    }
+}
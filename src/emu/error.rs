@@ -0,0 +1,61 @@
+// Everything that can go wrong while the machine runs.
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// 2NNN with all 16 stack slots in use
+    StackOverflow,
+    /// 00EE with nothing on the stack
+    StackUnderflow,
+    /// An instruction touched memory past the end of RAM
+    MemoryOutOfBounds { addr: usize },
+    /// The opcode doesn't decode to any instruction
+    UnknownOpcode { op: u16, pc: u16 },
+    /// The ROM doesn't fit between ROM_START_ADDRESS and the end of RAM
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::StackOverflow => write!(f, "stack overflow"),
+            Chip8Error::StackUnderflow => write!(f, "stack underflow"),
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:#06x}", addr)
+            }
+            Chip8Error::UnknownOpcode { op, pc } => {
+                write!(f, "unknown opcode {:04x} at {:#05x}", op, pc)
+            }
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes, at most {} fit in memory", size, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// A `Chip8Error` raised by the cpu, along with where it happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    /// Address of the faulting instruction
+    pub pc: u16,
+    pub opcode: u16,
+    pub error: Chip8Error,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (pc {:#05x}, opcode {:04x})",
+            self.error, self.pc, self.opcode
+        )
+    }
+}
+
+impl std::error::Error for Fault {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
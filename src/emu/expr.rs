@@ -0,0 +1,437 @@
+// Tiny expression language for debugger conditions, e.g. `V3 == 0x10 && I > 0x300`.
+//
+//   or    := and ("||" and)*
+//   and   := cmp ("&&" cmp)*
+//   cmp   := sum (("==" | "!=" | "<" | "<=" | ">" | ">=") sum)?
+//   sum   := unary (("+" | "-" | "&" | "|" | "^") unary)*
+//   unary := "!" unary | "-" unary | "(" or ")" | "[" or "]" | number | name
+//
+// Names are V0-VF, I, PC, SP, DT and ST, `[addr]` is the byte in memory at addr.
+// Numbers are decimal, or hex with 0x/$. Comparisons and ! give 1 or 0, anything non-zero is true.
+use super::machine::Machine;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Var {
+    Register(u8),
+    Index,
+    ProgramCounter,
+    StackPointer,
+    DelayTimer,
+    SoundTimer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    BitAnd,
+    BitOr,
+    BitXor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Var(Var),
+    /// The byte at an address
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parses a whole expression, the error says what went wrong and at which column.
+    pub fn parse(source: &str) -> Result<Expr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some((column, token)) => Err(format!("column {}: unexpected {}", column, token)),
+        }
+    }
+
+    /// Evaluates against the machine without it noticing, memory reads skip the bus log.
+    pub fn eval(&self, machine: &Machine) -> i64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Var(var) => {
+                let cpu = &machine.cpu;
+                match var {
+                    Var::Register(x) => cpu.registers[*x as usize] as i64,
+                    Var::Index => cpu.index_register as i64,
+                    Var::ProgramCounter => cpu.program_counter as i64,
+                    Var::StackPointer => cpu.stack_pointer as i64,
+                    Var::DelayTimer => machine.timers.delay_timer as i64,
+                    Var::SoundTimer => machine.timers.sound_timer as i64,
+                }
+            }
+            Expr::Memory(addr) => {
+                let addr = addr.eval(machine);
+                usize::try_from(addr)
+                    .ok()
+                    .and_then(|addr| machine.memory.ram.get(addr))
+                    .map_or(0, |&byte| byte as i64)
+            }
+            Expr::Not(expr) => (expr.eval(machine) == 0) as i64,
+            Expr::Neg(expr) => expr.eval(machine).wrapping_neg(),
+            Expr::Binary(op, left, right) => {
+                let left = left.eval(machine);
+                // short circuit, so `SP > 0 && [I] == 1` style guards work as expected
+                match op {
+                    BinOp::Or if left != 0 => return 1,
+                    BinOp::And if left == 0 => return 0,
+                    _ => {}
+                }
+                let right = right.eval(machine);
+                match op {
+                    BinOp::Or | BinOp::And => (right != 0) as i64,
+                    BinOp::Eq => (left == right) as i64,
+                    BinOp::Ne => (left != right) as i64,
+                    BinOp::Lt => (left < right) as i64,
+                    BinOp::Le => (left <= right) as i64,
+                    BinOp::Gt => (left > right) as i64,
+                    BinOp::Ge => (left >= right) as i64,
+                    BinOp::Add => left.wrapping_add(right),
+                    BinOp::Sub => left.wrapping_sub(right),
+                    BinOp::BitAnd => left & right,
+                    BinOp::BitOr => left | right,
+                    BinOp::BitXor => left ^ right,
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, machine: &Machine) -> bool {
+        self.eval(machine) != 0
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Expr::parse(s)
+    }
+}
+
+impl fmt::Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Var::Register(x) => write!(f, "V{:X}", x),
+            Var::Index => write!(f, "I"),
+            Var::ProgramCounter => write!(f, "PC"),
+            Var::StackPointer => write!(f, "SP"),
+            Var::DelayTimer => write!(f, "DT"),
+            Var::SoundTimer => write!(f, "ST"),
+        }
+    }
+}
+
+impl BinOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Or => "||",
+            BinOp::And => "&&",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+            BinOp::Lt => "<",
+            BinOp::Le => "<=",
+            BinOp::Gt => ">",
+            BinOp::Ge => ">=",
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+        }
+    }
+}
+
+/// Fully parenthesised, so it reads back the same no matter how it was written.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) if *n > 9 => write!(f, "{:#x}", n),
+            Expr::Number(n) => write!(f, "{}", n),
+            Expr::Var(var) => write!(f, "{}", var),
+            Expr::Memory(addr) => write!(f, "[{}]", addr),
+            Expr::Not(expr) => write!(f, "!{}", expr),
+            Expr::Neg(expr) => write!(f, "-{}", expr),
+            Expr::Binary(op, left, right) => write!(f, "({} {} {})", left, op.symbol(), right),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "`{}`", name),
+            Token::Op(op) => write!(f, "`{}`", op),
+        }
+    }
+}
+
+/// Longest first so `<=` isn't read as `<` then `=`
+const OPERATORS: [&str; 19] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "+", "-", "&", "|", "^", "!", "(", ")", "[", "]",
+    "=",
+];
+
+/// Tokens along with the (1-based) column they start at.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[start..];
+        let column = source.len() - rest.len() + 1;
+        let word_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+            .unwrap_or(rest.len());
+        if word_len > 0 {
+            let word = &rest[..word_len];
+            let token = if word.starts_with(|c: char| c.is_ascii_digit() || c == '$') {
+                Token::Number(
+                    parse_number(word)
+                        .ok_or(format!("column {}: {} is not a number", column, word))?,
+                )
+            } else {
+                Token::Name(word.to_ascii_uppercase())
+            };
+            tokens.push((column, token));
+            rest = &rest[word_len..];
+            continue;
+        }
+        let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) else {
+            let c = rest.chars().next().unwrap_or_default();
+            return Err(format!("column {}: unexpected {}", column, c));
+        };
+        if *op == "=" {
+            return Err(format!("column {}: use == to compare", column));
+        }
+        tokens.push((column, Token::Op(op)));
+        rest = &rest[op.len()..];
+    }
+    Ok(tokens)
+}
+
+/// 42, 0x2A, $2A or 0b101010
+pub fn parse_number(word: &str) -> Option<i64> {
+    let word = word.to_ascii_lowercase();
+    if let Some(hex) = word.strip_prefix("0x").or(word.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = word.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        word.parse().ok()
+    }
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some((_, Token::Op(op))) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", op)))
+        }
+    }
+
+    fn error(&self, message: &str) -> String {
+        match self.tokens.get(self.pos) {
+            Some((column, token)) => format!("column {}: {}, found {}", column, message, token),
+            None => format!("{} at the end", message),
+        }
+    }
+
+    /// Left associative chain of `ops` over `next`.
+    fn chain(
+        &mut self,
+        ops: &[(&str, BinOp)],
+        next: fn(&mut Self) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut left = next(self)?;
+        while let Some(&(_, op)) = ops.iter().find(|(s, _)| Some(*s) == self.peek_op()) {
+            self.pos += 1;
+            let right = next(self)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        self.chain(&[("||", BinOp::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        self.chain(&[("&&", BinOp::And)], Self::cmp)
+    }
+
+    fn cmp(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        let ops = [
+            ("==", BinOp::Eq),
+            ("!=", BinOp::Ne),
+            ("<", BinOp::Lt),
+            ("<=", BinOp::Le),
+            (">", BinOp::Gt),
+            (">=", BinOp::Ge),
+        ];
+        match ops.iter().find(|(s, _)| Some(*s) == self.peek_op()) {
+            Some(&(_, op)) => {
+                self.pos += 1;
+                let right = self.sum()?;
+                Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+            }
+            None => Ok(left),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let ops = [
+            ("+", BinOp::Add),
+            ("-", BinOp::Sub),
+            ("&", BinOp::BitAnd),
+            ("|", BinOp::BitOr),
+            ("^", BinOp::BitXor),
+        ];
+        self.chain(&ops, Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let Some((column, token)) = self.tokens.get(self.pos).cloned() else {
+            return Err(self.error("expected a value"));
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Name(name) => variable(&name)
+                .map(Expr::Var)
+                .ok_or(format!("column {}: unknown name {}", column, name)),
+            Token::Op("!") => Ok(Expr::Not(Box::new(self.unary()?))),
+            Token::Op("-") => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Token::Op("(") => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Op("[") => {
+                let expr = self.or()?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(expr)))
+            }
+            Token::Op(op) => Err(format!(
+                "column {}: expected a value, found `{}`",
+                column, op
+            )),
+        }
+    }
+}
+
+fn variable(name: &str) -> Option<Var> {
+    match name {
+        "I" => Some(Var::Index),
+        "PC" => Some(Var::ProgramCounter),
+        "SP" => Some(Var::StackPointer),
+        "DT" => Some(Var::DelayTimer),
+        "ST" => Some(Var::SoundTimer),
+        _ => {
+            let digit = name.strip_prefix('V')?;
+            match u8::from_str_radix(digit, 16) {
+                Ok(x) if digit.len() == 1 => Some(Var::Register(x)),
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod exprtests {
+    use super::Expr;
+    use crate::Machine;
+
+    fn eval(source: &str, machine: &Machine) -> i64 {
+        Expr::parse(source).unwrap().eval(machine)
+    }
+
+    #[test]
+    fn test_conditions() {
+        let mut machine = Machine::new();
+        machine.cpu.registers[3] = 0x10;
+        machine.cpu.index_register = 0x310;
+        assert_eq!(eval("V3 == 0x10 && I > 0x300", &machine), 1);
+        assert_eq!(eval("v3 != 16 || i < $300", &machine), 0);
+        assert_eq!(eval("!(V3 == 16)", &machine), 0);
+        assert_eq!(eval("PC - 0x200 + 1", &machine), 1);
+        // the font's first byte
+        assert_eq!(eval("[I - 0x310] == 0xF0", &machine), 1);
+        assert_eq!(eval("[0xFFFFF]", &machine), 0);
+    }
+
+    #[test]
+    fn test_precedence_and_display() {
+        let expr = Expr::parse("V0 + 1 == 2 && V1 == 3 || SP >= 4").unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "((((V0 + 1) == 2) && (V1 == 3)) || (SP >= 4))"
+        );
+        assert_eq!(Expr::parse(&expr.to_string()), Ok(expr));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Expr::parse("V3 = 1"),
+            Err("column 4: use == to compare".to_string())
+        );
+        assert_eq!(
+            Expr::parse("VG == 1"),
+            Err("column 1: unknown name VG".to_string())
+        );
+        assert_eq!(
+            Expr::parse("(V1 == 2"),
+            Err("expected `)` at the end".to_string())
+        );
+        assert!(Expr::parse("V1 == 2 3").is_err());
+        assert!(Expr::parse("0xZZ").is_err());
+        // not ASCII, nothing to cut in half
+        assert_eq!(
+            Expr::parse("V0 == 1 €"),
+            Err("column 9: unexpected €".to_string())
+        );
+        assert!(Expr::parse("é").is_err());
+    }
+}
@@ -0,0 +1,757 @@
+// GDB remote serial protocol stub, so external debuggers and scripts can drive the cpu over a
+// local TCP socket. GdbStub speaks the protocol and knows nothing about sockets, GdbServer puts
+// it on one. Pausing, stepping, breakpoints and watchpoints are the Debugger's, the stub only
+// translates, so they show up in the terminal debugger too.
+//
+// Registers in `g` packet order, the same as TARGET_XML:
+//   0-15  V0-VF   8 bits
+//   16    I      16 bits
+//   17    PC     16 bits
+//   18    SP      8 bits, how many return addresses are on the stack
+//   19    DT      8 bits
+//   20    ST      8 bits
+// The 16 bit ones go over the wire little endian.
+//
+// https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+use super::{
+    debugger::{Debugger, StopReason, Watchpoint},
+    error::Chip8Error,
+    machine::Machine,
+};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+
+/// Target description for the register file, gdb asks for it with qXfer
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.cpu">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// V0-VF, I, PC, SP, DT and ST
+pub const REGISTER_COUNT: usize = 21;
+
+/// Biggest packet we take, and so the most memory one `m` reads. Told to gdb in hex.
+const PACKET_SIZE: usize = 0x1000;
+
+/// SIGINT, SIGILL, SIGTRAP and SIGSEGV, what gdb expects stops to look like
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// The protocol side: bytes from gdb in, bytes for gdb out.
+#[derive(Debug, Default)]
+pub struct GdbStub {
+    /// Received bytes that don't make a whole packet yet
+    input: Vec<u8>,
+    /// Last packet sent, for when gdb asks for it again with `-`
+    last_sent: Vec<u8>,
+    /// QStartNoAckMode, TCP doesn't lose bytes
+    no_ack: bool,
+    /// gdb sent a continue and is waiting to hear where the machine stopped
+    running: bool,
+    /// gdb sent ^C while it was running
+    interrupted: bool,
+    /// gdb detached or killed, the connection can go
+    detached: bool,
+}
+
+impl GdbStub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles whatever came in from gdb, returns what to send back.
+    pub fn receive(
+        &mut self,
+        bytes: &[u8],
+        machine: &mut Machine,
+        debugger: &mut Debugger,
+    ) -> Vec<u8> {
+        self.input.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while let Some(&first) = self.input.first() {
+            match first {
+                b'$' => {
+                    // a body can't be longer than PACKET_SIZE, so that's as far as # can be
+                    let limit = self.input.len().min(PACKET_SIZE + 2);
+                    let Some(hash) = self.input[..limit].iter().position(|&b| b == b'#') else {
+                        if self.input.len() > PACKET_SIZE + 3 {
+                            // too big to be a packet, drop it up to whatever starts next
+                            let next = self.input[1..].iter().position(|&b| b == b'$');
+                            self.input
+                                .drain(..next.map_or(self.input.len(), |next| next + 1));
+                            if !self.no_ack {
+                                out.push(b'-');
+                            }
+                            continue;
+                        }
+                        break;
+                    };
+                    if self.input.len() < hash + 3 {
+                        break;
+                    }
+                    let packet: Vec<u8> = self.input.drain(..hash + 3).collect();
+                    let body = &packet[1..hash];
+                    let checksum = std::str::from_utf8(&packet[hash + 1..])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                    if checksum != Some(checksum_of(body)) {
+                        if !self.no_ack {
+                            out.push(b'-');
+                        }
+                        continue;
+                    }
+                    if !self.no_ack {
+                        out.push(b'+');
+                    }
+                    let body = String::from_utf8_lossy(body).into_owned();
+                    if let Some(reply) = self.handle(&body, machine, debugger) {
+                        out.extend(self.packet(&reply));
+                    }
+                }
+                b'-' => {
+                    self.input.remove(0);
+                    out.extend_from_slice(&self.last_sent);
+                }
+                // ^C
+                0x03 => {
+                    self.input.remove(0);
+                    if self.running {
+                        self.interrupted = true;
+                        debugger.pause();
+                    }
+                }
+                // acks, and anything else between packets
+                _ => {
+                    self.input.remove(0);
+                }
+            }
+        }
+        out
+    }
+
+    /// The stop reply gdb is owed once a continue has stopped, if it has.
+    pub fn poll_stop(&mut self, debugger: &Debugger) -> Vec<u8> {
+        if !self.running || !debugger.is_paused() {
+            return Vec::new();
+        }
+        self.running = false;
+        let reply = match std::mem::take(&mut self.interrupted) {
+            true => format!("S{:02x}", SIGINT),
+            false => stop_reply(debugger.last_stop(), debugger),
+        };
+        self.packet(&reply)
+    }
+
+    /// Whether gdb said goodbye.
+    pub fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    /// The reply to one packet, None for the ones that don't get one (yet).
+    fn handle(
+        &mut self,
+        packet: &str,
+        machine: &mut Machine,
+        debugger: &mut Debugger,
+    ) -> Option<String> {
+        let Some(command) = packet.chars().next() else {
+            return Some(String::new());
+        };
+        let args = &packet[command.len_utf8()..];
+        let reply = match command {
+            '?' => stop_reply(debugger.last_stop(), debugger),
+            'g' => (0..REGISTER_COUNT)
+                .flat_map(|n| register(machine, n).unwrap_or_default())
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+            'G' => {
+                let result = decode_hex(args).and_then(|bytes| set_registers(machine, &bytes));
+                debugger.machine_changed();
+                ok_or_error(result)
+            }
+            'p' => match usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|n| register(machine, n))
+            {
+                Some(bytes) => encode_hex(&bytes),
+                None => error(),
+            },
+            'P' => {
+                let result = args.split_once('=').and_then(|(n, value)| {
+                    let n = usize::from_str_radix(n, 16).ok()?;
+                    set_register(machine, n, &decode_hex(value)?)
+                });
+                debugger.machine_changed();
+                ok_or_error(result)
+            }
+            'm' => match parse_pair(args) {
+                Some((addr, len)) => match machine.memory.slice(addr, len.min(PACKET_SIZE / 2)) {
+                    Ok(bytes) => encode_hex(bytes),
+                    Err(_) => error(),
+                },
+                None => error(),
+            },
+            'M' => {
+                let result = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_pair(range)?;
+                    let data = decode_hex(data).filter(|data| data.len() == len)?;
+                    let ram = machine.memory.slice_mut(addr, len).ok()?;
+                    ram.copy_from_slice(&data);
+                    Some(())
+                });
+                debugger.machine_changed();
+                ok_or_error(result)
+            }
+            'Z' | 'z' => self.breakpoint(command == 'Z', args, debugger),
+            's' => {
+                resume_at(machine, args);
+                let reason = debugger.step(machine);
+                stop_reply(Some(reason), debugger)
+            }
+            'c' => {
+                resume_at(machine, args);
+                debugger.resume();
+                self.running = true;
+                return None;
+            }
+            'D' => {
+                debugger.resume();
+                self.detached = true;
+                "OK".to_string()
+            }
+            'k' => {
+                // the emulator keeps going, only the session ends
+                debugger.resume();
+                self.detached = true;
+                return None;
+            }
+            'H' | 'T' => "OK".to_string(),
+            'q' => query(args),
+            'Q' if packet == "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            'v' if packet.starts_with("vKill") => {
+                debugger.resume();
+                self.detached = true;
+                "OK".to_string()
+            }
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    /// Z/z: 0 and 1 are breakpoints, 2 and 3 write and read watchpoints.
+    fn breakpoint(&mut self, insert: bool, args: &str, debugger: &mut Debugger) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return error();
+        };
+        let (Ok(addr), Ok(len)) = (u16::from_str_radix(addr, 16), u16::from_str_radix(len, 16))
+        else {
+            return error();
+        };
+        let end = addr.saturating_add(len.max(1) - 1);
+        let watchpoint = match kind {
+            "0" | "1" => {
+                if debugger.has_breakpoint(addr) != insert {
+                    debugger.toggle_breakpoint(addr);
+                }
+                return "OK".to_string();
+            }
+            "2" => Watchpoint::Write { start: addr, end },
+            "3" => Watchpoint::Read { start: addr, end },
+            _ => return String::new(),
+        };
+        let index = debugger.watchpoints().iter().position(|w| *w == watchpoint);
+        match (insert, index) {
+            (true, None) => debugger.add_watchpoint(watchpoint),
+            (false, Some(index)) => {
+                debugger.remove_watchpoint(index);
+            }
+            _ => {}
+        }
+        "OK".to_string()
+    }
+
+    /// `$<data>#<checksum>`, remembered in case gdb wants it again.
+    fn packet(&mut self, data: &str) -> Vec<u8> {
+        let mut body = Vec::with_capacity(data.len());
+        for byte in data.bytes() {
+            if matches!(byte, b'#' | b'$' | b'}' | b'*') {
+                body.extend_from_slice(&[b'}', byte ^ 0x20]);
+            } else {
+                body.push(byte);
+            }
+        }
+        let mut packet = vec![b'$'];
+        packet.extend_from_slice(&body);
+        packet.extend_from_slice(format!("#{:02x}", checksum_of(&body)).as_bytes());
+        self.last_sent = packet.clone();
+        packet
+    }
+}
+
+/// Puts the stub on a TCP socket. Never blocks, the frontend calls `poll` once a frame and
+/// carries on running the machine in between.
+#[derive(Debug)]
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    stub: GdbStub,
+}
+
+impl GdbServer {
+    /// Listens on `addr`, keep it local, e.g. `127.0.0.1:1234`.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: None,
+            stub: GdbStub::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Takes a new connection, answers whatever gdb sent and tells it when the machine stops.
+    /// gdb expects a halted target, so a new connection pauses the machine. Once it goes away
+    /// the machine runs on.
+    pub fn poll(&mut self, machine: &mut Machine, debugger: &mut Debugger) {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        warn!(target: "gdb", "Couldn't take the connection from {}: {}", peer, e);
+                        return;
+                    }
+                    let _ = stream.set_nodelay(true);
+                    info!(target: "gdb", "gdb connected from {}", peer);
+                    debugger.pause();
+                    self.stub = GdbStub::new();
+                    self.client = Some(stream);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    warn!(target: "gdb", "Couldn't accept a connection: {}", e);
+                    return;
+                }
+            }
+        }
+        let Some(client) = &mut self.client else {
+            return;
+        };
+
+        let mut out = Vec::new();
+        let mut buffer = [0; 4096];
+        let mut closed = false;
+        loop {
+            match client.read(&mut buffer) {
+                Ok(0) => {
+                    closed = true;
+                    break;
+                }
+                Ok(n) => out.extend(self.stub.receive(&buffer[..n], machine, debugger)),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    warn!(target: "gdb", "gdb connection failed: {}", e);
+                    closed = true;
+                    break;
+                }
+            }
+        }
+        out.extend(self.stub.poll_stop(debugger));
+        if let Err(e) = send(client, &out) {
+            warn!(target: "gdb", "gdb connection failed: {}", e);
+            closed = true;
+        }
+        if closed || self.stub.is_detached() {
+            info!(target: "gdb", "gdb disconnected");
+            debugger.resume();
+            self.client = None;
+        }
+    }
+}
+
+/// write_all for a non-blocking socket, replies are small enough to just wait for room.
+fn send(client: &mut TcpStream, mut bytes: &[u8]) -> io::Result<()> {
+    while !bytes.is_empty() {
+        match client.write(bytes) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => bytes = &bytes[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::yield_now(),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// One register's bytes, little endian.
+fn register(machine: &Machine, n: usize) -> Option<Vec<u8>> {
+    let cpu = &machine.cpu;
+    let bytes = match n {
+        0..=15 => vec![cpu.registers[n]],
+        16 => cpu.index_register.to_le_bytes().to_vec(),
+        17 => cpu.program_counter.to_le_bytes().to_vec(),
+        18 => vec![cpu.stack_pointer as u8],
+        19 => vec![machine.timers.delay_timer],
+        20 => vec![machine.timers.sound_timer],
+        _ => return None,
+    };
+    Some(bytes)
+}
+
+fn set_register(machine: &mut Machine, n: usize, bytes: &[u8]) -> Option<()> {
+    if bytes.len() != register(machine, n)?.len() {
+        return None;
+    }
+    let word = || u16::from_le_bytes([bytes[0], bytes[1]]);
+    let cpu = &mut machine.cpu;
+    match n {
+        0..=15 => cpu.registers[n] = bytes[0],
+        16 => cpu.index_register = word(),
+        17 => cpu.program_counter = word(),
+        18 if (bytes[0] as usize) <= cpu.stack.len() => cpu.stack_pointer = bytes[0] as usize,
+        19 => machine.timers.delay_timer = bytes[0],
+        20 => machine.timers.sound_timer = bytes[0],
+        _ => return None,
+    }
+    Some(())
+}
+
+/// All of them, in `g` order. Nothing changes unless they all fit.
+fn set_registers(machine: &mut Machine, bytes: &[u8]) -> Option<()> {
+    let mut values = Vec::with_capacity(REGISTER_COUNT);
+    let mut rest = bytes;
+    for n in 0..REGISTER_COUNT {
+        let (value, more) = rest.split_at_checked(register(machine, n)?.len())?;
+        values.push(value);
+        rest = more;
+    }
+    if !rest.is_empty() || values[18][0] as usize > machine.cpu.stack.len() {
+        return None;
+    }
+    for (n, value) in values.into_iter().enumerate() {
+        set_register(machine, n, value)?;
+    }
+    Some(())
+}
+
+/// `s` and `c` can say where to carry on from.
+fn resume_at(machine: &mut Machine, args: &str) {
+    if let Ok(addr) = u16::from_str_radix(args, 16) {
+        machine.cpu.program_counter = addr;
+    }
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return format!(
+            "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+;hwbreak+",
+            PACKET_SIZE
+        );
+    }
+    if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        let Some((offset, len)) = parse_pair(range) else {
+            return error();
+        };
+        let xml = TARGET_XML.as_bytes();
+        let start = offset.min(xml.len());
+        let end = start.saturating_add(len).min(xml.len());
+        let more = if end < xml.len() { 'm' } else { 'l' };
+        return format!("{}{}", more, String::from_utf8_lossy(&xml[start..end]));
+    }
+    match args {
+        "Attached" => "1",
+        "C" => "QC1",
+        "fThreadInfo" => "m1",
+        "sThreadInfo" => "l",
+        "Symbol::" => "OK",
+        _ => "",
+    }
+    .to_string()
+}
+
+/// What gdb gets told when the machine stops, `reason` being the debugger's last stop.
+fn stop_reply(reason: Option<StopReason>, debugger: &Debugger) -> String {
+    match reason {
+        Some(StopReason::Breakpoint(_)) => format!("T{:02x}swbreak:;", SIGTRAP),
+        Some(StopReason::Watchpoint { index, .. }) => match debugger.watchpoints().get(index) {
+            Some(Watchpoint::Write { start, .. }) => format!("T{:02x}watch:{:x};", SIGTRAP, start),
+            Some(Watchpoint::Read { start, .. }) => format!("T{:02x}rwatch:{:x};", SIGTRAP, start),
+            _ => format!("S{:02x}", SIGTRAP),
+        },
+        Some(StopReason::Fault(fault)) => match fault.error {
+            Chip8Error::UnknownOpcode { .. } => format!("S{:02x}", SIGILL),
+            _ => format!("S{:02x}", SIGSEGV),
+        },
+        _ => format!("S{:02x}", SIGTRAP),
+    }
+}
+
+/// `addr,len` in hex
+fn parse_pair(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((
+        usize::from_str_radix(addr, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+fn checksum_of(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    match result {
+        Some(()) => "OK".to_string(),
+        None => error(),
+    }
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+#[cfg(test)]
+mod gdbtests {
+    use super::{checksum_of, GdbStub, TARGET_XML};
+    use crate::emu::{ClockSpeed, Debugger, Machine};
+
+    /// 0x200: 6005 -> V0 = 5, 0x202: 7001 -> V0 += 1, 0x204: 1202 -> loop
+    fn setup() -> (GdbStub, Machine, Debugger) {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(10));
+        machine
+            .load_rom(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02])
+            .unwrap();
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        (GdbStub::new(), machine, debugger)
+    }
+
+    fn packet(body: &str) -> Vec<u8> {
+        format!("${}#{:02x}", body, checksum_of(body.as_bytes())).into_bytes()
+    }
+
+    /// Sends one packet and returns the reply's body, checking the ack and checksum.
+    fn ask(
+        stub: &mut GdbStub,
+        machine: &mut Machine,
+        debugger: &mut Debugger,
+        body: &str,
+    ) -> String {
+        let out = stub.receive(&packet(body), machine, debugger);
+        let out = String::from_utf8(out).unwrap();
+        let reply = out.strip_prefix("+$").expect("acked reply");
+        let (body, checksum) = reply.rsplit_once('#').unwrap();
+        assert_eq!(
+            u8::from_str_radix(checksum, 16).unwrap(),
+            checksum_of(body.as_bytes())
+        );
+        body.to_string()
+    }
+
+    #[test]
+    fn test_registers() {
+        let (mut stub, mut machine, mut debugger) = setup();
+        machine.cpu.registers[0xF] = 0xAB;
+        machine.timers.delay_timer = 9;
+        let all = ask(&mut stub, &mut machine, &mut debugger, "g");
+        assert_eq!(all.len(), 23 * 2);
+        assert!(all.starts_with("00"));
+        assert_eq!(&all[30..32], "ab");
+        // I, then the pc little endian, then SP, DT and ST
+        assert_eq!(&all[32..], "00000002000900");
+
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "P10=0003"),
+            "OK"
+        );
+        assert_eq!(machine.cpu.index_register, 0x300);
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, "p10"), "0003");
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, "p15"), "E01");
+        // wrong sizes and a stack pointer past the stack don't go in
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "P0=0102"),
+            "E01"
+        );
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, "P12=11"), "E01");
+
+        // V0-VF all 1, I = 0x320, pc = 0x204, SP 2, DT 3, ST 4
+        let written = format!("G{}20030402020304", "01".repeat(16));
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, &written), "OK");
+        assert_eq!(machine.cpu.registers, [1; 16]);
+        assert_eq!(machine.cpu.index_register, 0x320);
+        assert_eq!(machine.cpu.program_counter, 0x204);
+        assert_eq!(machine.cpu.stack_pointer, 2);
+        assert_eq!(machine.timers.sound_timer, 4);
+        // all or nothing
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, "G0000"), "E01");
+        assert_eq!(machine.cpu.registers, [1; 16]);
+    }
+
+    #[test]
+    fn test_memory() {
+        let (mut stub, mut machine, mut debugger) = setup();
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "m200,4"),
+            "60057001"
+        );
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "M202,2:7003"),
+            "OK"
+        );
+        assert_eq!(machine.memory.ram[0x203], 0x03);
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, "mfff,2"), "E01");
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "M202,2:70"),
+            "E01"
+        );
+    }
+
+    #[test]
+    fn test_step_and_breakpoints() {
+        let (mut stub, mut machine, mut debugger) = setup();
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, "?"), "S05");
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, "s"), "S05");
+        assert_eq!(machine.cpu.program_counter, 0x202);
+
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "Z0,204,2"),
+            "OK"
+        );
+        assert!(debugger.has_breakpoint(0x204));
+        // continuing doesn't answer until the machine stops
+        let out = stub.receive(&packet("c"), &mut machine, &mut debugger);
+        assert_eq!(out, b"+");
+        assert!(stub.poll_stop(&debugger).is_empty());
+        debugger.run_frame(&mut machine);
+        let stop = String::from_utf8(stub.poll_stop(&debugger)).unwrap();
+        assert!(stop.starts_with("$T05swbreak:;#"));
+        assert_eq!(machine.cpu.program_counter, 0x204);
+
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "z0,204,2"),
+            "OK"
+        );
+        assert!(!debugger.has_breakpoint(0x204));
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "Z2,300,2"),
+            "OK"
+        );
+        assert_eq!(debugger.watchpoints().len(), 1);
+        assert_eq!(
+            ask(&mut stub, &mut machine, &mut debugger, "z2,300,2"),
+            "OK"
+        );
+        assert!(debugger.watchpoints().is_empty());
+        assert_eq!(ask(&mut stub, &mut machine, &mut debugger, "Z4,300,2"), "");
+    }
+
+    #[test]
+    fn test_framing() {
+        let (mut stub, mut machine, mut debugger) = setup();
+        // a bad checksum gets a nak, a nak gets the last packet again
+        assert_eq!(stub.receive(b"$g#00", &mut machine, &mut debugger), b"-");
+        let reply = stub.receive(&packet("qAttached"), &mut machine, &mut debugger);
+        assert_eq!(reply, b"+$1#31");
+        assert_eq!(stub.receive(b"-", &mut machine, &mut debugger), b"$1#31");
+        // a $ that never gets its # isn't kept around forever
+        let endless = vec![b'x'; super::PACKET_SIZE];
+        assert!(stub.receive(b"$", &mut machine, &mut debugger).is_empty());
+        assert!(stub
+            .receive(&endless, &mut machine, &mut debugger)
+            .is_empty());
+        assert_eq!(stub.receive(&endless, &mut machine, &mut debugger), b"-");
+        assert!(stub.input.is_empty());
+        let reply = stub.receive(&packet("qAttached"), &mut machine, &mut debugger);
+        assert_eq!(reply, b"+$1#31");
+        // packets split across reads, and no acks once asked not to
+        let start = packet("QStartNoAckMode");
+        let (a, b) = start.split_at(5);
+        assert!(stub.receive(a, &mut machine, &mut debugger).is_empty());
+        assert_eq!(stub.receive(b, &mut machine, &mut debugger), b"+$OK#9a");
+        assert_eq!(
+            stub.receive(&packet("vMustReplyEmpty"), &mut machine, &mut debugger),
+            b"$#00"
+        );
+    }
+
+    #[test]
+    fn test_target_xml() {
+        let (mut stub, mut machine, mut debugger) = setup();
+        let supported = ask(
+            &mut stub,
+            &mut machine,
+            &mut debugger,
+            "qSupported:swbreak+",
+        );
+        assert!(supported.contains("qXfer:features:read+"));
+        let first = ask(
+            &mut stub,
+            &mut machine,
+            &mut debugger,
+            "qXfer:features:read:target.xml:0,10",
+        );
+        assert_eq!(first, format!("m{}", &TARGET_XML[..16]));
+        let whole = ask(
+            &mut stub,
+            &mut machine,
+            &mut debugger,
+            "qXfer:features:read:target.xml:0,fff",
+        );
+        assert_eq!(whole, format!("l{}", TARGET_XML));
+        assert_eq!(TARGET_XML.matches("<reg ").count(), super::REGISTER_COUNT);
+    }
+}
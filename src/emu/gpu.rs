@@ -1,58 +1,164 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+use serde::{Deserialize, Serialize};
 use std::io;
 use std::io::Write;
 
-// I am thinking of making this a layer between the App and the Ratatui
 // Contains the graphics processing.
-// use crossterm::event::Event;
-use ratatui::{
-    buffer::Buffer,
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
-    style::{
-        Color,
-        // Style,
-        Stylize,
-    },
-    symbols::{border, Marker},
-    text::{
-        Line,
-        // Span,
-        Text,
-    },
-    widgets::{
-        // block::{Position, Title},
-        canvas::{Canvas, Rectangle},
-        Block,
-        Paragraph,
-        Widget,
-    },
-    // DefaultTerminal,
-};
-// use std::sync::mpsc;
-// use tui_logger::LevelFilter;
-// use tui_logger::TuiWidgetState;
-
-// use tui_logger::{ExtLogRecord, LogFormatter, TuiWidgetEvent};
-//
-// use crate::emu::KeyCode;
+// The display is just a grid of pixels here, drawing it is up to the frontend
+// (see tui/screen.rs for the ratatui widget).
 
+/// Low resolution, the only mode plain CHIP-8 has
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+/// SUPER-CHIP high resolution, switched on with 00FF
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
 
-#[derive(Debug)]
+/// XO-CHIP draws on two bitplanes, FN01 picks which ones the drawing opcodes touch
+pub const PLANE_COUNT: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Gpu {
-    pub screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    /// Row-major, `width() * height()` pixels. Resized when the resolution changes.
+    /// This is the first bitplane, the only one plain CHIP-8 and SCHIP programs use.
+    pub screen: Vec<bool>,
+    /// XO-CHIP's second bitplane, same layout as `screen`
+    pub second_plane: Vec<bool>,
+    hires: bool,
+    /// Bitmask of the planes that are drawn to, cleared and scrolled. 1 unless set by FN01
+    selected_planes: u8,
 }
 
 //--------------------------------------------------------------
 // Gpu
 // Here be graphics processing
 //--------------------------------------------------------------
+impl Default for Gpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Gpu {
     pub fn new() -> Self {
         Self {
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            screen: vec![false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            second_plane: vec![false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            hires: false,
+            selected_planes: 1,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            SCREEN_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switches between 64x32 and 128x64. Like most SCHIP emulators this clears the screen,
+    /// both planes regardless of which are selected.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = vec![false; self.width() * self.height()];
+        self.second_plane = vec![false; self.width() * self.height()];
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    /// 0 (none), 1 (first), 2 (second) or 3 (both)
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & 0b11;
+    }
+
+    /// Plane 0 is `screen`, plane 1 is `second_plane`.
+    pub fn plane_mut(&mut self, plane: usize) -> &mut [bool] {
+        match plane {
+            0 => &mut self.screen,
+            _ => &mut self.second_plane,
+        }
+    }
+
+    /// Indices of the selected planes, in drawing order.
+    pub fn planes(&self) -> impl Iterator<Item = usize> {
+        let mask = self.selected_planes;
+        (0..PLANE_COUNT).filter(move |plane| mask & (1 << plane) != 0)
+    }
+
+    /// Colour of a pixel, 0-3: bit 0 from the first plane, bit 1 from the second.
+    pub fn colour(&self, index: usize) -> u8 {
+        self.screen[index] as u8 | (self.second_plane[index] as u8) << 1
+    }
+
+    /// Clears the selected planes.
+    pub fn clear(&mut self) {
+        self.for_each_plane(|plane| plane.fill(false));
+    }
+
+    /// Moves every row of the selected planes down by `rows`, the top fills with blank pixels.
+    pub fn scroll_down(&mut self, rows: usize) {
+        let shift = rows * self.width();
+        self.for_each_plane(|plane| {
+            let shift = shift.min(plane.len());
+            plane.rotate_right(shift);
+            plane[..shift].fill(false);
+        });
+    }
+
+    /// Moves every row up by `rows`, the bottom fills with blank pixels. (XO-CHIP 00DN)
+    pub fn scroll_up(&mut self, rows: usize) {
+        let shift = rows * self.width();
+        self.for_each_plane(|plane| {
+            let shift = shift.min(plane.len());
+            plane.rotate_left(shift);
+            let len = plane.len();
+            plane[len - shift..].fill(false);
+        });
+    }
+
+    /// Moves every row right by `pixels`, the left edge fills with blank pixels.
+    pub fn scroll_right(&mut self, pixels: usize) {
+        let width = self.width();
+        let pixels = pixels.min(width);
+        self.for_each_plane(|plane| {
+            for row in plane.chunks_mut(width) {
+                row.rotate_right(pixels);
+                row[..pixels].fill(false);
+            }
+        });
+    }
+
+    /// Moves every row left by `pixels`, the right edge fills with blank pixels.
+    pub fn scroll_left(&mut self, pixels: usize) {
+        let width = self.width();
+        let pixels = pixels.min(width);
+        self.for_each_plane(|plane| {
+            for row in plane.chunks_mut(width) {
+                row.rotate_left(pixels);
+                row[width - pixels..].fill(false);
+            }
+        });
+    }
+
+    fn for_each_plane(&mut self, mut f: impl FnMut(&mut [bool])) {
+        for plane in self.planes().collect::<Vec<_>>() {
+            f(self.plane_mut(plane));
         }
     }
 
@@ -65,7 +171,7 @@ impl Gpu {
                 false => ' ',
             };
             write!(handle, "{}", character).expect("Failed to write to stdout");
-            if (i + 1) % SCREEN_WIDTH == 0 {
+            if (i + 1) % self.width() == 0 {
                 writeln!(handle).expect("Failed to write newline");
             }
         }
@@ -73,7 +179,11 @@ impl Gpu {
     }
 
     pub fn debug_screen_print_string(&self) {
-        let mut screen_string = String::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT + SCREEN_HEIGHT);
+        // this runs every tick, don't build a whole screen of text for nobody
+        if !log_enabled!(target: "gpu", log::Level::Info) {
+            return;
+        }
+        let mut screen_string = String::with_capacity(self.screen.len() + self.height());
 
         // Iterate over the screen array with the index `i`
         for (i, &pixel) in self.screen.iter().enumerate() {
@@ -85,7 +195,7 @@ impl Gpu {
             screen_string.push(character);
 
             // After every row, push a newline character
-            if (i + 1) % SCREEN_WIDTH == 0 {
+            if (i + 1) % self.width() == 0 {
                 screen_string.push('\n');
             }
         }
@@ -93,98 +203,46 @@ impl Gpu {
         //println!("{}", screen_string);
         info!(target: "gpu", "{}", screen_string);
     }
+} // End impl Gpu
 
-    //pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
-    //    while !self.exit {
-    //        // Render
-    //        terminal.draw(|frame| self.render_frame(frame))?;
-    //
-    //        // Handle Input
-    //        self.handle_events().wrap_err("handle events failed")?;
-    //    }
-    //    Ok(())
-    //}
-
-    //fn render_frame(&self, frame: &mut Frame) {
-    //    frame.render_widget(self, frame.area());
-    //}
-
-    //pub fn handle_events(&mut self) -> Result<u8> {
-    //    //color_eyre::install()?; // error hooks
-    //    match event::read()? {
-    //        Event::Key(key_event) if key_event.kind == KeyEventKind::Press => self
-    //            .handle_key_event(key_event)
-    //            .wrap_err_with(|| format!("handling key event failed:\n{key_event:#?}")),
-    //        _ => Ok(255),
-    //    }
-    //}
-
-    fn content(&self) -> impl Widget + '_ {
-        let screen_ref = &self.screen;
-        //let mut screen = self.screen.clone();
-        //screen[1000..1099].copy_from_slice(&[true; 99]);
-
-        let canvas = Canvas::default()
-            .marker(Marker::Block)
-            .block(Block::bordered().title("Canvas"))
-            .x_bounds([0.0, SCREEN_WIDTH as f64])
-            .y_bounds([0.0, SCREEN_HEIGHT as f64])
-            .paint(move |ctx| {
-                for y in 0..SCREEN_HEIGHT {
-                    for x in 0..SCREEN_WIDTH {
-                        let index = y * SCREEN_WIDTH + x;
-                        if screen_ref[index] {
-                            let flipped_y = (SCREEN_HEIGHT - 1 - y) as f64;
-                            ctx.draw(&Rectangle {
-                                x: x as f64,
-                                y: flipped_y,
-                                width: 0.8,
-                                height: 0.4,
-                                color: Color::Cyan,
-                            })
-                        }
-                    }
-                }
-            });
-        canvas
+#[cfg(test)]
+mod gputests {
+    use super::{Gpu, HIRES_HEIGHT, HIRES_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[test]
+    fn test_set_hires_resizes_and_clears() {
+        let mut gpu = Gpu::new();
+        gpu.screen[0] = true;
+        gpu.set_hires(true);
+        assert_eq!((gpu.width(), gpu.height()), (HIRES_WIDTH, HIRES_HEIGHT));
+        assert_eq!(gpu.screen.len(), HIRES_WIDTH * HIRES_HEIGHT);
+        assert!(gpu.screen.iter().all(|&pixel| !pixel));
+        gpu.set_hires(false);
+        assert_eq!(gpu.screen.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+    }
+
+    #[test]
+    fn test_scroll_down() {
+        let mut gpu = Gpu::new();
+        gpu.screen[3] = true; // (3, 0)
+        gpu.scroll_down(2);
+        assert!(!gpu.screen[3]);
+        assert!(gpu.screen[2 * SCREEN_WIDTH + 3]);
+        // off the bottom is gone for good
+        gpu.scroll_down(SCREEN_HEIGHT);
+        assert!(gpu.screen.iter().all(|&pixel| !pixel));
     }
-} // End impl Gpu
 
-/// I like to say Ratatui
-impl Widget for &Gpu {
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        let title = vec![" GPU".bold(), "<3".red().bold(), " Galus ".bold()];
-
-        let instructions = vec![
-            " Left ".into(),
-            "<H> ".blue().bold(),
-            " Right ".into(),
-            "<L> ".blue().bold(),
-            " Quit ".into(),
-            "<Q> ".blue().bold(),
-        ];
-
-        let block = Block::bordered()
-            .title_top(title)
-            .title_bottom(instructions)
-            .border_set(border::THICK);
-
-        let counter_text = Text::from(vec![Line::from(vec![
-            " Value: ".into(),
-            " soonTm".to_string().yellow(),
-            //self.progress_counter.to_string().yellow(),
-            " ".into(),
-        ])]);
-
-        let paragraph = Paragraph::new(counter_text).alignment(Alignment::Center);
-
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Length(1), Constraint::Min(0)])
-            .split(block.inner(area));
-
-        block.render(area, buf);
-        paragraph.render(chunks[0], buf);
-        self.content().render(chunks[1], buf);
+    #[test]
+    fn test_scroll_left_right() {
+        let mut gpu = Gpu::new();
+        gpu.screen[SCREEN_WIDTH + 1] = true; // (1, 1)
+        gpu.scroll_right(4);
+        assert!(gpu.screen[SCREEN_WIDTH + 5]);
+        gpu.scroll_left(4);
+        assert!(gpu.screen[SCREEN_WIDTH + 1]);
+        // pixels pushed past the left edge don't wrap onto the previous row
+        gpu.scroll_left(4);
+        assert!(gpu.screen.iter().all(|&pixel| !pixel));
     }
 }
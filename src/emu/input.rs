@@ -1,7 +1,102 @@
-#[derive(Debug)]
-pub struct Keypad {}
+// Contains the 16-key hexadecimal keypad.
+//
+// Original COSMAC VIP keypad       Default keyboard mapping
+// +---+---+---+---+                +---+---+---+---+
+// | 1 | 2 | 3 | C |                | 1 | 2 | 3 | 4 |
+// +---+---+---+---+                +---+---+---+---+
+// | 4 | 5 | 6 | D |                | Q | W | E | R |
+// +---+---+---+---+                +---+---+---+---+
+// | 7 | 8 | 9 | E |                | A | S | D | F |
+// +---+---+---+---+                +---+---+---+---+
+// | A | 0 | B | F |                | Z | X | C | V |
+// +---+---+---+---+                +---+---+---+---+
+
+use serde::{Deserialize, Serialize};
+
+pub const KEY_COUNT: usize = 16;
+
+/// Where FX0A is in its press-then-release handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum KeyWait {
+    /// FX0A is not executing.
+    Idle,
+    /// FX0A is executing and no key has gone down yet.
+    Listening,
+    /// This key went down while listening, FX0A completes once it is released.
+    Held(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keypad {
+    /// Pressed (true) / released (false) state of keys 0x0-0xF
+    keys: [bool; KEY_COUNT],
+    wait: KeyWait,
+}
+
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Keypad {
-    pub(crate) fn new() -> Self {
-        Self {}
+    pub fn new() -> Self {
+        Self {
+            keys: [false; KEY_COUNT],
+            wait: KeyWait::Idle,
+        }
+    }
+
+    /// Marks a key as held down.
+    pub fn press(&mut self, key: u8) {
+        let key = key & 0xF;
+        self.keys[key as usize] = true;
+        if self.wait == KeyWait::Listening {
+            self.wait = KeyWait::Held(key);
+        }
+    }
+
+    /// Marks a key as let go.
+    pub fn release(&mut self, key: u8) {
+        self.keys[(key & 0xF) as usize] = false;
+    }
+
+    /// Releases every key, e.g. when the frontend loses focus.
+    pub fn release_all(&mut self) {
+        self.keys = [false; KEY_COUNT];
+    }
+
+    /// Returns true when the key is currently held down.
+    /// Only the lowest nibble is looked at, just like the VIP did with vX.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0xF) as usize]
+    }
+
+    /// Returns the lowest key that is currently held down, if any.
+    pub fn first_pressed(&self) -> Option<u8> {
+        self.keys.iter().position(|&down| down).map(|key| key as u8)
+    }
+
+    /// Polled by FX0A every time it executes.
+    ///
+    /// Returns `Some(key)` once a key has been pressed *and* released since the
+    /// first poll, `None` while FX0A should keep blocking. Keys that were already
+    /// held when FX0A started do not count, they have to go down again.
+    pub fn poll_key_release(&mut self) -> Option<u8> {
+        match self.wait {
+            KeyWait::Idle => {
+                self.wait = KeyWait::Listening;
+                None
+            }
+            KeyWait::Listening => None,
+            KeyWait::Held(key) => {
+                if self.is_pressed(key) {
+                    None
+                } else {
+                    self.wait = KeyWait::Idle;
+                    Some(key)
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,339 @@
+// Opcodes decoded once into something typed. The cpu executes these, the disassembler prints
+// them, and anything that wants to cache decoded code can keep them around.
+use super::iset::OpCode;
+
+/// One decoded instruction. X and Y are register numbers (0-F), `nn` is a byte immediate,
+/// `nnn` a 12 bit address and `n` the low nibble.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00CN (SUPER-CHIP)
+    ScrollDown(u8),
+    /// 00DN (XO-CHIP)
+    ScrollUp(u8),
+    /// 00FB (SUPER-CHIP)
+    ScrollRight,
+    /// 00FC (SUPER-CHIP)
+    ScrollLeft,
+    /// 00FD (SUPER-CHIP)
+    Exit,
+    /// 00FE (SUPER-CHIP)
+    LowRes,
+    /// 00FF (SUPER-CHIP)
+    HighRes,
+    /// 0NNN, any other 0 opcode
+    Sys(u16),
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SkipEqImm { x: u8, nn: u8 },
+    /// 4XNN
+    SkipNeImm { x: u8, nn: u8 },
+    /// 5XY0
+    SkipEqReg { x: u8, y: u8 },
+    /// 5XY2 (XO-CHIP)
+    SaveRange { x: u8, y: u8 },
+    /// 5XY3 (XO-CHIP)
+    LoadRange { x: u8, y: u8 },
+    /// 6XNN
+    LoadImm { x: u8, nn: u8 },
+    /// 7XNN
+    AddImm { x: u8, nn: u8 },
+    /// 8XY0
+    Move { x: u8, y: u8 },
+    /// 8XY1
+    Or { x: u8, y: u8 },
+    /// 8XY2
+    And { x: u8, y: u8 },
+    /// 8XY3
+    Xor { x: u8, y: u8 },
+    /// 8XY4
+    Add { x: u8, y: u8 },
+    /// 8XY5
+    Sub { x: u8, y: u8 },
+    /// 8XY6
+    ShiftRight { x: u8, y: u8 },
+    /// 8XY7
+    SubN { x: u8, y: u8 },
+    /// 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    /// 9XY0
+    SkipNeReg { x: u8, y: u8 },
+    /// ANNN
+    LoadI(u16),
+    /// BNNN, X is the top nibble of NNN
+    JumpOffset(u16),
+    /// CXNN
+    Random { x: u8, nn: u8 },
+    /// DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E
+    SkipKey { x: u8 },
+    /// EXA1
+    SkipNotKey { x: u8 },
+    /// F000 NNNN (XO-CHIP), the address is the next word and read when it runs
+    LoadILong,
+    /// FN01 (XO-CHIP)
+    Plane(u8),
+    /// F002 (XO-CHIP)
+    Audio,
+    /// FX07
+    LoadDelay { x: u8 },
+    /// FX0A
+    WaitKey { x: u8 },
+    /// FX15
+    SetDelay { x: u8 },
+    /// FX18
+    SetSound { x: u8 },
+    /// FX1E
+    AddI { x: u8 },
+    /// FX29
+    Font { x: u8 },
+    /// FX30 (SUPER-CHIP)
+    BigFont { x: u8 },
+    /// FX33
+    Bcd { x: u8 },
+    /// FX3A (XO-CHIP)
+    Pitch { x: u8 },
+    /// FX55
+    Store { x: u8 },
+    /// FX65
+    Load { x: u8 },
+    /// FX75 (SUPER-CHIP)
+    SaveFlags { x: u8 },
+    /// FX85 (SUPER-CHIP)
+    LoadFlags { x: u8 },
+    /// Anything else, running it is a fault
+    Unknown(u16),
+}
+
+impl Instruction {
+    /// The single place opcodes get taken apart.
+    pub fn decode(op: u16) -> Instruction {
+        use Instruction::*;
+        let x = ((op >> 8) & 0xF) as u8;
+        let y = ((op >> 4) & 0xF) as u8;
+        let n = (op & 0xF) as u8;
+        let nn = (op & 0xFF) as u8;
+        let nnn = op & 0xFFF;
+        match (op >> 12, x, y, n) {
+            (0x0, 0x0, 0xE, 0xE) => Ret,
+            (0x0, 0x0, 0xE, 0x0) => Cls,
+            (0x0, 0x0, 0xC, _) => ScrollDown(n),
+            (0x0, 0x0, 0xF, 0xB) => ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Exit,
+            (0x0, 0x0, 0xF, 0xE) => LowRes,
+            (0x0, 0x0, 0xF, 0xF) => HighRes,
+            (0x0, 0x0, 0xD, _) => ScrollUp(n),
+            (0x0, _, _, _) => Sys(nnn),
+            (0x1, _, _, _) => Jump(nnn),
+            (0x2, _, _, _) => Call(nnn),
+            (0x3, _, _, _) => SkipEqImm { x, nn },
+            (0x4, _, _, _) => SkipNeImm { x, nn },
+            (0x5, _, _, 0x0) => SkipEqReg { x, y },
+            (0x5, _, _, 0x2) => SaveRange { x, y },
+            (0x5, _, _, 0x3) => LoadRange { x, y },
+            (0x6, _, _, _) => LoadImm { x, nn },
+            (0x7, _, _, _) => AddImm { x, nn },
+            (0x8, _, _, 0x0) => Move { x, y },
+            (0x8, _, _, 0x1) => Or { x, y },
+            (0x8, _, _, 0x2) => And { x, y },
+            (0x8, _, _, 0x3) => Xor { x, y },
+            (0x8, _, _, 0x4) => Add { x, y },
+            (0x8, _, _, 0x5) => Sub { x, y },
+            (0x8, _, _, 0x6) => ShiftRight { x, y },
+            (0x8, _, _, 0x7) => SubN { x, y },
+            (0x8, _, _, 0xE) => ShiftLeft { x, y },
+            (0x9, _, _, 0x0) => SkipNeReg { x, y },
+            (0xA, _, _, _) => LoadI(nnn),
+            (0xB, _, _, _) => JumpOffset(nnn),
+            (0xC, _, _, _) => Random { x, nn },
+            (0xD, _, _, _) => Draw { x, y, n },
+            (0xE, _, 0x9, 0xE) => SkipKey { x },
+            (0xE, _, 0xA, 0x1) => SkipNotKey { x },
+            (0xF, 0x0, 0x0, 0x0) => LoadILong,
+            (0xF, _, 0x0, 0x1) => Plane(x),
+            (0xF, 0x0, 0x0, 0x2) => Audio,
+            (0xF, _, 0x0, 0x7) => LoadDelay { x },
+            (0xF, _, 0x0, 0xA) => WaitKey { x },
+            (0xF, _, 0x1, 0x5) => SetDelay { x },
+            (0xF, _, 0x1, 0x8) => SetSound { x },
+            (0xF, _, 0x1, 0xE) => AddI { x },
+            (0xF, _, 0x2, 0x9) => Font { x },
+            (0xF, _, 0x3, 0x0) => BigFont { x },
+            (0xF, _, 0x3, 0x3) => Bcd { x },
+            (0xF, _, 0x3, 0xA) => Pitch { x },
+            (0xF, _, 0x5, 0x5) => Store { x },
+            (0xF, _, 0x6, 0x5) => Load { x },
+            (0xF, _, 0x7, 0x5) => SaveFlags { x },
+            (0xF, _, 0x8, 0x5) => LoadFlags { x },
+            _ => Unknown(op),
+        }
+    }
+
+    /// `decode` for a machine that is or isn't in XO-CHIP mode. Outside it the XO-CHIP
+    /// opcodes stay `Unknown`, so running one faults.
+    pub fn decode_for(op: u16, xo_chip: bool) -> Instruction {
+        match Instruction::decode(op) {
+            instruction if instruction.is_xo_chip() && !xo_chip => Instruction::Unknown(op),
+            instruction => instruction,
+        }
+    }
+
+    /// Only exists on XO-CHIP
+    pub fn is_xo_chip(self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            ScrollUp(_)
+                | SaveRange { .. }
+                | LoadRange { .. }
+                | LoadILong
+                | Plane(_)
+                | Audio
+                | Pitch { .. }
+        )
+    }
+
+    /// Back to the opcode, `decode(op).encode() == op` for every op.
+    pub fn encode(self) -> u16 {
+        use Instruction::*;
+        let xy = |base: u16, x: u8, y: u8| base | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |base: u16, x: u8, nn: u8| base | (x as u16) << 8 | nn as u16;
+        let x_ = |base: u16, x: u8| base | (x as u16) << 8;
+        match self {
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollDown(n) => 0x00C0 | n as u16,
+            ScrollUp(n) => 0x00D0 | n as u16,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            LowRes => 0x00FE,
+            HighRes => 0x00FF,
+            Sys(nnn) => nnn,
+            Jump(nnn) => 0x1000 | nnn,
+            Call(nnn) => 0x2000 | nnn,
+            SkipEqImm { x, nn } => xnn(0x3000, x, nn),
+            SkipNeImm { x, nn } => xnn(0x4000, x, nn),
+            SkipEqReg { x, y } => xy(0x5000, x, y),
+            SaveRange { x, y } => xy(0x5002, x, y),
+            LoadRange { x, y } => xy(0x5003, x, y),
+            LoadImm { x, nn } => xnn(0x6000, x, nn),
+            AddImm { x, nn } => xnn(0x7000, x, nn),
+            Move { x, y } => xy(0x8000, x, y),
+            Or { x, y } => xy(0x8001, x, y),
+            And { x, y } => xy(0x8002, x, y),
+            Xor { x, y } => xy(0x8003, x, y),
+            Add { x, y } => xy(0x8004, x, y),
+            Sub { x, y } => xy(0x8005, x, y),
+            ShiftRight { x, y } => xy(0x8006, x, y),
+            SubN { x, y } => xy(0x8007, x, y),
+            ShiftLeft { x, y } => xy(0x800E, x, y),
+            SkipNeReg { x, y } => xy(0x9000, x, y),
+            LoadI(nnn) => 0xA000 | nnn,
+            JumpOffset(nnn) => 0xB000 | nnn,
+            Random { x, nn } => xnn(0xC000, x, nn),
+            Draw { x, y, n } => xy(0xD000, x, y) | n as u16,
+            SkipKey { x } => x_(0xE09E, x),
+            SkipNotKey { x } => x_(0xE0A1, x),
+            LoadILong => 0xF000,
+            Plane(n) => x_(0xF001, n),
+            Audio => 0xF002,
+            LoadDelay { x } => x_(0xF007, x),
+            WaitKey { x } => x_(0xF00A, x),
+            SetDelay { x } => x_(0xF015, x),
+            SetSound { x } => x_(0xF018, x),
+            AddI { x } => x_(0xF01E, x),
+            Font { x } => x_(0xF029, x),
+            BigFont { x } => x_(0xF030, x),
+            Bcd { x } => x_(0xF033, x),
+            Pitch { x } => x_(0xF03A, x),
+            Store { x } => x_(0xF055, x),
+            Load { x } => x_(0xF065, x),
+            SaveFlags { x } => x_(0xF075, x),
+            LoadFlags { x } => x_(0xF085, x),
+            Unknown(op) => op,
+        }
+    }
+
+    /// Where this sends the pc, when that's known without running it
+    pub fn target(self) -> Option<u16> {
+        match self {
+            Instruction::Jump(nnn) | Instruction::Call(nnn) | Instruction::JumpOffset(nnn) => {
+                Some(nnn)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<OpCode> for Instruction {
+    fn from(op: OpCode) -> Self {
+        Instruction::decode(op.0)
+    }
+}
+
+impl From<Instruction> for OpCode {
+    fn from(instruction: Instruction) -> Self {
+        OpCode(instruction.encode())
+    }
+}
+
+#[cfg(test)]
+mod instructiontests {
+    use super::Instruction;
+    use crate::emu::iset::OpCode;
+
+    #[test]
+    fn test_round_trip() {
+        for op in 0..=u16::MAX {
+            let instruction = Instruction::decode(op);
+            assert_eq!(instruction.encode(), op, "{:04X} -> {:?}", op, instruction);
+            assert_eq!(Instruction::decode(instruction.encode()), instruction);
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            Instruction::decode(0xD4A2),
+            Instruction::Draw { x: 4, y: 0xA, n: 2 }
+        );
+        assert_eq!(Instruction::decode(0xA234), Instruction::LoadI(0x234));
+        assert_eq!(
+            Instruction::decode(0x3A10),
+            Instruction::SkipEqImm { x: 0xA, nn: 0x10 }
+        );
+        assert_eq!(Instruction::decode(0x00E1), Instruction::Sys(0x0E1));
+        assert_eq!(Instruction::decode(0xF102), Instruction::Unknown(0xF102));
+        assert_eq!(Instruction::decode(0x8008), Instruction::Unknown(0x8008));
+        assert_eq!(Instruction::from(OpCode(0xF301)), Instruction::Plane(3));
+        assert_eq!(OpCode::from(Instruction::Store { x: 5 }), OpCode(0xF555));
+    }
+
+    #[test]
+    fn test_decode_for() {
+        for op in [0x00D2, 0x5122, 0x5123, 0xF000, 0xF201, 0xF002, 0xF03A] {
+            assert_eq!(Instruction::decode_for(op, false), Instruction::Unknown(op));
+            assert_eq!(Instruction::decode_for(op, true), Instruction::decode(op));
+        }
+        assert_eq!(
+            Instruction::decode_for(0xF075, false),
+            Instruction::SaveFlags { x: 0 }
+        );
+    }
+
+    #[test]
+    fn test_target() {
+        assert_eq!(Instruction::decode(0x1ABC).target(), Some(0xABC));
+        assert_eq!(Instruction::decode(0xB300).target(), Some(0x300));
+        assert_eq!(Instruction::decode(0xA300).target(), None);
+    }
+}
@@ -1,20 +1,24 @@
 use super::{
+    audio::{Audio, PATTERN_SIZE},
     cpu::Cpu,
-    gpu::{Gpu, SCREEN_HEIGHT, SCREEN_WIDTH},
-    mem::Memory,
+    error::Chip8Error,
+    gpu::Gpu,
+    input::Keypad,
+    mem::{Memory, BIG_FONT_START_ADDRESS, FONT_START_ADDRESS},
     timer::Timer,
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct OpCode(pub u16);
 
 pub trait Nibbles {
-    fn into_tuple(&self) -> (u8, u8, u8, u8);
+    fn into_tuple(self) -> (u8, u8, u8, u8);
     // fn into_vec(&self) -> Vec<u8>;
 }
 
 impl Nibbles for OpCode {
-    fn into_tuple(&self) -> (u8, u8, u8, u8) {
+    fn into_tuple(self) -> (u8, u8, u8, u8) {
         (
             ((0xF000 & self.0) >> 12) as u8,
             ((0x0F00 & self.0) >> 8) as u8,
@@ -34,134 +38,211 @@ impl Nibbles for OpCode {
     //}
 }
 
+/// Bits shared by several instructions, mostly the quirk-dependent ones (see `quirks.rs`).
+impl OpCode {
+    /// vF = 0 after the logic ops on the COSMAC VIP
+    fn reset_vf(cpu: &mut Cpu) {
+        if cpu.quirks.vf_reset {
+            cpu.registers[0xF] = 0;
+        }
+    }
+
+    /// Registers touched by 5XY2/5XY3, from X to Y in either direction
+    fn register_range(x: u8, y: u8) -> Vec<usize> {
+        let (x, y) = (x as usize, y as usize);
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+
+    /// The register 8XY6/8XYE shift
+    fn shift_source(cpu: &Cpu, x: u8, y: u8) -> u8 {
+        if cpu.quirks.shift_uses_vy {
+            cpu.registers[y as usize]
+        } else {
+            cpu.registers[x as usize]
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ExecutionResult {
     Jumped,
     Advanced,
     Skipped,
+    /// The instruction is blocking (e.g. FX0A waiting on the keypad), keep the pc where it is
+    /// so it executes again on the next tick.
+    Waiting,
+    /// The program asked to quit (00FD). The pc stays put and the cpu stops for good.
+    Exited,
 }
 
+/// Every instruction either tells the cpu where the pc goes next, or fails with a
+/// `Chip8Error` that `Cpu::tick` turns into a `Fault`.
+/// Operands come in already decoded, see `Instruction` and `Cpu::execute`.
 pub trait Chip8ISet {
-    /// Returns current opcodes 2nd nibble
-    fn get_x(cpu: &Cpu) -> u8;
-
-    /// Returns current opcodes 3rd nibble
-    fn get_y(cpu: &Cpu) -> u8;
-
     /// Clear the screen
-    fn _00e0(gpu: &mut Gpu) -> ExecutionResult;
+    fn _00e0(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error>;
 
     /// Return from a subroutine
-    fn _00ee(_emu: &mut Cpu) -> ExecutionResult;
+    fn _00ee(_emu: &mut Cpu) -> Result<ExecutionResult, Chip8Error>;
 
     /// Execute machine language subroutine at address NNN
-    fn _0nnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn _0nnn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error>;
+
+    /// SCD nibble (SUPER-CHIP)
+    /// Scroll the display down N pixels
+    fn _00cn(gpu: &mut Gpu, n: u8) -> Result<ExecutionResult, Chip8Error>;
+
+    /// SCR (SUPER-CHIP)
+    /// Scroll the display right 4 pixels
+    fn _00fb(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error>;
+
+    /// SCL (SUPER-CHIP)
+    /// Scroll the display left 4 pixels
+    fn _00fc(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error>;
+
+    /// EXIT (SUPER-CHIP)
+    /// Stop the interpreter
+    fn _00fd(cpu: &mut Cpu) -> Result<ExecutionResult, Chip8Error>;
+
+    /// LOW (SUPER-CHIP)
+    /// Switch to the 64x32 low resolution display
+    fn _00fe(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error>;
+
+    /// HIGH (SUPER-CHIP)
+    /// Switch to the 128x64 high resolution display
+    fn _00ff(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error>;
+
+    /// SCROLL-UP nibble (XO-CHIP)
+    /// Scroll the selected planes up N pixels
+    fn _00dn(gpu: &mut Gpu, n: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Jump to address NNN
-    fn _1nnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn _1nnn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error>;
 
     /// Execute subroutine starting at address NNN
-    fn _2nnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn _2nnn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error>;
 
     /// Skip the following instruction if the value of register vX is equal to NN
-    fn _3xnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn _3xnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Skip the following instruction if the value of register vX is NOT equal to NN
-    fn _4xnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn _4xnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Skip the following instruction if the value of register vX is equal to the value of
     /// register vY.
-    fn _5xy0(cpu: &mut Cpu) -> ExecutionResult;
+    fn _5xy0(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
+
+    /// SAVE vX - vY (XO-CHIP)
+    /// Store registers vX to vY inclusive at I, in reverse order when X > Y. I is unchanged.
+    fn _5xy2(cpu: &mut Cpu, mem: &mut Memory, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
+
+    /// LOAD vX - vY (XO-CHIP)
+    /// Load registers vX to vY inclusive from I, in reverse order when X > Y. I is unchanged.
+    fn _5xy3(cpu: &mut Cpu, mem: &Memory, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Store the number NN in register vX
-    fn _6xnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn _6xnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Add the value NN to register vX
-    fn _7xnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn _7xnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Store the value of register vY in register vX
-    fn _8xy0(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xy0(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Set vX to vX OR vY
-    fn _8xy1(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xy1(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Set vX to vX AND vY
-    fn _8xy2(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xy2(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Set vX to vX XOR vY
-    fn _8xy3(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xy3(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Set vX to vX + vY
     /// Add the value of register VY to register VX
     /// Set VF to 01 if a carry occurs
     /// Set VF to 00 if a carry does not occur
     //#[feature(bigint_helper_methods)]
-    fn _8xy4(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xy4(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Set vX to Vx - Vy
     /// Subtract the value of register VY from register VX
     /// ... Vx = Vx - Vy, set VF = NOT borrow
     /// ... Set VF to 00 if a borrow occurs
     /// ... Set VF to 01 if a borrow does not occur
-    fn _8xy5(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xy5(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Set vX to vY>>
     /// Store the value of register VY shifted right one bit in register VX¹
     /// Set register VF to the least significant bit prior to the shift
     /// VY is unchanged
-    fn _8xy6(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xy6(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Set register VX to the value of VY minus VX
     /// ... Vx = Vy - Vx, VF = NOT borrow
     /// ... Set VF to 00 if a borrow occurs
     /// ... Set VF to 01 if a borrow does not occur
-    fn _8xy7(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xy7(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Set vX to vY<<
     /// Store the value of register vY shifted left one bit in register vX
     /// Set register vF to the most significant bit prior to the shift
     /// vY is unchanged
-    fn _8xye(cpu: &mut Cpu) -> ExecutionResult;
+    fn _8xye(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// Skip the following instruction if the value of register vX is not equal to the value of
     /// register vY.
-    fn _9xy0(cpu: &mut Cpu) -> ExecutionResult;
+    fn _9xy0(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// LD I, addr
     /// Store memory address NNN in register I
-    fn annn(cpu: &mut Cpu) -> ExecutionResult;
+    fn annn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error>;
 
     /// JP V0, addr
     /// Jump to address NNN + v0
-    fn bnnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn bnnn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error>;
 
     /// RND vX, byte
     /// Set vX to a random number with a mask of NN
     /// Set Vx = random byte AND kk.
     /// The interpreter generates a random number from 0 to 255, which is then ANDed with the value kk. The results are stored in Vx. See instruction 8xy2 for more information on AND.
-    fn cxnn(cpu: &mut Cpu) -> ExecutionResult;
+    fn cxnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// DRW vX, vY, nibble
     /// Draw a sprite at position vX, vY with N bytes of sprite data starting at the address
     /// stored in I. Set vF to 01 if any set pixels are changed to unset, and 00 otherwise.
-    fn dxyn(cpu: &mut Cpu, mem: &Memory, gpu: &mut Gpu) -> ExecutionResult;
+    /// DRW vX, vY, 0 (SUPER-CHIP) draws a 16x16 sprite from 32 bytes instead.
+    fn dxyn(
+        cpu: &mut Cpu,
+        mem: &Memory,
+        gpu: &mut Gpu,
+        x: u8,
+        y: u8,
+        n: u8,
+    ) -> Result<ExecutionResult, Chip8Error>;
 
     /// SKP vX
     /// Skip the following instruction if the key corresponding to
     /// the hex value currently stored in register vX is pressed
-    fn ex9e(cpu: &mut Cpu) -> ExecutionResult;
+    fn ex9e(cpu: &mut Cpu, keypad: &Keypad, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// SKNP vX
     /// Skip the following instruction if the key corresponding to
     /// the hex value currently stored in register vX is NOT pressed
-    fn exa1(cpu: &mut Cpu) -> ExecutionResult;
+    fn exa1(cpu: &mut Cpu, keypad: &Keypad, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// LD vX, DT
     /// Store the current value of the delay timer in register vX
-    fn fx07(cpu: &mut Cpu, timers: &Timer) -> ExecutionResult;
+    fn fx07(cpu: &mut Cpu, timers: &Timer, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// LD vX, K
     /// Wait for a keypress and store the result in register vX
-    fn fx0a(cpu: &mut Cpu, _gpu: &Gpu) -> ExecutionResult;
+    /// Blocks until a key is pressed and then released, like the COSMAC VIP did.
+    fn fx0a(cpu: &mut Cpu, keypad: &mut Keypad, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     ///// fx0a but presses the 'x' key
     //pub fn fx0a_test(cpu: &mut Cpu) {
@@ -178,21 +259,41 @@ pub trait Chip8ISet {
 
     /// LD DT, vX
     /// Set the delay timer to the value of register vX
-    fn fx15(cpu: &mut Cpu, timers: &mut Timer) -> ExecutionResult;
+    fn fx15(cpu: &mut Cpu, timers: &mut Timer, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// LD ST, vX
     /// Set the sound timer to value of register vX
-    fn fx18(cpu: &mut Cpu, timers: &mut Timer) -> ExecutionResult;
+    fn fx18(cpu: &mut Cpu, timers: &mut Timer, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// ADD I, vX
     /// Add the value stored in register vX to register I
     /// Set I = I + Vx.
     /// The values of I and Vx are added, and the results are stored in I.
-    fn fx1e(cpu: &mut Cpu) -> ExecutionResult;
+    fn fx1e(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error>;
+
+    /// LD I, long NNNN (XO-CHIP)
+    /// Set I to the 16 bit address in the next two bytes. The instruction is 4 bytes long.
+    fn f000(cpu: &mut Cpu, mem: &Memory) -> Result<ExecutionResult, Chip8Error>;
+
+    /// PLANE n (XO-CHIP)
+    /// Select the bitplanes drawing, clearing and scrolling work on (0-3)
+    fn fn01(gpu: &mut Gpu, n: u8) -> Result<ExecutionResult, Chip8Error>;
+
+    /// AUDIO (XO-CHIP)
+    /// Load the 16 byte audio pattern at I
+    fn f002(cpu: &mut Cpu, mem: &Memory, audio: &mut Audio) -> Result<ExecutionResult, Chip8Error>;
+
+    /// PITCH vX (XO-CHIP)
+    /// Set the audio pattern playback rate from vX
+    fn fx3a(cpu: &mut Cpu, audio: &mut Audio, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// LD F, vX
     /// Set I to memory address of the sprite data corresponding to hex digit stored in register vX
-    fn fx29(cpu: &mut Cpu) -> ExecutionResult;
+    fn fx29(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error>;
+
+    /// LD HF, vX (SUPER-CHIP)
+    /// Set I to the 8x10 big font sprite for the hex digit in vX
+    fn fx30(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// LD B, vX
     /// Store BCD of value in vX at addresses I, I+1, I+2
@@ -200,53 +301,90 @@ pub trait Chip8ISet {
     /// Stores the binary-coded decimal representation of VX, with the hundreds digit
     /// in memory at location in I, the tens digit at location I+1,
     /// and the ones digit at location I+2.[24]
-    fn fx33(cpu: &mut Cpu, mem: &mut Memory) -> ExecutionResult;
+    fn fx33(cpu: &mut Cpu, mem: &mut Memory, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// LD [I], vX
     /// Store register vals v0 to vX inclusive in memory starting at address I.
-    /// Sets I = I + X + 1
+    /// Sets I = I + X + 1 with the load_store_increments_i quirk
     /// Basically fx65 but instead of putting memory into registers, puts registers into memory.
-    fn fx55(cpu: &mut Cpu, mem: &mut Memory) -> ExecutionResult;
+    fn fx55(cpu: &mut Cpu, mem: &mut Memory, x: u8) -> Result<ExecutionResult, Chip8Error>;
 
     /// LD vX, [I]
     /// Fill registers v0 to vX inclusive.
-    /// Sets I = I + X + 1
+    /// Sets I = I + X + 1 with the load_store_increments_i quirk
     /// The interpreter reads values from memory starting at location I into registers V0 through Vx.
-    fn fx65(cpu: &mut Cpu, mem: &Memory) -> ExecutionResult;
+    fn fx65(cpu: &mut Cpu, mem: &Memory, x: u8) -> Result<ExecutionResult, Chip8Error>;
+
+    /// LD R, vX (SUPER-CHIP)
+    /// Store v0 to vX inclusive in the RPL user flags (HP48 calculator registers)
+    fn fx75(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error>;
+
+    /// LD vX, R (SUPER-CHIP)
+    /// Read v0 to vX inclusive back from the RPL user flags
+    fn fx85(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error>;
 }
 
 impl Chip8ISet for OpCode {
-    /// Returns current opcodes 2nd nibble
-    fn get_x(cpu: &Cpu) -> u8 {
-        //let op = cpu.current_opcode;
-        //let (_, x, _, _) = op.into_tuple();
-        //x
-        cpu.current_opcode.into_tuple().1
+    /// Clear the screen
+    fn _00e0(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error> {
+        gpu.clear();
+        Ok(ExecutionResult::Advanced)
     }
 
-    /// Returns current opcodes 3rd nibble
-    fn get_y(cpu: &Cpu) -> u8 {
-        cpu.current_opcode.into_tuple().2
+    /// Scroll the display down N pixels
+    /// Scrolls are in pixels of the current resolution, like Octo and XO-CHIP do it.
+    fn _00cn(gpu: &mut Gpu, n: u8) -> Result<ExecutionResult, Chip8Error> {
+        gpu.scroll_down(n as usize);
+        Ok(ExecutionResult::Advanced)
     }
 
-    /// Clear the screen
-    fn _00e0(gpu: &mut Gpu) -> ExecutionResult {
-        gpu.screen = [false; 64 * 32];
-        ExecutionResult::Advanced
+    /// Scroll the display right 4 pixels
+    fn _00fb(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error> {
+        gpu.scroll_right(4);
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Scroll the display left 4 pixels
+    fn _00fc(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error> {
+        gpu.scroll_left(4);
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Stop the interpreter
+    fn _00fd(_cpu: &mut Cpu) -> Result<ExecutionResult, Chip8Error> {
+        Ok(ExecutionResult::Exited)
+    }
+
+    /// Switch to low resolution
+    fn _00fe(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error> {
+        gpu.set_hires(false);
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Switch to high resolution
+    fn _00ff(gpu: &mut Gpu) -> Result<ExecutionResult, Chip8Error> {
+        gpu.set_hires(true);
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Scroll the selected planes up N pixels
+    fn _00dn(gpu: &mut Gpu, n: u8) -> Result<ExecutionResult, Chip8Error> {
+        gpu.scroll_up(n as usize);
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Return from a subroutine
     /// decrements the stack pointer and sets the program counter
     /// to the current address on the top of the stack
-    fn _00ee(cpu: &mut Cpu) -> ExecutionResult {
+    fn _00ee(cpu: &mut Cpu) -> Result<ExecutionResult, Chip8Error> {
         if cpu.stack_pointer == 0 {
-            panic!("Stack underflow!")
+            return Err(Chip8Error::StackUnderflow);
         } else {
             cpu.stack_pointer -= 1;
 
             cpu.program_counter = cpu.stack[cpu.stack_pointer];
         }
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Execute machine language subroutine at address NNN
@@ -255,7 +393,7 @@ impl Chip8ISet for OpCode {
     /// Tobias lied, because the ibm chip8 logo program uses this
     /// 00000050: 0f02 0202 0202 0000 1f3f 71e0 e5e0 e8a0  .........?q.....
     ///                ^^^^
-    fn _0nnn(cpu: &mut Cpu) -> ExecutionResult {
+    fn _0nnn(_cpu: &mut Cpu, _nnn: u16) -> Result<ExecutionResult, Chip8Error> {
         // let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
         // let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
         // Figure out if this NNN is BCD'd or if its the bits sequentially
@@ -266,63 +404,53 @@ impl Chip8ISet for OpCode {
         // 9/7/25 - b/c memory for chip8 goes to 0xFFF, I assume its not BCD.
         // cpu.program_counter = address;
         // DO NOTHING
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Jump to address NNN
-    fn _1nnn(cpu: &mut Cpu) -> ExecutionResult {
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
-        debug!(target: "iset", "1nnn: Jumping to address {:x?} (setting pc)", address);
-        cpu.program_counter = address;
-        ExecutionResult::Jumped
+    fn _1nnn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error> {
+        debug!(target: "iset", "1nnn: Jumping to address {:x?} (setting pc)", nnn);
+        cpu.program_counter = nnn;
+        Ok(ExecutionResult::Jumped)
     }
 
     /// Execute subroutine starting at address NNN
-    fn _2nnn(cpu: &mut Cpu) -> ExecutionResult {
+    fn _2nnn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error> {
         if cpu.stack_pointer >= cpu.stack.len() {
-            panic!("Stack overflow!");
+            return Err(Chip8Error::StackOverflow);
         }
 
         // push current address onto the stack
         cpu.stack[cpu.stack_pointer] = cpu.program_counter;
         cpu.stack_pointer += 1;
 
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
-        cpu.program_counter = address;
-        ExecutionResult::Jumped
+        cpu.program_counter = nnn;
+        Ok(ExecutionResult::Jumped)
     }
 
     /// Skip the following instruction if the value of register vX is equal to NN
-    fn _3xnn(cpu: &mut Cpu) -> ExecutionResult {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let value = (n2 as u8) << 4 | n3 as u8;
+    fn _3xnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
-        if vx == value {
-            ExecutionResult::Skipped
+        if vx == nn {
+            Ok(ExecutionResult::Skipped)
         } else {
-            ExecutionResult::Advanced
+            Ok(ExecutionResult::Advanced)
         }
     }
 
     /// Skip the following instruction if the value of register vX is NOT equal to NN
-    fn _4xnn(cpu: &mut Cpu) -> ExecutionResult {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let value = (n2 as u8) << 4 | n3 as u8;
+    fn _4xnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
-        if vx != value {
-            ExecutionResult::Skipped
+        if vx != nn {
+            Ok(ExecutionResult::Skipped)
         } else {
-            ExecutionResult::Advanced
+            Ok(ExecutionResult::Advanced)
         }
     }
 
     /// Skip the following instruction if the value of register vX is equal to the value of
     /// register vY.
-    fn _5xy0(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _5xy0(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         if vx == vy {
@@ -331,78 +459,88 @@ impl Chip8ISet for OpCode {
             // future galus: we need to handle the execution w/ the program_counter
             // ... the index_register is for interacting with memory and other things
             // ... and +1 will go to next instruction, so we need to +2 instead
-            ExecutionResult::Skipped
+            Ok(ExecutionResult::Skipped)
         } else {
-            ExecutionResult::Advanced
+            Ok(ExecutionResult::Advanced)
+        }
+    }
+
+    /// Store registers vX to vY inclusive at I, I is unchanged
+    fn _5xy2(cpu: &mut Cpu, mem: &mut Memory, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
+        let registers = OpCode::register_range(x, y);
+        let dest = mem.write(cpu.index_register as usize, registers.len())?;
+        for (byte, register) in dest.iter_mut().zip(registers) {
+            *byte = cpu.registers[register];
         }
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Load registers vX to vY inclusive from I, I is unchanged
+    fn _5xy3(cpu: &mut Cpu, mem: &Memory, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
+        let registers = OpCode::register_range(x, y);
+        let src = mem.read(cpu.index_register as usize, registers.len())?;
+        for (&byte, register) in src.iter().zip(registers) {
+            cpu.registers[register] = byte;
+        }
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Store the number NN in register vX
-    fn _6xnn(cpu: &mut Cpu) -> ExecutionResult {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let value = (n2 as u8) << 4 | n3 as u8;
-        cpu.registers[x as usize] = value;
-        ExecutionResult::Advanced
+    fn _6xnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error> {
+        cpu.registers[x as usize] = nn;
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Add the value NN to register vX
-    fn _7xnn(cpu: &mut Cpu) -> ExecutionResult {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let value = (n2 as u8) << 4 | n3 as u8;
-        let temp = cpu.registers[x as usize] + value;
+    fn _7xnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error> {
+        // no carry flag for 7xnn, it just wraps
+        let temp = cpu.registers[x as usize].wrapping_add(nn);
         cpu.registers[x as usize] = temp;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Store the value of register vY in register vX
-    fn _8xy0(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _8xy0(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vy = cpu.registers[y as usize];
         cpu.registers[x as usize] = vy;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Set vX to vX OR vY
-    fn _8xy1(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _8xy1(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         cpu.registers[x as usize] = vx | vy;
-        ExecutionResult::Advanced
+        OpCode::reset_vf(cpu);
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Set vX to vX AND vY
-    fn _8xy2(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _8xy2(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         cpu.registers[x as usize] = vx & vy;
-        ExecutionResult::Advanced
+        OpCode::reset_vf(cpu);
+        Ok(ExecutionResult::Advanced)
     }
 
-    /// 11 + 11 =>  3 + 3 = 6 = 110 , 111 + 111 = 7+7 = 14 = 1110 , overflow means lsb of larger
-    ///    type
+    // 11 + 11 =>  3 + 3 = 6 = 110 , 111 + 111 = 7+7 = 14 = 1110 , overflow means lsb of larger
+    //    type
 
     /// Set vX to vX XOR vY
-    fn _8xy3(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _8xy3(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         cpu.registers[x as usize] = vx ^ vy;
-        ExecutionResult::Advanced
+        OpCode::reset_vf(cpu);
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Add the value of register VY to register VX
     /// Set VF to 01 if a carry occurs
     /// Set VF to 00 if a carry does not occur
     //#[feature(bigint_helper_methods)]
-    fn _8xy4(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _8xy4(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         let (sum, carry) = {
@@ -415,16 +553,14 @@ impl Chip8ISet for OpCode {
         };
         cpu.registers[x as usize] = sum;
         cpu.registers[0xF] = carry as u8;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Subtract the value of register VY from register VX
     /// ... Vx = Vx - Vy, set VF = NOT borrow
     /// ... Set VF to 00 if a borrow occurs
     /// ... Set VF to 01 if a borrow does not occur
-    fn _8xy5(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _8xy5(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         let (diff, borrow) = {
@@ -437,34 +573,30 @@ impl Chip8ISet for OpCode {
         };
         cpu.registers[x as usize] = diff;
         if borrow {
-            cpu.registers[0xF as usize] = 0x00;
+            cpu.registers[0xF] = 0x00;
         } else {
-            cpu.registers[0xF as usize] = 0x01;
+            cpu.registers[0xF] = 0x01;
         }
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Store the value of register VY shifted right one bit in register VX¹
     /// Set register VF to the least significant bit prior to the shift
     /// VY is unchanged
-    fn _8xy6(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
-        let vy = cpu.registers[y as usize];
-        let lsb_vy = vy & 0b00000001;
-        cpu.registers[0xF as usize] = lsb_vy;
-        let shifted_vy = vy >> 1;
-        cpu.registers[x as usize] = shifted_vy;
-        ExecutionResult::Advanced
+    /// ¹ without the shift_uses_vy quirk VX is shifted in place
+    fn _8xy6(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
+        let source = OpCode::shift_source(cpu, x, y);
+        let lsb = source & 0b00000001;
+        cpu.registers[x as usize] = source >> 1;
+        cpu.registers[0xF] = lsb;
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Set register VX to the value of VY minus VX
     /// ... Vx = Vy - Vx, VF = NOT borrow
     /// ... Set VF to 00 if a borrow occurs
     /// ... Set VF to 01 if a borrow does not occur
-    fn _8xy7(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _8xy7(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         let (diff, borrow) = {
@@ -477,165 +609,175 @@ impl Chip8ISet for OpCode {
         };
         cpu.registers[x as usize] = diff;
         if borrow {
-            cpu.registers[0xF as usize] = 0x00;
+            cpu.registers[0xF] = 0x00;
         } else {
-            cpu.registers[0xF as usize] = 0x01;
+            cpu.registers[0xF] = 0x01;
         }
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Store the value of register vY shifted left one bit in register vX
     /// Set register vF to the most significant bit prior to the shift
-    /// vY is unchanged
-    fn _8xye(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
-        let vy = cpu.registers[y as usize];
-        let msb_vy = (vy & 0b10000000) >> 7;
-        cpu.registers[0xF as usize] = msb_vy;
-        let shifted_vy = vy << 1;
-        cpu.registers[x as usize] = shifted_vy;
-        ExecutionResult::Advanced
+    /// vY is unchanged (and unused without the shift_uses_vy quirk, see 8XY6)
+    fn _8xye(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
+        let source = OpCode::shift_source(cpu, x, y);
+        let msb = (source & 0b10000000) >> 7;
+        cpu.registers[x as usize] = source << 1;
+        cpu.registers[0xF] = msb;
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Skip the following instruction if the value of register vX is not equal to the value of
     /// register vY.
-    fn _9xy0(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let y = OpCode::get_y(cpu);
+    fn _9xy0(cpu: &mut Cpu, x: u8, y: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         let vy = cpu.registers[y as usize];
         if vx != vy {
-            ExecutionResult::Skipped
+            Ok(ExecutionResult::Skipped)
         } else {
-            ExecutionResult::Advanced
+            Ok(ExecutionResult::Advanced)
         }
     }
 
     /// Store memory address NNN in register I
-    fn annn(cpu: &mut Cpu) -> ExecutionResult {
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
-        cpu.index_register = address;
-        ExecutionResult::Advanced
+    fn annn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error> {
+        cpu.index_register = nnn;
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Jump to address NNN + v0
-    fn bnnn(cpu: &mut Cpu) -> ExecutionResult {
-        let (_, n1, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let address = (n1 as u16) << 8 | (n2 as u16) << 4 | n3 as u16;
-        let added_address = cpu.registers[0] as u16 + address;
+    /// With the jump_uses_vx quirk it's BXNN: jump to XNN + vX
+    fn bnnn(cpu: &mut Cpu, nnn: u16) -> Result<ExecutionResult, Chip8Error> {
+        let offset_register = if cpu.quirks.jump_uses_vx { nnn >> 8 } else { 0 };
+        let added_address = cpu.registers[offset_register as usize] as u16 + nnn;
         cpu.program_counter = added_address;
-        ExecutionResult::Jumped
+        Ok(ExecutionResult::Jumped)
     }
 
     /// Set vX to a random number with a mask of NN
     /// Set Vx = random byte AND kk.
     /// The interpreter generates a random number from 0 to 255, which is then ANDed with the value kk. The results are stored in Vx. See instruction 8xy2 for more information on AND.
-    fn cxnn(cpu: &mut Cpu) -> ExecutionResult {
-        let (_, x, n2, n3) = cpu.current_opcode.into_tuple(); //opcodes are u16
-        let rng = rand::random::<u8>();
-        let masked_rng = (n2 << 4 | n3) & rng;
+    fn cxnn(cpu: &mut Cpu, x: u8, nn: u8) -> Result<ExecutionResult, Chip8Error> {
+        let masked_rng = nn & cpu.rng.next_byte();
         cpu.registers[x as usize] = masked_rng;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Draw a sprite at position vX, vY with n height.
     /// bytes of sprite data start at the address stored in I.
     /// Set vF to 01 if any set pixels are changed to unset, and 00 otherwise.
-    fn dxyn(cpu: &mut Cpu, mem: &Memory, gpu: &mut Gpu) -> ExecutionResult {
-        let (_, x, y, n) = OpCode::into_tuple(&cpu.current_opcode);
-        let start = cpu.index_register as usize;
-        let end = start + (n as usize);
-        let sprite_data = &mem.ram[start..end];
+    /// The starting position always wraps, the clip_sprites quirk decides whether the
+    /// rest of the sprite wraps too or gets cut off at the edge.
+    fn dxyn(
+        cpu: &mut Cpu,
+        mem: &Memory,
+        gpu: &mut Gpu,
+        x: u8,
+        y: u8,
+        n: u8,
+    ) -> Result<ExecutionResult, Chip8Error> {
+        if cpu.quirks.display_wait {
+            if !cpu.vblank {
+                return Ok(ExecutionResult::Waiting);
+            }
+            cpu.vblank = false;
+        }
+        // DXY0 is SUPER-CHIP's 16x16 sprite, two bytes per row
+        let (sprite_width, rows) = if n == 0 { (16, 16) } else { (8, n as usize) };
+        let bytes_per_row = sprite_width / 8;
+        // XO-CHIP: one sprite per selected plane, back to back in memory
+        let planes: Vec<usize> = gpu.planes().collect();
+        let sprite_len = rows * bytes_per_row;
+        let sprite_data = mem.read(cpu.index_register as usize, sprite_len * planes.len())?;
+        let clip = cpu.quirks.clip_sprites;
+        let (width, height) = (gpu.width(), gpu.height());
         // Too many people online say that we should wrap around w/ modulus
         let (vx, vy) = (
-            cpu.registers[x as usize] as usize % SCREEN_WIDTH,
-            cpu.registers[y as usize] as usize % SCREEN_HEIGHT,
+            cpu.registers[x as usize] as usize % width,
+            cpu.registers[y as usize] as usize % height,
         );
-        let mut collision_detected = false;
-
-        for row in 0..n {
-            let sprite_byte = sprite_data[row as usize];
-            let current_y = vy.wrapping_add(row as usize) % SCREEN_HEIGHT;
-
-            for bit_index in 0..8 {
-                let current_x = vx.wrapping_add(bit_index) % SCREEN_WIDTH;
-                let screen_index = current_y * SCREEN_WIDTH + current_x;
-
-                if screen_index >= SCREEN_WIDTH * SCREEN_HEIGHT {
-                    continue;
+        // rows that hit something, plus the ones clipped off the bottom, for SUPER-CHIP's vF
+        let mut collided_rows = 0;
+        let mut clipped_rows = 0;
+
+        for (sprite, &plane) in sprite_data.chunks(sprite_len).zip(&planes) {
+            let pixels = gpu.plane_mut(plane);
+            for row in 0..rows {
+                let current_y = vy + row;
+                if clip && current_y >= height {
+                    clipped_rows += rows - row;
+                    break;
                 }
-                let old_pixel = gpu.screen[screen_index];
-                let new_pixel = (sprite_byte >> (7 - bit_index)) & 0x1 == 1;
-                gpu.screen[screen_index] = old_pixel ^ new_pixel;
-
-                if old_pixel && !gpu.screen[screen_index] {
-                    collision_detected = true;
+                let current_y = current_y % height;
+                let mut row_collided = false;
+
+                for bit_index in 0..sprite_width {
+                    let current_x = vx + bit_index;
+                    if clip && current_x >= width {
+                        break;
+                    }
+                    let current_x = current_x % width;
+                    let screen_index = current_y * width + current_x;
+
+                    let sprite_byte = sprite[row * bytes_per_row + bit_index / 8];
+                    let old_pixel = pixels[screen_index];
+                    let new_pixel = (sprite_byte >> (7 - bit_index % 8)) & 0x1 == 1;
+                    pixels[screen_index] = old_pixel ^ new_pixel;
+
+                    if old_pixel && !pixels[screen_index] {
+                        row_collided = true;
+                    }
                 }
+                collided_rows += row_collided as usize;
             }
         }
-        if collision_detected {
-            cpu.registers[0xF] = 1;
+        cpu.registers[0xF] = if cpu.quirks.hires_collision_rows && gpu.is_hires() {
+            (collided_rows + clipped_rows) as u8
         } else {
-            cpu.registers[0xF] = 0;
-        }
-        ExecutionResult::Advanced
+            (collided_rows > 0) as u8
+        };
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Skip the following instruction if the key corresponding to
     /// the hex value currently stored in register vX is pressed
-    fn ex9e(cpu: &mut Cpu) -> ExecutionResult {
-        todo!("impl later");
-        //let x = OpCode::get_x(cpu);
-        //let vx = cpu.registers[x as usize];
-        //let pressed_value = cpu.memory.gpu.handle_events().unwrap();
-        //if pressed_value == vx {
-        //    // skip instruction
-        //    cpu.program_counter += 2;
-        //} else {
-        //    // dont skip
-        //    // galus note: I think that program counter being automatically incremented may
-        //    // start to cause problems... future galus will find out soonTm.
-        //}
-        //ExecutionResult::Skipped
+    fn ex9e(cpu: &mut Cpu, keypad: &Keypad, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let vx = cpu.registers[x as usize];
+        if keypad.is_pressed(vx) {
+            Ok(ExecutionResult::Skipped)
+        } else {
+            Ok(ExecutionResult::Advanced)
+        }
     }
 
     /// Skip the following instruction if the key corresponding to
     /// the hex value currently stored in register vX is NOT pressed
-    fn exa1(cpu: &mut Cpu) -> ExecutionResult {
-        todo!("impl later");
-        //let x = OpCode::get_x(cpu);
-        //let vx = cpu.registers[x as usize];
-        //let pressed_value = cpu.memory.gpu.handle_events().unwrap();
-        //if pressed_value != vx {
-        //    // skip instruction
-        //    cpu.program_counter += 2;
-        //} else {
-        //    // dont skip
-        //}
-        //ExecutionResult::Advanced
+    fn exa1(cpu: &mut Cpu, keypad: &Keypad, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let vx = cpu.registers[x as usize];
+        if keypad.is_pressed(vx) {
+            Ok(ExecutionResult::Advanced)
+        } else {
+            Ok(ExecutionResult::Skipped)
+        }
     }
 
     /// Store the current value of the delay timer in register vX
-    fn fx07(cpu: &mut Cpu, timers: &Timer) -> ExecutionResult {
+    fn fx07(cpu: &mut Cpu, timers: &Timer, x: u8) -> Result<ExecutionResult, Chip8Error> {
         let delay_timer = timers.delay_timer;
-        let x = OpCode::get_x(cpu);
         cpu.registers[x as usize] = delay_timer;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Wait for a keypress and store the result in register vX
-    fn fx0a(cpu: &mut Cpu, _gpu: &Gpu) -> ExecutionResult {
-        // use ratatui::crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-        let x = OpCode::get_x(cpu);
-        //let _ = enable_raw_mode();
-        //TODO: figure it out
-        //let pressed_value = gpu.handle_events().unwrap();
-        let pressed_value = 111;
-        //let _ = disable_raw_mode();
-        cpu.registers[x as usize] = pressed_value;
-        ExecutionResult::Advanced
+    /// The pc stays on this instruction until the keypad reports a key that went down and back up.
+    fn fx0a(cpu: &mut Cpu, keypad: &mut Keypad, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        match keypad.poll_key_release() {
+            Some(key) => {
+                cpu.registers[x as usize] = key;
+                Ok(ExecutionResult::Advanced)
+            }
+            None => Ok(ExecutionResult::Waiting),
+        }
     }
 
     ///// fx0a but presses the 'x' key
@@ -652,39 +794,73 @@ impl Chip8ISet for OpCode {
     //}
 
     /// Set the delay timer to the value of register vX
-    fn fx15(cpu: &mut Cpu, timers: &mut Timer) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
+    fn fx15(cpu: &mut Cpu, timers: &mut Timer, x: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         timers.delay_timer = vx;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Set the sound timer to value of register vX
-    fn fx18(cpu: &mut Cpu, timers: &mut Timer) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
+    fn fx18(cpu: &mut Cpu, timers: &mut Timer, x: u8) -> Result<ExecutionResult, Chip8Error> {
         let vx = cpu.registers[x as usize];
         timers.sound_timer = vx;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Add the value stored in register vX to register I
     /// Set I = I + Vx.
     /// The values of I and Vx are added, and the results are stored in I.
-    fn fx1e(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let vx = &cpu.registers[x as usize];
-        let i = &cpu.index_register;
-        let new_i = (*vx) as u16 + i;
+    fn fx1e(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let vx = cpu.registers[x as usize];
+        let i = cpu.index_register;
+        let new_i = i.wrapping_add(vx as u16);
         cpu.index_register = new_i;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Set I to the 16 bit address stored after the opcode
+    fn f000(cpu: &mut Cpu, mem: &Memory) -> Result<ExecutionResult, Chip8Error> {
+        let address = mem.slice(cpu.program_counter as usize + 2, 2)?;
+        cpu.index_register = (address[0] as u16) << 8 | address[1] as u16;
+        // skip over the address as well
+        cpu.program_counter = cpu.program_counter.wrapping_add(4);
+        Ok(ExecutionResult::Jumped)
+    }
+
+    /// Select the bitplanes to draw on
+    fn fn01(gpu: &mut Gpu, n: u8) -> Result<ExecutionResult, Chip8Error> {
+        gpu.select_planes(n);
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Load the 16 byte audio pattern at I
+    fn f002(cpu: &mut Cpu, mem: &Memory, audio: &mut Audio) -> Result<ExecutionResult, Chip8Error> {
+        let src = mem.read(cpu.index_register as usize, PATTERN_SIZE)?;
+        let mut pattern = [0; PATTERN_SIZE];
+        pattern.copy_from_slice(src);
+        audio.pattern = Some(pattern);
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Set the audio pitch to vX
+    fn fx3a(cpu: &mut Cpu, audio: &mut Audio, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        audio.pitch = cpu.registers[x as usize];
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Set I to memory address of the sprite data corresponding to hex digit stored in register vX
-    fn fx29(cpu: &mut Cpu) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
-        let vx = &cpu.registers[x as usize];
-        cpu.index_register = *vx as u16;
-        ExecutionResult::Advanced
+    /// Each digit is 5 bytes, only the low nibble of vX counts.
+    fn fx29(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let digit = cpu.registers[x as usize] & 0xF;
+        cpu.index_register = (FONT_START_ADDRESS + digit as usize * 5) as u16;
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Set I to the big font sprite for the hex digit in vX, 10 bytes each
+    fn fx30(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let digit = cpu.registers[x as usize] & 0xF;
+        cpu.index_register = (BIG_FONT_START_ADDRESS + digit as usize * 10) as u16;
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Store BCD of value in vX at addresses I, I+1, I+2
@@ -692,43 +868,54 @@ impl Chip8ISet for OpCode {
     /// Stores the binary-coded decimal representation of VX, with the hundreds digit
     /// in memory at location in I, the tens digit at location I+1,
     /// and the ones digit at location I+2.[24]
-    fn fx33(cpu: &mut Cpu, mem: &mut Memory) -> ExecutionResult {
-        let x = OpCode::get_x(cpu);
+    fn fx33(cpu: &mut Cpu, mem: &mut Memory, x: u8) -> Result<ExecutionResult, Chip8Error> {
         let register = cpu.registers[x as usize];
         let padded = format!("{:0>3}", register);
         let a: u8 = padded.chars().nth(0).unwrap() as u8 - 48; // ascii '0' starts at decimal 48
         let b: u8 = padded.chars().nth(1).unwrap() as u8 - 48;
         let c: u8 = padded.chars().nth(2).unwrap() as u8 - 48;
-        let index = cpu.index_register as usize;
-        mem.ram[index] = a;
-        mem.ram[index + 1] = b;
-        mem.ram[index + 2] = c;
-        ExecutionResult::Advanced
+        let digits = mem.write(cpu.index_register as usize, 3)?;
+        digits.copy_from_slice(&[a, b, c]);
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Store register vals v0 to vX inclusive in memory starting at address I.
-    /// Sets I = I + X + 1
+    /// Sets I = I + X + 1 with the load_store_increments_i quirk
     /// Basically fx65 but instead of putting memory into registers, puts registers into memory.
-    fn fx55(cpu: &mut Cpu, mem: &mut Memory) -> ExecutionResult {
-        let num_registers = OpCode::get_x(&cpu);
-        for x in 0..=num_registers {
-            let load_index = cpu.index_register + (x as u16);
-            mem.ram[load_index as usize] = cpu.registers[x as usize];
+    fn fx55(cpu: &mut Cpu, mem: &mut Memory, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let count = x as usize + 1;
+        let dest = mem.write(cpu.index_register as usize, count)?;
+        dest.copy_from_slice(&cpu.registers[..count]);
+        if cpu.quirks.load_store_increments_i {
+            cpu.index_register = cpu.index_register.wrapping_add(count as u16);
         }
-        cpu.index_register += (num_registers + 1) as u16;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
     }
 
     /// Fill registers v0 to vX inclusive.
-    /// Sets I = I + X + 1
+    /// Sets I = I + X + 1 with the load_store_increments_i quirk
     /// The interpreter reads values from memory starting at location I into registers V0 through Vx.
-    fn fx65(cpu: &mut Cpu, mem: &Memory) -> ExecutionResult {
-        let num_registers = OpCode::get_x(&cpu);
-        for x in 0..=num_registers {
-            let load_index = cpu.index_register + (x as u16);
-            cpu.registers[x as usize] = mem.ram[load_index as usize]
+    fn fx65(cpu: &mut Cpu, mem: &Memory, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let count = x as usize + 1;
+        let src = mem.read(cpu.index_register as usize, count)?;
+        cpu.registers[..count].copy_from_slice(src);
+        if cpu.quirks.load_store_increments_i {
+            cpu.index_register = cpu.index_register.wrapping_add(count as u16);
         }
-        cpu.index_register += (num_registers + 1) as u16;
-        ExecutionResult::Advanced
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Store v0 to vX inclusive in the RPL user flags
+    fn fx75(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let count = x as usize + 1;
+        cpu.rpl_flags[..count].copy_from_slice(&cpu.registers[..count]);
+        Ok(ExecutionResult::Advanced)
+    }
+
+    /// Read v0 to vX inclusive back from the RPL user flags
+    fn fx85(cpu: &mut Cpu, x: u8) -> Result<ExecutionResult, Chip8Error> {
+        let count = x as usize + 1;
+        cpu.registers[..count].copy_from_slice(&cpu.rpl_flags[..count]);
+        Ok(ExecutionResult::Advanced)
     }
 }
@@ -0,0 +1,284 @@
+// Block compiler for headless runs. Straight-line code starting at a pc is decoded once into
+// an array of closures with the operands baked in (threaded code), and a whole block runs
+// without going back through fetch and decode. Blocks end wherever the pc might not just
+// move on by 2, and after anything that writes memory, since that might be the code itself.
+use super::{
+    error::{Chip8Error, Fault},
+    instruction::Instruction,
+    iset::{Chip8ISet, ExecutionResult, OpCode},
+    machine::Machine,
+    mem::Memory,
+};
+use std::fmt;
+use std::ops::Range;
+
+/// Longest run of instructions compiled into one block
+pub const MAX_BLOCK_LEN: usize = 64;
+
+/// One compiled instruction
+type Op = Box<dyn Fn(&mut Machine) -> Result<ExecutionResult, Chip8Error>>;
+
+struct Block {
+    /// In program order, each with the opcode it came from
+    ops: Vec<(OpCode, Op)>,
+}
+
+/// Compiled blocks by start address. Like the decode cache it keeps itself in sync through
+/// `Memory::take_written`, so it can't share a machine with one.
+#[derive(Default)]
+pub struct Jit {
+    blocks: Vec<Option<Block>>,
+    compiled: u64,
+}
+
+impl fmt::Debug for Jit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Jit")
+            .field("blocks", &self.blocks.iter().flatten().count())
+            .field("compiled", &self.compiled)
+            .finish()
+    }
+}
+
+impl Jit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `count` instructions, exactly as that many `Machine::step`s would.
+    /// The caller records the fault, see `Machine::run_frame`.
+    pub(crate) fn run(&mut self, machine: &mut Machine, count: u32) -> Result<(), Fault> {
+        if count > 0 && machine.fault().is_some() {
+            // halted, the interpreter knows what to say about that
+            return machine.step();
+        }
+        let mut remaining = count;
+        while remaining > 0 {
+            self.sync(&mut machine.memory);
+            let start = machine.cpu.program_counter;
+            let Some(block) = self.block_at(start, &machine.memory) else {
+                // nothing to fetch there, let the interpreter fault the usual way
+                return machine.step();
+            };
+            let mut pc = start;
+            for (op, run) in &block.ops {
+                machine.cpu.current_opcode = *op;
+                let result = run(machine);
+                machine.cpu.retire(pc, result, &machine.memory)?;
+                remaining -= 1;
+                pc = pc.wrapping_add(2);
+                if remaining == 0 || machine.cpu.program_counter != pc {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Throws away every block with a byte in `range`.
+    pub fn invalidate(&mut self, range: Range<usize>) {
+        // a block can start up to MAX_BLOCK_LEN instructions before the range
+        let first = range.start.saturating_sub(MAX_BLOCK_LEN * 2);
+        let last = range.end.min(self.blocks.len());
+        for start in first..last {
+            let Some(block) = &self.blocks[start] else {
+                continue;
+            };
+            if start + block.ops.len() * 2 > range.start {
+                self.blocks[start] = None;
+            }
+        }
+    }
+
+    /// Throws away everything, for when `ram` was changed behind the memory's back.
+    pub fn clear(&mut self) {
+        self.blocks.iter_mut().for_each(|block| *block = None);
+    }
+
+    /// How many blocks have been compiled so far, recompiles included.
+    pub fn compiled(&self) -> u64 {
+        self.compiled
+    }
+
+    fn sync(&mut self, memory: &mut Memory) {
+        if self.blocks.len() != memory.ram.len() {
+            self.blocks = std::iter::repeat_with(|| None)
+                .take(memory.ram.len())
+                .collect();
+            memory.take_written();
+        } else if let Some(range) = memory.take_written() {
+            self.invalidate(range);
+        }
+    }
+
+    fn block_at(&mut self, start: u16, memory: &Memory) -> Option<&Block> {
+        let start = start as usize;
+        if self.blocks.get(start)?.is_none() {
+            let mut ops = Vec::new();
+            let mut addr = start;
+            while ops.len() < MAX_BLOCK_LEN {
+                let Ok(bytes) = memory.slice(addr, 2) else {
+                    break;
+                };
+                let op = OpCode((bytes[0] as u16) << 8 | bytes[1] as u16);
+                let instruction = Instruction::decode_for(op.0, memory.is_xo_chip());
+                ops.push((op, compile(instruction)));
+                addr += 2;
+                if ends_block(instruction) {
+                    break;
+                }
+            }
+            if ops.is_empty() {
+                return None;
+            }
+            self.compiled += 1;
+            self.blocks[start] = Some(Block { ops });
+        }
+        self.blocks[start].as_ref()
+    }
+}
+
+/// Anything that can send the pc somewhere other than the next instruction, or write memory.
+fn ends_block(instruction: Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        Ret | Exit
+            | Jump(_)
+            | Call(_)
+            | JumpOffset(_)
+            | SkipEqImm { .. }
+            | SkipNeImm { .. }
+            | SkipEqReg { .. }
+            | SkipNeReg { .. }
+            | SkipKey { .. }
+            | SkipNotKey { .. }
+            | Draw { .. }
+            | WaitKey { .. }
+            | LoadILong
+            | SaveRange { .. }
+            | Bcd { .. }
+            | Store { .. }
+            | Unknown(_)
+    )
+}
+
+/// The hot instructions call straight into the instruction set, the rest go through
+/// `Cpu::execute`. Either way the semantics are the interpreter's own.
+fn compile(instruction: Instruction) -> Op {
+    use Instruction::*;
+    match instruction {
+        Jump(nnn) => Box::new(move |m| OpCode::_1nnn(&mut m.cpu, nnn)),
+        Call(nnn) => Box::new(move |m| OpCode::_2nnn(&mut m.cpu, nnn)),
+        Ret => Box::new(|m| OpCode::_00ee(&mut m.cpu)),
+        SkipEqImm { x, nn } => Box::new(move |m| OpCode::_3xnn(&mut m.cpu, x, nn)),
+        SkipNeImm { x, nn } => Box::new(move |m| OpCode::_4xnn(&mut m.cpu, x, nn)),
+        SkipEqReg { x, y } => Box::new(move |m| OpCode::_5xy0(&mut m.cpu, x, y)),
+        LoadImm { x, nn } => Box::new(move |m| OpCode::_6xnn(&mut m.cpu, x, nn)),
+        AddImm { x, nn } => Box::new(move |m| OpCode::_7xnn(&mut m.cpu, x, nn)),
+        Move { x, y } => Box::new(move |m| OpCode::_8xy0(&mut m.cpu, x, y)),
+        Or { x, y } => Box::new(move |m| OpCode::_8xy1(&mut m.cpu, x, y)),
+        And { x, y } => Box::new(move |m| OpCode::_8xy2(&mut m.cpu, x, y)),
+        Xor { x, y } => Box::new(move |m| OpCode::_8xy3(&mut m.cpu, x, y)),
+        Add { x, y } => Box::new(move |m| OpCode::_8xy4(&mut m.cpu, x, y)),
+        Sub { x, y } => Box::new(move |m| OpCode::_8xy5(&mut m.cpu, x, y)),
+        ShiftRight { x, y } => Box::new(move |m| OpCode::_8xy6(&mut m.cpu, x, y)),
+        SubN { x, y } => Box::new(move |m| OpCode::_8xy7(&mut m.cpu, x, y)),
+        ShiftLeft { x, y } => Box::new(move |m| OpCode::_8xye(&mut m.cpu, x, y)),
+        SkipNeReg { x, y } => Box::new(move |m| OpCode::_9xy0(&mut m.cpu, x, y)),
+        LoadI(nnn) => Box::new(move |m| OpCode::annn(&mut m.cpu, nnn)),
+        AddI { x } => Box::new(move |m| OpCode::fx1e(&mut m.cpu, x)),
+        Draw { x, y, n } => {
+            Box::new(move |m| OpCode::dxyn(&mut m.cpu, &m.memory, &mut m.gpu, x, y, n))
+        }
+        SkipKey { x } => Box::new(move |m| OpCode::ex9e(&mut m.cpu, &m.keypad, x)),
+        SkipNotKey { x } => Box::new(move |m| OpCode::exa1(&mut m.cpu, &m.keypad, x)),
+        LoadDelay { x } => Box::new(move |m| OpCode::fx07(&mut m.cpu, &m.timers, x)),
+        Bcd { x } => Box::new(move |m| OpCode::fx33(&mut m.cpu, &mut m.memory, x)),
+        Store { x } => Box::new(move |m| OpCode::fx55(&mut m.cpu, &mut m.memory, x)),
+        Load { x } => Box::new(move |m| OpCode::fx65(&mut m.cpu, &m.memory, x)),
+        other => Box::new(move |m| {
+            m.cpu.execute(
+                other,
+                &mut m.memory,
+                &mut m.gpu,
+                &mut m.timers,
+                &mut m.keypad,
+                &mut m.audio,
+            )
+        }),
+    }
+}
+
+#[cfg(test)]
+mod jittests {
+    use crate::emu::{ClockSpeed, Machine};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const IBM_LOGO: &[u8] = include_bytes!("../../roms/2-ibm-logo.ch8");
+
+    /// Runs `rom` on a plain and a jitted machine side by side, comparing every frame.
+    fn assert_same(rom: &[u8], xo_chip: bool, frames: usize) {
+        let mut plain = Machine::with_speed(ClockSpeed::Ipf(13));
+        let mut jitted = Machine::with_speed(ClockSpeed::Ipf(13));
+        plain.set_xo_chip(xo_chip);
+        jitted.set_xo_chip(xo_chip);
+        jitted.set_jit(true);
+        plain.load_rom(rom).unwrap();
+        jitted.load_rom(rom).unwrap();
+        for _ in 0..frames {
+            assert_eq!(plain.run_frame(), jitted.run_frame());
+            assert_eq!(plain.snapshot(), jitted.snapshot());
+            assert_eq!(plain.fault(), jitted.fault());
+        }
+    }
+
+    #[test]
+    fn test_matches_the_interpreter() {
+        // 0x20C starts out as LD V3, 5 and gets rewritten to ADD V3, 1 by F155 until V3 is 8
+        let self_modifying = [
+            0x60, 0x73, 0x61, 0x01, 0x12, 0x0C, 0xA2, 0x0C, 0xF1, 0x55, 0x12, 0x0C, 0x63, 0x05,
+            0x33, 0x08, 0x12, 0x06, 0x12, 0x12,
+        ];
+        assert_same(&self_modifying, false, 30);
+        assert_same(IBM_LOGO, false, 30);
+    }
+
+    #[test]
+    fn test_random_programs() {
+        let mut rng = StdRng::seed_from_u64(0xC8);
+        for round in 0..200 {
+            let rom: Vec<u8> = (0..256).map(|_| rng.gen()).collect();
+            assert_same(&rom, round % 2 == 1, 20);
+        }
+    }
+
+    #[test]
+    fn test_blocks_are_recompiled_after_writes() {
+        // two trips round the loop a frame, so frames never end halfway through a block
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(8));
+        machine.set_jit(true);
+        // LD V0, 1; LD I, 0x20A; LD [I], V0; JP 0x200, which keeps writing V0 to 0x20A
+        machine
+            .load_rom(&[
+                0x60, 0x01, 0xA2, 0x0A, 0xF0, 0x55, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00,
+            ])
+            .unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.memory.ram[0x20A], 0x01);
+        // the block at 0x200 ends at the store, so it never has to be thrown away
+        assert_eq!(machine.jit().unwrap().compiled(), 2);
+
+        // a write into the middle of a block throws it away
+        machine.memory.write(0x201, 1).unwrap()[0] = 0x07;
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu.registers[0], 7);
+        assert_eq!(machine.jit().unwrap().compiled(), 3);
+
+        // poking ram by hand needs a clear
+        machine.memory.ram[0x201] = 0x09;
+        machine.clear_decode_cache();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu.registers[0], 9);
+    }
+}
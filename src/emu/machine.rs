@@ -0,0 +1,666 @@
+// A complete CHIP-8: cpu, memory, display, timers and keypad wired together.
+#[cfg(feature = "jit")]
+use super::jit::Jit;
+use super::{
+    audio::Audio,
+    cache::DecodeCache,
+    clock::{ClockSpeed, FrameClock},
+    cpu::Cpu,
+    error::{Chip8Error, Fault},
+    gpu::Gpu,
+    input::Keypad,
+    mem::{Memory, RAM_SIZE, XO_RAM_SIZE},
+    quirks::Quirks,
+    rewind::RewindBuffer,
+    sound::{AudioSink, NullSink, Synth},
+    state::{Snapshot, StateError},
+    timer::Timer,
+};
+use std::io;
+
+#[derive(Debug)]
+pub struct Machine {
+    pub cpu: Cpu,
+    pub gpu: Gpu,
+    pub memory: Memory,
+    pub timers: Timer,
+    pub keypad: Keypad,
+    pub audio: Audio,
+    clock: FrameClock,
+    synth: Synth,
+    /// Where each frame's samples go, a NullSink unless the frontend sets one
+    sink: Box<dyn AudioSink>,
+    /// Every frame goes in here once rewind is enabled
+    rewind: Option<RewindBuffer>,
+    /// Set when an instruction fails, the machine stays halted until it's cleared
+    fault: Option<Fault>,
+    /// Predecoded instructions, only when asked for with `set_decode_cache`
+    cache: Option<DecodeCache>,
+    /// Compiled blocks, only when asked for with `set_jit`
+    #[cfg(feature = "jit")]
+    jit: Option<Jit>,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    /// A powered-on machine with the font loaded and no ROM.
+    pub fn new() -> Self {
+        Self {
+            cpu: Cpu::new(),
+            gpu: Gpu::new(),
+            memory: Memory::new(),
+            timers: Timer::new(0),
+            keypad: Keypad::new(),
+            audio: Audio::new(),
+            clock: FrameClock::new(ClockSpeed::default()),
+            synth: Synth::default(),
+            sink: Box::new(NullSink),
+            rewind: None,
+            fault: None,
+            cache: None,
+            #[cfg(feature = "jit")]
+            jit: None,
+        }
+    }
+
+    pub fn with_speed(speed: ClockSpeed) -> Self {
+        let mut machine = Self::new();
+        machine.set_speed(speed);
+        machine
+    }
+
+    pub fn speed(&self) -> ClockSpeed {
+        self.clock.speed()
+    }
+
+    pub fn set_speed(&mut self, speed: ClockSpeed) {
+        self.clock = FrameClock::new(speed);
+    }
+
+    /// Where the clock is between frames, see `FrameClock::leftover_cycles`. Not part of a
+    /// snapshot, replays that start from one keep it next to it.
+    pub fn leftover_cycles(&self) -> u32 {
+        self.clock.leftover_cycles()
+    }
+
+    pub fn set_leftover_cycles(&mut self, cycles: u32) {
+        self.clock.set_leftover_cycles(cycles);
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.cpu.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.cpu.quirks = quirks;
+    }
+
+    /// XO-CHIP programs get 64 KiB of RAM. Swaps the memory out, so call this before `load_rom`.
+    pub fn set_xo_chip(&mut self, enabled: bool) {
+        let size = if enabled { XO_RAM_SIZE } else { RAM_SIZE };
+        self.memory = Memory::with_size(size);
+        self.clear_decode_cache();
+    }
+
+    pub fn is_xo_chip(&self) -> bool {
+        self.memory.is_xo_chip()
+    }
+
+    pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<(), Chip8Error> {
+        info!(target: "emu", "load_rom before mem: {:x?}", self.memory);
+        self.memory.load_rom(rom_data)?;
+        info!(target: "emu", "load_rom after mem: {:x?}", self.memory);
+        Ok(())
+    }
+
+    /// Fetches and executes a single instruction. Timers are not touched.
+    /// A failing instruction is recorded as the machine's fault.
+    pub fn step(&mut self) -> Result<(), Fault> {
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+        let result = match self.cache.as_mut() {
+            Some(cache) => self.cpu.tick_cached(
+                cache,
+                &mut self.memory,
+                &mut self.gpu,
+                &mut self.timers,
+                &mut self.keypad,
+                &mut self.audio,
+            ),
+            None => self.cpu.tick(
+                &mut self.memory,
+                &mut self.gpu,
+                &mut self.timers,
+                &mut self.keypad,
+                &mut self.audio,
+            ),
+        };
+        result.inspect_err(|&fault| self.fault = Some(fault))
+    }
+
+    /// Decodes each instruction once and reuses it until the program writes over it.
+    /// Runs exactly like the plain interpreter. Turns the JIT off, they can't share the memory.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        match (enabled, self.cache.is_some()) {
+            (true, false) => {
+                #[cfg(feature = "jit")]
+                self.set_jit(false);
+                self.cache = Some(DecodeCache::new())
+            }
+            (false, true) => self.cache = None,
+            _ => {}
+        }
+    }
+
+    pub fn decode_cache(&self) -> Option<&DecodeCache> {
+        self.cache.as_ref()
+    }
+
+    /// Needed after changing `memory.ram` by hand, everything else keeps the cache up to date.
+    /// Clears the JIT's blocks as well.
+    pub fn clear_decode_cache(&mut self) {
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+        #[cfg(feature = "jit")]
+        if let Some(jit) = self.jit.as_mut() {
+            jit.clear();
+        }
+    }
+
+    /// Runs whole frames as compiled blocks, see `emu::jit`. Only `run_frame` uses it,
+    /// `step` and `run_frame_until` stay one instruction at a time for the debugger.
+    /// Turns the decode cache off.
+    #[cfg(feature = "jit")]
+    pub fn set_jit(&mut self, enabled: bool) {
+        match (enabled, self.jit.is_some()) {
+            (true, false) => {
+                self.cache = None;
+                self.jit = Some(Jit::new())
+            }
+            (false, true) => self.jit = None,
+            _ => {}
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn jit(&self) -> Option<&Jit> {
+        self.jit.as_ref()
+    }
+
+    /// Runs one 60 Hz frame: as many instructions as the clock speed allows,
+    /// then the frame's audio goes to the sink, followed by exactly one timer tick.
+    /// Once faulted the machine is frozen, nothing runs and the timers stop.
+    pub fn run_frame(&mut self) -> Result<(), Fault> {
+        #[cfg(feature = "jit")]
+        if let Some(mut jit) = self.jit.take() {
+            let count = self.begin_frame();
+            let result = jit.run(self, count);
+            self.jit = Some(jit);
+            if let Err(fault) = result {
+                self.fault = Some(fault);
+                return Err(fault);
+            }
+            self.end_frame();
+            return Ok(());
+        }
+        self.run_frame_until(|_| false).map(|_| ())
+    }
+
+    /// `run_frame`, asking `should_stop` before every instruction. Once it says yes the rest
+    /// of the frame's instructions are skipped, the timers still tick.
+    /// Returns whether it stopped early. This is what the debugger's breakpoints hook into.
+    pub fn run_frame_until(
+        &mut self,
+        mut should_stop: impl FnMut(&Machine) -> bool,
+    ) -> Result<bool, Fault> {
+        let mut stopped = false;
+        for _ in 0..self.begin_frame() {
+            if should_stop(self) {
+                stopped = true;
+                break;
+            }
+            self.step()?;
+        }
+        self.end_frame();
+        Ok(stopped)
+    }
+
+    /// Start of a frame, returns how many instructions it gets. For running a frame an
+    /// instruction at a time with `step`, then `end_frame`.
+    pub(crate) fn begin_frame(&mut self) -> u32 {
+        self.cpu.vblank = true;
+        self.clock.instructions_for_frame()
+    }
+
+    /// Audio out, one timer tick and the rewind snapshot, after the frame's instructions.
+    pub(crate) fn end_frame(&mut self) {
+        let samples = self.synth.render_frame(&self.audio, self.sound_active());
+        if let Err(e) = self.sink.write(&samples) {
+            // a broken sink shouldn't take the game down with it
+            warn!(target: "audio", "Audio output failed, muting: {}", e);
+            self.sink = Box::new(NullSink);
+        }
+        self.timers.tick();
+        if let Some(mut rewind) = self.rewind.take() {
+            rewind.push(&self.snapshot());
+            self.rewind = Some(rewind);
+        }
+    }
+
+    /// Starts recording the last `frames` frames so they can be stepped back through.
+    /// Recording starts from the machine as it is now.
+    pub fn enable_rewind(&mut self, frames: usize) {
+        let mut rewind = RewindBuffer::new(frames);
+        rewind.push(&self.snapshot());
+        self.rewind = Some(rewind);
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Goes back one frame. False once the history is used up (or rewind is off).
+    pub fn rewind_frame(&mut self) -> bool {
+        match self.rewind.as_mut().and_then(RewindBuffer::step_back) {
+            Some(snapshot) => {
+                self.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// How many frames `rewind_frame` can still go back.
+    pub fn rewind_frames(&self) -> usize {
+        self.rewind.as_ref().map_or(0, RewindBuffer::len)
+    }
+
+    /// Sends the audio somewhere, see `sound::WavSink` and `sound::LiveSink`.
+    /// Samples are mono 16 bit at `sound::SAMPLE_RATE`.
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.sink = sink;
+    }
+
+    /// Tells the sink there's nothing more coming, call it before exiting.
+    pub fn finish_audio(&mut self) -> io::Result<()> {
+        self.sink.finish()
+    }
+
+    /// A copy of the whole machine as it is right now.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cpu: self.cpu.clone(),
+            memory: self.memory.clone(),
+            gpu: self.gpu.clone(),
+            timers: self.timers.clone(),
+            keypad: self.keypad.clone(),
+            audio: self.audio,
+        }
+    }
+
+    /// Puts the machine back into a snapshot's state. Any fault is cleared, the clock speed
+    /// and audio sink stay as they are.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.cpu = snapshot.cpu;
+        // whoever was watching the bus still is
+        let watched = self.memory.is_watched();
+        self.memory = snapshot.memory;
+        self.memory.watch(watched);
+        self.gpu = snapshot.gpu;
+        self.timers = snapshot.timers;
+        self.keypad = snapshot.keypad;
+        self.audio = snapshot.audio;
+        self.fault = None;
+        self.clear_decode_cache();
+    }
+
+    /// The machine in the binary save state format, see state.rs.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.snapshot().to_bytes()
+    }
+
+    /// Restores a state written by `save_state`. The machine is untouched if it doesn't load.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        self.restore(Snapshot::from_bytes(bytes)?);
+        Ok(())
+    }
+
+    /// The error that halted the machine, if any.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    /// Lets the machine run again after a fault, e.g. once the state has been patched up.
+    pub fn clear_fault(&mut self) {
+        self.fault = None;
+    }
+
+    /// Presses (true) or releases (false) one of the keys 0x0-0xF.
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        if pressed {
+            self.keypad.press(key);
+        } else {
+            self.keypad.release(key);
+        }
+    }
+
+    /// Row-major pixels, `true` is lit. See `screen_size` for the dimensions.
+    /// Only the first bitplane, which is all there is outside of XO-CHIP.
+    pub fn framebuffer(&self) -> &[bool] {
+        &self.gpu.screen
+    }
+
+    /// Row-major colour indices 0-3, combining both XO-CHIP bitplanes.
+    pub fn colour_framebuffer(&self) -> Vec<u8> {
+        (0..self.gpu.screen.len())
+            .map(|index| self.gpu.colour(index))
+            .collect()
+    }
+
+    /// (width, height) of the framebuffer in pixels, 64x32 or 128x64 in SUPER-CHIP hires mode
+    pub fn screen_size(&self) -> (usize, usize) {
+        (self.gpu.width(), self.gpu.height())
+    }
+
+    /// True once the program has run 00FD
+    pub fn has_exited(&self) -> bool {
+        self.cpu.exited
+    }
+
+    /// The beeper sounds for as long as the sound timer is non-zero.
+    pub fn sound_active(&self) -> bool {
+        self.timers.sound_timer > 0
+    }
+}
+
+#[cfg(test)]
+mod machinetests {
+    use super::Machine;
+    use crate::emu::{
+        clock::ClockSpeed,
+        error::{Chip8Error, Fault},
+        quirks::QuirksProfile,
+        rng::{Rng, RngKind},
+        sound::{AudioSink, AMPLITUDE},
+    };
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    const IBM_LOGO: &[u8] = include_bytes!("../../roms/2-ibm-logo.ch8");
+
+    #[test]
+    fn test_run_frame_ticks_timers_once() {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(10));
+        // 0x200: 1200 -> jump to self forever
+        machine.load_rom(&[0x12, 0x00]).unwrap();
+        machine.timers.delay_timer = 5;
+        machine.timers.sound_timer = 1;
+        assert!(machine.sound_active());
+        machine.run_frame().unwrap();
+        assert_eq!(machine.timers.delay_timer, 4);
+        assert!(!machine.sound_active());
+    }
+
+    #[test]
+    fn test_set_key() {
+        let mut machine = Machine::new();
+        machine.set_key(0xA, true);
+        assert!(machine.keypad.is_pressed(0xA));
+        machine.set_key(0xA, false);
+        assert!(!machine.keypad.is_pressed(0xA));
+    }
+
+    #[test]
+    fn test_ibm_logo_draws() {
+        let mut machine = Machine::new();
+        machine.load_rom(IBM_LOGO).unwrap();
+        assert!(machine.framebuffer().iter().all(|&pixel| !pixel));
+        for _ in 0..30 {
+            machine.run_frame().unwrap();
+        }
+        let (width, height) = machine.screen_size();
+        assert_eq!(machine.framebuffer().len(), width * height);
+        assert!(machine.framebuffer().iter().any(|&pixel| pixel));
+    }
+
+    #[test]
+    fn test_fault_halts_machine() {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(10));
+        // 0x200: 6005 -> V0 = 5, 0x202: 00EE with an empty stack
+        machine.load_rom(&[0x60, 0x05, 0x00, 0xEE]).unwrap();
+        machine.timers.delay_timer = 5;
+        let fault = Fault {
+            pc: 0x202,
+            opcode: 0x00EE,
+            error: Chip8Error::StackUnderflow,
+        };
+        assert_eq!(machine.run_frame(), Err(fault));
+        assert_eq!(machine.fault(), Some(fault));
+        assert_eq!(machine.cpu.registers[0], 5);
+        assert_eq!(machine.cpu.program_counter, 0x202);
+        // frozen: timers don't tick either
+        assert_eq!(machine.run_frame(), Err(fault));
+        assert_eq!(machine.timers.delay_timer, 5);
+        machine.clear_fault();
+        assert!(machine.fault().is_none());
+    }
+
+    #[test]
+    fn test_display_wait_draws_once_per_frame() {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(10));
+        machine.set_quirks(QuirksProfile::CosmacVip.quirks());
+        // 0x200: D001, 0x202: D001, 0x204: 1204 -> spin
+        machine
+            .load_rom(&[0xD0, 0x01, 0xD0, 0x01, 0x12, 0x04])
+            .unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu.program_counter, 0x202);
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu.program_counter, 0x204);
+    }
+
+    #[test]
+    fn test_rom_too_large() {
+        let mut machine = Machine::new();
+        let rom = vec![0; 4096];
+        assert!(matches!(
+            machine.load_rom(&rom),
+            Err(Chip8Error::RomTooLarge { size: 4096, .. })
+        ));
+    }
+
+    #[test]
+    fn test_xo_chip_memory() {
+        let mut machine = Machine::new();
+        let rom = vec![0; 8000];
+        assert!(machine.load_rom(&rom).is_err());
+        machine.set_xo_chip(true);
+        assert!(machine.is_xo_chip());
+        machine.load_rom(&rom).unwrap();
+        // 0x200: F000 FFF0 -> I = 0xFFF0, 0x204: F155 -> store v0 and v1 way past 4 KiB
+        machine
+            .load_rom(&[0xF0, 0x00, 0xFF, 0xF0, 0xF1, 0x55])
+            .unwrap();
+        machine.cpu.registers[1] = 0x42;
+        machine.step().unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.memory.ram[0xFFF1], 0x42);
+    }
+
+    #[test]
+    fn test_xo_chip_opcodes_need_xo_chip_mode() {
+        for op in [0x00D1u16, 0x5012, 0x5013, 0xF000, 0xF101, 0xF002, 0xF03A] {
+            let mut machine = Machine::new();
+            machine.load_rom(&op.to_be_bytes()).unwrap();
+            let fault = machine.step().unwrap_err();
+            assert_eq!(fault.error, Chip8Error::UnknownOpcode { op, pc: 0x200 });
+        }
+        // 0x200: 3000 skips, over 2 bytes of F000 here and all 4 of F000 NNNN in XO-CHIP mode
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34];
+        let mut machine = Machine::new();
+        machine.load_rom(&rom).unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.cpu.program_counter, 0x204);
+        let mut machine = Machine::new();
+        machine.set_xo_chip(true);
+        machine.load_rom(&rom).unwrap();
+        machine.step().unwrap();
+        assert_eq!(machine.cpu.program_counter, 0x206);
+    }
+
+    /// Keeps everything it's given where the test can still see it.
+    #[derive(Debug, Default)]
+    struct RecordingSink(Rc<RefCell<Vec<i16>>>);
+
+    impl AudioSink for RecordingSink {
+        fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+            self.0.borrow_mut().extend_from_slice(samples);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sound_timer_beeps() {
+        let mut machine = Machine::new();
+        let recorded = Rc::new(RefCell::new(Vec::new()));
+        machine.set_audio_sink(Box::new(RecordingSink(Rc::clone(&recorded))));
+        // 0x200: 6002 -> V0 = 2, 0x202: F018 -> sound timer = V0, 0x204: 1204 -> spin
+        machine
+            .load_rom(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04])
+            .unwrap();
+        for _ in 0..3 {
+            machine.run_frame().unwrap();
+        }
+        let recorded = recorded.borrow();
+        assert_eq!(recorded.len(), 3 * 735);
+        // two frames of beep, then quiet
+        assert!(recorded[..2 * 735].iter().all(|s| s.abs() == AMPLITUDE));
+        assert!(recorded[2 * 735..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut machine = Machine::new();
+        machine.load_rom(IBM_LOGO).unwrap();
+        // partway through drawing the logo
+        machine.run_frame().unwrap();
+        let state = machine.save_state();
+        let saved_pc = machine.cpu.program_counter;
+        for _ in 0..30 {
+            machine.run_frame().unwrap();
+        }
+        let logo = machine.framebuffer().to_vec();
+
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.cpu.program_counter, saved_pc);
+        assert_ne!(machine.framebuffer(), &logo[..]);
+        // same state, same frames, same picture
+        for _ in 0..30 {
+            machine.run_frame().unwrap();
+        }
+        assert_eq!(machine.framebuffer(), &logo[..]);
+        assert!(machine.load_state(b"garbage").is_err());
+    }
+
+    #[test]
+    fn test_rewind_undoes_frames() {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(2));
+        // 0x200: 7001 -> V0 += 1, 0x202: 1200 -> loop
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        assert!(!machine.rewind_frame());
+        machine.enable_rewind(10);
+        machine.timers.delay_timer = 20;
+        for _ in 0..4 {
+            machine.run_frame().unwrap();
+        }
+        assert_eq!(machine.cpu.registers[0], 4);
+        assert_eq!(machine.rewind_frames(), 4);
+
+        assert!(machine.rewind_frame());
+        assert_eq!(machine.cpu.registers[0], 3);
+        assert_eq!(machine.timers.delay_timer, 17);
+        while machine.rewind_frame() {}
+        assert_eq!(machine.cpu.registers[0], 0);
+
+        // and time moves forward again from there
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu.registers[0], 1);
+        assert_eq!(machine.rewind_frames(), 1);
+    }
+
+    #[test]
+    fn test_decode_cache_matches_the_interpreter() {
+        // 0x20C starts out as LD V3, 5 and gets rewritten to ADD V3, 1 by F155 until V3 is 8
+        let self_modifying = [
+            0x60, 0x73, 0x61, 0x01, 0x12, 0x0C, 0xA2, 0x0C, 0xF1, 0x55, 0x12, 0x0C, 0x63, 0x05,
+            0x33, 0x08, 0x12, 0x06, 0x12, 0x12,
+        ];
+        for rom in [&self_modifying[..], IBM_LOGO] {
+            let mut plain = Machine::with_speed(ClockSpeed::Ipf(7));
+            let mut cached = Machine::with_speed(ClockSpeed::Ipf(7));
+            cached.set_decode_cache(true);
+            plain.load_rom(rom).unwrap();
+            cached.load_rom(rom).unwrap();
+            for _ in 0..30 {
+                plain.run_frame().unwrap();
+                cached.run_frame().unwrap();
+                assert_eq!(plain.snapshot(), cached.snapshot());
+            }
+        }
+        let mut machine = Machine::new();
+        machine.set_decode_cache(true);
+        machine.load_rom(&self_modifying).unwrap();
+        let start = machine.snapshot();
+        for _ in 0..10 {
+            machine.run_frame().unwrap();
+        }
+        assert_eq!(machine.cpu.registers[3], 8);
+        assert_eq!(machine.cpu.program_counter, 0x212);
+        let (hits, misses) = machine.decode_cache().unwrap().stats();
+        assert!(hits > misses);
+
+        // restoring swaps the memory out from under the cache, 0x20C is LD V3, 5 again
+        machine.restore(start);
+        for _ in 0..4 {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.cpu.registers[3], 5);
+    }
+
+    #[test]
+    fn test_rng_is_part_of_the_state() {
+        // RND V0..V3 with the full mask, then round again
+        let rom = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF, 0x12, 0x00];
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(5));
+        machine.load_rom(&rom).unwrap();
+        machine.run_frame().unwrap();
+        let state = machine.save_state();
+        machine.run_frame().unwrap();
+        let after = machine.cpu.registers;
+
+        // loading the state picks the sequence up where it was
+        machine.load_state(&state).unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.cpu.registers, after);
+
+        // and a fresh machine with the same seed rolls the same numbers
+        let mut other = Machine::with_speed(ClockSpeed::Ipf(5));
+        other.load_rom(&rom).unwrap();
+        other.run_frame().unwrap();
+        other.run_frame().unwrap();
+        assert_eq!(other.cpu.registers, after);
+
+        other.cpu.rng = Rng::new(RngKind::Xorshift, 1);
+        other.run_frame().unwrap();
+        machine.run_frame().unwrap();
+        assert_ne!(other.cpu.registers, machine.cpu.registers);
+    }
+}
@@ -1,7 +1,14 @@
+use super::error::Chip8Error;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::ops::Range;
+
 /* Chip8 Memory layout
-0x000-0x04F - Chip 8 interpreter (contains font set in emu)       0 -   79
-0x050-0x0A0 - Used for the built in 4x5 pixel font set (0-F)    080 -  160
+0x000-0x04F - Built in 4x5 pixel font set (0-F)                   0 -   79
+0x050-0x0EF - SUPER-CHIP 8x10 pixel font set (0-F)               80 -  239
+0x0F0-0x1FF - Unused, the interpreter lived here on real hardware
 0x200-0xFFF - Program ROM and work RAM                          512 - 4096
+              (0x200-0xFFFF on XO-CHIP, which has 64 KiB)
 
 0x200-0xE8F
 "final 352 bytes of memory are reserved for “variables and display refresh"
@@ -30,30 +37,131 @@ https://github.com/mattmikolay/chip-8/wiki/CHIP%E2%80%908-Technical-Reference#re
 /// | Reserved for  |
 /// |  interpreter  |
 /// +---------------+= 0x000 (0) Start of Chip-8 RAM
-
 pub const RAM_SIZE: usize = 4096;
+/// XO-CHIP addresses the full 16 bits
+pub const XO_RAM_SIZE: usize = 65536;
 pub const ROM_START_ADDRESS: usize = 0x200; // 512
 pub const ROM_MAX_SIZE: usize = RAM_SIZE - ROM_START_ADDRESS;
 pub const FONT_MAX_SIZE: usize = 80;
+/// FX29 points I at FONT_START_ADDRESS + 5 * digit
+pub const FONT_START_ADDRESS: usize = 0x000;
+/// FX30 points I at BIG_FONT_START_ADDRESS + 10 * digit
+pub const BIG_FONT_START_ADDRESS: usize = 0x050;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// One trip an instruction made over the bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub addr: usize,
+    pub len: usize,
+}
+
+impl Access {
+    /// Whether any byte of it falls in `start..=end`.
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.len > 0 && self.addr <= end && self.addr + self.len > start
+    }
+}
+
+/// What went over the bus since it was last drained, None while nobody's watching.
+/// Not part of the machine's state: clones start empty, it's never saved and never compared.
+#[derive(Debug, Default)]
+pub struct BusLog(RefCell<Option<Vec<Access>>>);
 
-#[derive(Debug)]
+impl Clone for BusLog {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for BusLog {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for BusLog {}
+
+/// The span of RAM handed out for writing since `take_written` last looked, so the decode
+/// cache knows what to throw away. Not part of the machine's state either.
+#[derive(Debug, Default)]
+struct Written(Option<Range<usize>>);
+
+impl Written {
+    fn mark(&mut self, range: Range<usize>) {
+        self.0 = Some(match self.0.take() {
+            Some(old) => old.start.min(range.start)..old.end.max(range.end),
+            None => range,
+        });
+    }
+}
+
+impl Clone for Written {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for Written {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Written {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Memory {
-    pub ram: [u8; RAM_SIZE],
+    /// RAM_SIZE bytes, or XO_RAM_SIZE in XO-CHIP mode
+    pub ram: Vec<u8>,
+    #[serde(skip)]
+    bus: BusLog,
+    #[serde(skip)]
+    written: Written,
 }
 
 impl Default for Memory {
     fn default() -> Self {
-        Self { ram: [0; RAM_SIZE] }
+        Self {
+            ram: vec![0; RAM_SIZE],
+            bus: BusLog::default(),
+            written: Written::default(),
+        }
     }
 }
 
 impl Memory {
     pub fn new() -> Self {
-        let mut new_memory = Self::default();
+        Self::with_size(RAM_SIZE)
+    }
+
+    /// Zeroed RAM of `size` bytes with the fonts loaded, e.g. `XO_RAM_SIZE`.
+    pub fn with_size(size: usize) -> Self {
+        let mut new_memory = Self {
+            ram: vec![0; size],
+            bus: BusLog::default(),
+            written: Written::default(),
+        };
         new_memory.load_font();
         new_memory
     }
 
+    /// XO-CHIP mode is just the bigger RAM.
+    pub fn is_xo_chip(&self) -> bool {
+        self.ram.len() == XO_RAM_SIZE
+    }
+
+    /// Largest ROM that fits between ROM_START_ADDRESS and the end of RAM.
+    pub fn rom_max_size(&self) -> usize {
+        self.ram.len() - ROM_START_ADDRESS
+    }
+
     pub fn load_font(&mut self) {
         assert!(
             FONTS.len() <= FONT_MAX_SIZE,
@@ -61,22 +169,103 @@ impl Memory {
             FONTS.len(),
             FONT_MAX_SIZE
         );
-        self.ram[0..FONTS.len()].copy_from_slice(&FONTS);
+        let start = FONT_START_ADDRESS;
+        self.ram[start..start + FONTS.len()].copy_from_slice(&FONTS);
+        let start = BIG_FONT_START_ADDRESS;
+        self.ram[start..start + BIG_FONTS.len()].copy_from_slice(&BIG_FONTS);
+        self.written
+            .mark(FONT_START_ADDRESS..start + BIG_FONTS.len());
     }
 
     // Loads ROM bytes into RAM
-    pub fn load_rom(&mut self, rom_data: &[u8]) {
-        assert!(
-            rom_data.len() <= ROM_MAX_SIZE,
-            "ROM size ({}) exceeds max allowed size ({}).",
-            rom_data.len(),
-            ROM_MAX_SIZE
-        );
+    pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<(), Chip8Error> {
+        if rom_data.len() > self.rom_max_size() {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom_data.len(),
+                max: self.rom_max_size(),
+            });
+        }
 
         // Copy ROM bytes into RAM, starting at 0x200
         let start = ROM_START_ADDRESS;
         let end = ROM_START_ADDRESS + rom_data.len();
         self.ram[start..end].copy_from_slice(rom_data);
+        self.written.mark(start..end);
+        Ok(())
+    }
+
+    /// The bus instructions go through: like `slice`, but watchpoints get to see it.
+    pub fn read(&self, addr: usize, len: usize) -> Result<&[u8], Chip8Error> {
+        let bytes = self.slice(addr, len)?;
+        self.record(AccessKind::Read, addr, len);
+        Ok(bytes)
+    }
+
+    /// Like `slice_mut`, but watchpoints get to see it.
+    pub fn write(&mut self, addr: usize, len: usize) -> Result<&mut [u8], Chip8Error> {
+        self.checked_range(addr, len)?;
+        self.record(AccessKind::Write, addr, len);
+        self.slice_mut(addr, len)
+    }
+
+    /// Starts or stops logging what goes through `read` and `write`.
+    pub fn watch(&mut self, on: bool) {
+        let log = self.bus.0.get_mut();
+        match (on, log.is_some()) {
+            (true, false) => *log = Some(Vec::new()),
+            (false, true) => *log = None,
+            _ => {}
+        }
+    }
+
+    pub fn is_watched(&self) -> bool {
+        self.bus.0.borrow().is_some()
+    }
+
+    /// Everything logged since the last call, oldest first.
+    pub fn take_accesses(&self) -> Vec<Access> {
+        self.bus
+            .0
+            .borrow_mut()
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record(&self, kind: AccessKind, addr: usize, len: usize) {
+        if let Some(log) = self.bus.0.borrow_mut().as_mut() {
+            log.push(Access { kind, addr, len });
+        }
+    }
+
+    /// Bounds checked view of `len` bytes starting at `addr`.
+    /// Doesn't go on the bus, for instruction fetches and looking around in a debugger.
+    pub fn slice(&self, addr: usize, len: usize) -> Result<&[u8], Chip8Error> {
+        let range = self.checked_range(addr, len)?;
+        Ok(&self.ram[range])
+    }
+
+    /// Bounds checked mutable view of `len` bytes starting at `addr`.
+    pub fn slice_mut(&mut self, addr: usize, len: usize) -> Result<&mut [u8], Chip8Error> {
+        let range = self.checked_range(addr, len)?;
+        self.written.mark(range.clone());
+        Ok(&mut self.ram[range])
+    }
+
+    /// Everything `write`, `slice_mut` or a load could have changed since the last call.
+    /// Poking `ram` directly doesn't count.
+    pub fn take_written(&mut self) -> Option<Range<usize>> {
+        self.written.0.take()
+    }
+
+    fn checked_range(&self, addr: usize, len: usize) -> Result<Range<usize>, Chip8Error> {
+        match addr.checked_add(len) {
+            Some(end) if end <= self.ram.len() => Ok(addr..end),
+            // report the first address that doesn't exist
+            _ => Err(Chip8Error::MemoryOutOfBounds {
+                addr: addr.max(self.ram.len()),
+            }),
+        }
     }
 
     pub fn print_memory(&self) {
@@ -90,7 +279,11 @@ impl Memory {
     }
 
     pub fn print_memory_bytes(&self, start: usize, num_bytes: usize) {
-        let end = start + num_bytes;
+        if !log_enabled!(target: "mem", log::Level::Debug) {
+            return;
+        }
+        let start = start.min(self.ram.len());
+        let end = (start + num_bytes).min(self.ram.len());
         for (i, byte) in self.ram[start..end].iter().enumerate() {
             if i % 16 == 0 {
                 debug!(target:"mem", "\ndumping memory {:04X}: ", i+start);
@@ -155,3 +348,86 @@ pub const FONTS: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
+
+/// # SUPER-CHIP large FONT encoding
+/// Same idea as FONTS but 8x10 pixels, one byte per row, so 10 bytes per digit.
+/// SCHIP 1.1 only shipped 0-9, A-F are the ones Octo and XO-CHIP use.
+pub const BIG_FONTS: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[cfg(test)]
+mod memtests {
+    use super::{Access, AccessKind, Memory};
+
+    #[test]
+    fn test_bus_log() {
+        let mut memory = Memory::new();
+        memory.write(0x300, 2).unwrap();
+        assert!(memory.take_accesses().is_empty());
+
+        memory.watch(true);
+        memory.write(0x300, 2).unwrap()[1] = 7;
+        assert_eq!(memory.read(0x301, 1).unwrap(), [7]);
+        // fetches and peeks aren't on the bus
+        memory.slice(0x200, 2).unwrap();
+        assert!(memory.read(0xFFFF, 1).is_err());
+        let accesses = memory.take_accesses();
+        assert_eq!(
+            accesses,
+            vec![
+                Access {
+                    kind: AccessKind::Write,
+                    addr: 0x300,
+                    len: 2
+                },
+                Access {
+                    kind: AccessKind::Read,
+                    addr: 0x301,
+                    len: 1
+                },
+            ]
+        );
+        assert!(accesses[0].overlaps(0x301, 0x3FF));
+        assert!(!accesses[0].overlaps(0x302, 0x3FF));
+        assert!(memory.take_accesses().is_empty());
+
+        // the log isn't part of the state
+        assert_eq!(memory.clone(), memory);
+        assert!(!memory.clone().is_watched());
+    }
+
+    #[test]
+    fn test_take_written() {
+        let mut memory = Memory::new();
+        assert_eq!(memory.take_written(), Some(0x000..0x0F0)); // the fonts
+        assert_eq!(memory.take_written(), None);
+
+        memory.load_rom(&[0x12, 0x00]).unwrap();
+        memory.write(0x300, 3).unwrap();
+        memory.read(0x400, 1).unwrap();
+        assert_eq!(memory.take_written(), Some(0x200..0x303));
+        memory.slice_mut(0x310, 1).unwrap();
+        assert_eq!(memory.take_written(), Some(0x310..0x311));
+        assert!(memory.write(0xFFFF, 2).is_err());
+        assert_eq!(memory.take_written(), None);
+        // ranges that wrap around usize don't slip through
+        assert!(memory.slice(usize::MAX, 2).is_err());
+        assert!(memory.slice_mut(0x200, usize::MAX).is_err());
+    }
+}
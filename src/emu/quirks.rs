@@ -0,0 +1,222 @@
+// The handful of opcodes that CHIP-8 interpreters never agreed on.
+// https://chip8.gulrak.net/#quirk-table has the full story, roms written for one
+// interpreter often break on another unless these match.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift vY into vX. Off: vX is shifted in place and vY is ignored
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register. Off: I is unchanged
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to XNN + vX instead of NNN + v0
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset vF to 0
+    pub vf_reset: bool,
+    /// DXYN cuts sprites off at the screen edge. Off: they wrap around to the other side
+    pub clip_sprites: bool,
+    /// DXYN waits for the next 60 Hz frame, so at most one sprite is drawn per frame
+    pub display_wait: bool,
+    /// DXYN in hires mode sets vF to the number of rows that hit something or got clipped off
+    /// the bottom, like SUPER-CHIP 1.1. Off: vF is 1 for any collision
+    #[serde(default)]
+    pub hires_collision_rows: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        QuirksProfile::default().quirks()
+    }
+}
+
+impl Quirks {
+    /// Applies a per-ROM quirks file on top of `self`.
+    ///
+    /// One `key = value` per line, `#` starts a comment. `profile = <name>` resets every
+    /// quirk to that profile, the other keys are the field names above set to true/false:
+    ///
+    /// ```text
+    /// profile = schip
+    /// clip_sprites = false
+    /// ```
+    pub fn with_overrides(mut self, config: &str) -> Result<Self, String> {
+        for (number, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or(format!("line {}: expected `key = value`", number + 1))?;
+            if key == "profile" {
+                self = value.parse::<QuirksProfile>()?.quirks();
+                continue;
+            }
+            let flag = match value {
+                "true" | "on" | "1" => true,
+                "false" | "off" | "0" => false,
+                _ => return Err(format!("line {}: {} is not true/false", number + 1, value)),
+            };
+            match key {
+                "shift_uses_vy" => self.shift_uses_vy = flag,
+                "load_store_increments_i" => self.load_store_increments_i = flag,
+                "jump_uses_vx" => self.jump_uses_vx = flag,
+                "vf_reset" => self.vf_reset = flag,
+                "clip_sprites" => self.clip_sprites = flag,
+                "display_wait" => self.display_wait = flag,
+                "hires_collision_rows" => self.hires_collision_rows = flag,
+                _ => return Err(format!("line {}: unknown quirk {}", number + 1, key)),
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Named sets of quirks matching the interpreters ROMs were written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuirksProfile {
+    /// The original 1977 interpreter
+    CosmacVip,
+    /// HP48 calculators, where most 90s games come from
+    Chip48,
+    /// SUPER-CHIP 1.1
+    Schip,
+    /// What most modern emulators (and Octo) do, and what this one always did
+    #[default]
+    Modern,
+    /// Octo's XO-CHIP extension
+    XoChip,
+}
+
+impl QuirksProfile {
+    pub const ALL: [QuirksProfile; 5] = [
+        QuirksProfile::CosmacVip,
+        QuirksProfile::Chip48,
+        QuirksProfile::Schip,
+        QuirksProfile::Modern,
+        QuirksProfile::XoChip,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QuirksProfile::CosmacVip => "vip",
+            QuirksProfile::Chip48 => "chip48",
+            QuirksProfile::Schip => "schip",
+            QuirksProfile::Modern => "modern",
+            QuirksProfile::XoChip => "xochip",
+        }
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            QuirksProfile::CosmacVip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: true,
+                clip_sprites: true,
+                display_wait: true,
+                hires_collision_rows: false,
+            },
+            QuirksProfile::Chip48 => Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                vf_reset: false,
+                clip_sprites: true,
+                display_wait: false,
+                hires_collision_rows: false,
+            },
+            QuirksProfile::Schip => Quirks {
+                hires_collision_rows: true,
+                ..QuirksProfile::Chip48.quirks()
+            },
+            QuirksProfile::Modern | QuirksProfile::XoChip => Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                vf_reset: false,
+                clip_sprites: false,
+                display_wait: false,
+                hires_collision_rows: false,
+            },
+        }
+    }
+}
+
+impl fmt::Display for QuirksProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for QuirksProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        match s.as_str() {
+            "vip" | "cosmac" | "cosmac-vip" | "chip8" | "chip-8" => Ok(QuirksProfile::CosmacVip),
+            "chip48" | "chip-48" => Ok(QuirksProfile::Chip48),
+            "schip" | "superchip" | "super-chip" => Ok(QuirksProfile::Schip),
+            "modern" | "octo" => Ok(QuirksProfile::Modern),
+            "xochip" | "xo-chip" => Ok(QuirksProfile::XoChip),
+            _ => {
+                let names: Vec<_> = QuirksProfile::ALL.iter().map(|p| p.name()).collect();
+                Err(format!(
+                    "unknown quirks profile {}, expected one of {}",
+                    s,
+                    names.join(", ")
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod quirkstests {
+    use super::{Quirks, QuirksProfile};
+
+    #[test]
+    fn test_profile_names_round_trip() {
+        for profile in QuirksProfile::ALL {
+            assert_eq!(profile.name().parse::<QuirksProfile>(), Ok(profile));
+        }
+        assert_eq!("COSMAC-VIP".parse(), Ok(QuirksProfile::CosmacVip));
+        assert!("gameboy".parse::<QuirksProfile>().is_err());
+    }
+
+    #[test]
+    fn test_default_is_modern() {
+        assert_eq!(Quirks::default(), QuirksProfile::Modern.quirks());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config = "
+            # pong from some 90s archive
+            profile = schip
+            clip_sprites = false   # wraps the ball
+        ";
+        let quirks = Quirks::default().with_overrides(config).unwrap();
+        assert_eq!(
+            quirks,
+            Quirks {
+                clip_sprites: false,
+                ..QuirksProfile::Schip.quirks()
+            }
+        );
+    }
+
+    #[test]
+    fn test_bad_overrides() {
+        let quirks = Quirks::default();
+        assert!(quirks.with_overrides("profile = nes").is_err());
+        assert!(quirks.with_overrides("vf_reset = maybe").is_err());
+        assert!(quirks.with_overrides("turbo = true").is_err());
+        assert!(quirks.with_overrides("vf_reset").is_err());
+    }
+}
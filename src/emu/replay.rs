@@ -0,0 +1,407 @@
+// Replays: every keypad press and release of a session with the frame it happened before,
+// plus everything else that decides how the ROM runs (which ROM, clock speed, quirks and the
+// CXNN seed). Playing one back from the same start gives exactly the same session, so a
+// tester's replay file is a bug report CI can run.
+//
+// Stored as JSON, the key events in the same form as a key script (see script.rs).
+use super::{
+    clock::ClockSpeed,
+    error::Chip8Error,
+    machine::Machine,
+    quirks::Quirks,
+    rng::{Rng, RngKind},
+    script::{KeyEvent, KeyScript},
+    state::{Snapshot, StateError, Versioned},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Bump this whenever a Replay field changes. A replay can hold a Snapshot too, so bump it
+/// along with STATE_VERSION.
+pub const REPLAY_VERSION: u16 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replay {
+    /// CRC-32 of the ROM, playback won't start on a different one
+    pub rom_crc32: u32,
+    pub xo_chip: bool,
+    pub speed: ClockSpeed,
+    pub quirks: Quirks,
+    pub rng: RngKind,
+    pub seed: u64,
+    /// Where the session started, a freshly loaded ROM if not set
+    pub start: Option<Snapshot>,
+    /// The clock's leftover cycles at `start`, `--hz` frames don't all run as many
+    pub start_cycles: u32,
+    /// How many frames the session ran
+    pub frames: u32,
+    /// Sorted by frame, in the order they happened
+    pub keys: Vec<KeyEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// Written by a build with a different REPLAY_VERSION
+    UnsupportedVersion(u16),
+    /// Not a replay, or a damaged one
+    Corrupt(String),
+    /// Recorded with another ROM
+    WrongRom { expected: u32, actual: u32 },
+    /// The ROM didn't load
+    Rom(Chip8Error),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "replay version {} is not supported, expected {}",
+                version, REPLAY_VERSION
+            ),
+            ReplayError::Corrupt(message) => write!(f, "not a valid replay: {}", message),
+            ReplayError::WrongRom { expected, actual } => write!(
+                f,
+                "replay was recorded with another ROM (crc32 {:08x}, this one is {:08x})",
+                expected, actual
+            ),
+            ReplayError::Rom(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    /// An empty replay of `rom` on `machine` as it's set up now, from power-on.
+    /// The machine's generator has to have been seeded with `seed`.
+    pub fn new(machine: &Machine, rom: &[u8], seed: u64) -> Self {
+        Self {
+            rom_crc32: crc32fast::hash(rom),
+            xo_chip: machine.is_xo_chip(),
+            speed: machine.speed(),
+            quirks: machine.quirks(),
+            rng: machine.cpu.rng.kind(),
+            seed,
+            start: None,
+            start_cycles: 0,
+            frames: 0,
+            keys: Vec::new(),
+        }
+    }
+
+    /// Sets `machine` up the way the session started and loads `rom`, which has to be the
+    /// recorded one. The machine is left alone if it isn't.
+    pub fn setup(&self, machine: &mut Machine, rom: &[u8]) -> Result<(), ReplayError> {
+        let actual = crc32fast::hash(rom);
+        if actual != self.rom_crc32 {
+            return Err(ReplayError::WrongRom {
+                expected: self.rom_crc32,
+                actual,
+            });
+        }
+        machine.set_speed(self.speed);
+        machine.set_xo_chip(self.xo_chip);
+        machine.set_quirks(self.quirks);
+        machine.cpu.rng = Rng::new(self.rng, self.seed);
+        machine.load_rom(rom).map_err(ReplayError::Rom)?;
+        if let Some(start) = &self.start {
+            machine.restore(start.clone());
+            machine.set_leftover_cycles(self.start_cycles);
+        }
+        Ok(())
+    }
+
+    /// The key events as a script, `KeyScript::run` for `frames` frames plays the session.
+    pub fn script(&self) -> KeyScript {
+        KeyScript::new(self.keys.clone())
+    }
+
+    pub fn to_json(&self) -> String {
+        let versioned = Versioned {
+            version: REPLAY_VERSION,
+            state: self,
+        };
+        serde_json::to_string_pretty(&versioned).expect("replays always encode")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ReplayError> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| ReplayError::Corrupt(e.to_string()))?;
+        match value.get("version").and_then(|v| v.as_u64()) {
+            Some(version) if version == REPLAY_VERSION as u64 => {}
+            Some(version) => return Err(ReplayError::UnsupportedVersion(version as u16)),
+            None => return Err(ReplayError::Corrupt("no version".to_string())),
+        }
+        let versioned: Versioned<Replay> =
+            serde_json::from_value(value).map_err(|e| ReplayError::Corrupt(e.to_string()))?;
+        let mut replay = versioned.state;
+        if let Some(start) = replay.start.take() {
+            let start = start.validate().map_err(|e| match e {
+                StateError::Corrupt(message) => ReplayError::Corrupt(message),
+                other => ReplayError::Corrupt(other.to_string()),
+            })?;
+            replay.start = Some(start);
+        }
+        // hand-edited files don't have to be in order, scripts sort themselves the same way
+        replay.keys = replay.script().events().to_vec();
+        Ok(replay)
+    }
+}
+
+/// Builds a replay while a session runs. The frontend tells it about every keypad change
+/// and every frame, forwards or backwards.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    replay: Replay,
+}
+
+impl Recorder {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay }
+    }
+
+    /// The frame the next key event belongs to, how many have run so far.
+    pub fn frame(&self) -> u32 {
+        self.replay.frames
+    }
+
+    /// Call for every `Machine::set_key`, repeats included, FX0A can tell them apart.
+    pub fn key(&mut self, key: u8, pressed: bool) {
+        self.replay.keys.push(KeyEvent {
+            frame: self.replay.frames,
+            key,
+            pressed,
+        });
+    }
+
+    /// Call after every `Machine::run_frame`.
+    pub fn end_frame(&mut self) {
+        self.replay.frames += 1;
+    }
+
+    /// Call after every successful `Machine::rewind_frame`. What happened in the frame
+    /// that was undone didn't happen after all.
+    pub fn rewind_frame(&mut self) {
+        self.replay.frames = self.replay.frames.saturating_sub(1);
+        let frames = self.replay.frames;
+        self.replay.keys.retain(|event| event.frame < frames);
+    }
+
+    /// Starts over from `machine` as it is now. For anything that changes the machine
+    /// behind the keypad's back: a save state loaded in the middle, or a debugger stopping
+    /// part way through a frame, stepping or poking at it.
+    pub fn restart(&mut self, machine: &Machine) {
+        self.replay.start = Some(machine.snapshot());
+        self.replay.start_cycles = machine.leftover_cycles();
+        self.replay.frames = 0;
+        self.replay.keys.clear();
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+}
+
+/// Plays a replay's keys into a machine one frame at a time, for frontends that run the
+/// frames themselves. Headless runs can just use `Replay::script`.
+#[derive(Debug, Clone)]
+pub struct Player {
+    script: KeyScript,
+    frames: u32,
+    frame: u32,
+}
+
+impl Player {
+    pub fn new(replay: &Replay) -> Self {
+        Self {
+            script: replay.script(),
+            frames: replay.frames,
+            frame: 0,
+        }
+    }
+
+    /// Presses and releases the keys due before the next frame. False once the replay is
+    /// over, the keypad is the frontend's again.
+    pub fn start_frame(&self, machine: &mut Machine) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+        for event in self.script.at(self.frame) {
+            machine.set_key(event.key, event.pressed);
+        }
+        true
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn rewind_frame(&mut self) {
+        self.frame = self.frame.saturating_sub(1);
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.frames
+    }
+}
+
+#[cfg(test)]
+mod replaytests {
+    use super::{Player, Recorder, Replay, ReplayError, REPLAY_VERSION};
+    use crate::emu::{
+        quirks::QuirksProfile,
+        rng::{Rng, RngKind},
+        ClockSpeed, Debugger, Machine,
+    };
+
+    /// Waits for a key with FX0A, rolls V1 with C1FF, draws its digit and goes round again.
+    const ROM: [u8; 12] = [
+        0xF0, 0x0A, 0xC1, 0xFF, 0xF1, 0x29, 0xD0, 0x05, 0x70, 0x05, 0x12, 0x00,
+    ];
+
+    fn machine() -> Machine {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(7));
+        machine.set_quirks(QuirksProfile::CosmacVip.quirks());
+        machine.cpu.rng = Rng::new(RngKind::Counter, 99);
+        machine.load_rom(&ROM).unwrap();
+        machine
+    }
+
+    /// Runs a session, pressing keys as it goes, and records it.
+    fn record() -> (Machine, Replay) {
+        let mut machine = machine();
+        let mut recorder = Recorder::new(Replay::new(&machine, &ROM, 99));
+        for frame in 0..40 {
+            if frame % 6 == 2 || frame % 6 == 4 {
+                let key = (frame / 6) as u8;
+                let pressed = frame % 6 == 2;
+                machine.set_key(key, pressed);
+                recorder.key(key, pressed);
+            }
+            machine.run_frame().unwrap();
+            recorder.end_frame();
+        }
+        (machine, recorder.replay().clone())
+    }
+
+    #[test]
+    fn test_replay_reproduces_the_session() {
+        let (recorded, replay) = record();
+        assert_eq!(replay.frames, 40);
+        assert_eq!(replay.keys.len(), 13);
+
+        let replay = Replay::from_json(&replay.to_json()).unwrap();
+        let mut machine = Machine::new();
+        replay.setup(&mut machine, &ROM).unwrap();
+        replay.script().run(&mut machine, replay.frames).unwrap();
+        assert_eq!(machine.snapshot(), recorded.snapshot());
+
+        // and the same again a frame at a time
+        let mut machine = Machine::new();
+        replay.setup(&mut machine, &ROM).unwrap();
+        let mut player = Player::new(&replay);
+        while player.start_frame(&mut machine) {
+            machine.run_frame().unwrap();
+            player.end_frame();
+        }
+        assert_eq!(machine.snapshot(), recorded.snapshot());
+    }
+
+    #[test]
+    fn test_recording_across_the_debugger() {
+        // 500 Hz, so how far into its 60 cycle pattern the clock is matters too
+        let mut machine = machine();
+        machine.set_speed(ClockSpeed::Hz(500));
+        let mut recorder = Recorder::new(Replay::new(&machine, &ROM, 99));
+        let mut debugger = Debugger::new();
+        debugger.toggle_breakpoint(0x206);
+        let mut stops = 0;
+        for frame in 0..40 {
+            if frame % 6 == 2 || frame % 6 == 4 {
+                let key = (frame / 6) as u8;
+                let pressed = frame % 6 == 2;
+                machine.set_key(key, pressed);
+                recorder.key(key, pressed);
+            }
+            // what the terminal does: step off the breakpoint, then carry on
+            if debugger.is_paused() {
+                debugger.step(&mut machine);
+                recorder.restart(&machine);
+                debugger.resume();
+            }
+            let stop = debugger.run_frame(&mut machine);
+            recorder.end_frame();
+            if stop.is_some() {
+                stops += 1;
+                recorder.restart(&machine);
+            }
+        }
+        assert!(stops > 2);
+        let replay = Replay::from_json(&recorder.replay().to_json()).unwrap();
+        assert!(replay.frames < 40);
+
+        let mut replayed = Machine::new();
+        replay.setup(&mut replayed, &ROM).unwrap();
+        assert_eq!(replayed.leftover_cycles(), replay.start_cycles);
+        replay.script().run(&mut replayed, replay.frames).unwrap();
+        assert_eq!(replayed.snapshot(), machine.snapshot());
+        assert_eq!(replayed.leftover_cycles(), machine.leftover_cycles());
+    }
+
+    #[test]
+    fn test_wrong_rom() {
+        let (_, replay) = record();
+        let mut machine = Machine::new();
+        let error = replay.setup(&mut machine, &[0x12, 0x00]).unwrap_err();
+        assert!(matches!(error, ReplayError::WrongRom { .. }));
+        assert_eq!(machine.memory.ram[0x200], 0);
+    }
+
+    #[test]
+    fn test_rewind_drops_the_undone_frames() {
+        let mut machine = machine();
+        machine.enable_rewind(10);
+        let mut recorder = Recorder::new(Replay::new(&machine, &ROM, 99));
+        for frame in 0..5 {
+            recorder.key(frame as u8, true);
+            machine.run_frame().unwrap();
+            recorder.end_frame();
+        }
+        assert!(machine.rewind_frame());
+        recorder.rewind_frame();
+        assert!(machine.rewind_frame());
+        recorder.rewind_frame();
+        assert_eq!(recorder.frame(), 3);
+        let frames: Vec<u32> = recorder.replay().keys.iter().map(|e| e.frame).collect();
+        assert_eq!(frames, [0, 1, 2]);
+
+        // a loaded state starts the recording over
+        recorder.restart(&machine);
+        assert_eq!(recorder.frame(), 0);
+        assert!(recorder.replay().keys.is_empty());
+        assert_eq!(recorder.replay().start, Some(machine.snapshot()));
+    }
+
+    #[test]
+    fn test_bad_files() {
+        assert!(matches!(
+            Replay::from_json("{}"),
+            Err(ReplayError::Corrupt(_))
+        ));
+        assert!(matches!(
+            Replay::from_json(r#"{"version": 999}"#),
+            Err(ReplayError::UnsupportedVersion(999))
+        ));
+        let (_, replay) = record();
+        let truncated = replay.to_json().replace("\"frames\"", "\"framez\"");
+        assert!(Replay::from_json(&truncated).is_err());
+        assert!(replay
+            .to_json()
+            .contains(&format!("\"version\": {}", REPLAY_VERSION)));
+    }
+}
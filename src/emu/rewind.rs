@@ -0,0 +1,184 @@
+// Rewind: a bounded history of the last few seconds of frames, stepped back one at a time.
+// Only the newest frame is kept whole. Every older one is stored as the bytes that differ
+// from the frame after it, which between two frames is usually a handful of registers,
+// a few pixels and maybe a byte or two of RAM.
+use super::state::Snapshot;
+use bincode::config::{self, Configuration, Fixint, LittleEndian, NoLimit};
+use std::collections::VecDeque;
+
+/// 10 seconds at 60 frames a second
+pub const DEFAULT_REWIND_FRAMES: usize = 600;
+
+/// Fixed width integers keep every field at the same offset from frame to frame,
+/// so the byte diff only picks up what actually changed.
+const ENCODING: Configuration<LittleEndian, Fixint, NoLimit> =
+    config::standard().with_fixed_int_encoding();
+
+/// Unchanged bytes between two changes shorter than this don't start a new run,
+/// the run header would cost more than the bytes.
+const MIN_GAP: usize = 8;
+
+/// How to turn a frame back into the one before it.
+#[derive(Debug)]
+struct Delta {
+    /// Length of the older frame, they only differ when RAM or the resolution changed size
+    len: usize,
+    /// (offset, older bytes) for every stretch that changed
+    runs: Vec<(usize, Vec<u8>)>,
+}
+
+impl Delta {
+    fn between(older: &[u8], newer: &[u8]) -> Self {
+        if older.len() != newer.len() {
+            return Self {
+                len: older.len(),
+                runs: vec![(0, older.to_vec())],
+            };
+        }
+        let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+        let mut last_change = None;
+        for (offset, (&old, &new)) in older.iter().zip(newer).enumerate() {
+            if old == new {
+                continue;
+            }
+            match (last_change, runs.last_mut()) {
+                (Some(last), Some((start, bytes))) if offset - last <= MIN_GAP => {
+                    bytes.extend_from_slice(&older[*start + bytes.len()..=offset]);
+                }
+                _ => runs.push((offset, vec![old])),
+            }
+            last_change = Some(offset);
+        }
+        Self {
+            len: older.len(),
+            runs,
+        }
+    }
+
+    /// Turns `newer` back into the older frame, in place.
+    fn apply(&self, newer: &mut Vec<u8>) {
+        newer.resize(self.len, 0);
+        for (offset, bytes) in &self.runs {
+            newer[*offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.runs.iter().map(|(_, bytes)| bytes.len()).sum()
+    }
+}
+
+#[derive(Debug)]
+pub struct RewindBuffer {
+    capacity: usize,
+    /// The newest frame, encoded
+    head: Option<Vec<u8>>,
+    /// Oldest first, the back turns `head` into the frame before it
+    history: VecDeque<Delta>,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_FRAMES)
+    }
+}
+
+impl RewindBuffer {
+    /// Keeps up to `capacity` frames to step back through.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            head: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Records the latest frame. The oldest one falls off once the buffer is full.
+    pub fn push(&mut self, snapshot: &Snapshot) {
+        let newer =
+            bincode::serde::encode_to_vec(snapshot, ENCODING).expect("snapshots always encode");
+        if let Some(older) = self.head.take() {
+            self.history.push_back(Delta::between(&older, &newer));
+            if self.history.len() > self.capacity {
+                self.history.pop_front();
+            }
+        }
+        self.head = Some(newer);
+    }
+
+    /// Drops the newest frame and returns the one before it, None when there's nothing left.
+    pub fn step_back(&mut self) -> Option<Snapshot> {
+        let delta = self.history.pop_back()?;
+        let head = self.head.as_mut().expect("history without a head");
+        delta.apply(head);
+        let (snapshot, _) =
+            bincode::serde::decode_from_slice(head, ENCODING).expect("rewind frames always decode");
+        Some(snapshot)
+    }
+
+    /// How many frames back we can go.
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.head = None;
+        self.history.clear();
+    }
+
+    /// Rough number of bytes held, the newest frame plus all the deltas.
+    pub fn memory_used(&self) -> usize {
+        self.head.as_ref().map_or(0, Vec::len) + self.history.iter().map(Delta::size).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod rewindtests {
+    use super::{Delta, RewindBuffer};
+    use crate::Machine;
+
+    #[test]
+    fn test_delta_round_trip() {
+        let older: Vec<u8> = (1..=20).collect();
+        let mut newer = older.clone();
+        newer[1] = 0;
+        newer[3] = 0;
+        newer[19] = 0;
+        let delta = Delta::between(&older, &newer);
+        // 1 and 3 are close enough to share a run
+        assert_eq!(delta.runs, vec![(1, vec![2, 3, 4]), (19, vec![20])]);
+        delta.apply(&mut newer);
+        assert_eq!(newer, older);
+
+        let mut longer = vec![0; 30];
+        Delta::between(&older, &longer).apply(&mut longer);
+        assert_eq!(longer, older);
+    }
+
+    #[test]
+    fn test_step_back_through_frames() {
+        let mut machine = Machine::new();
+        // 0x200: 7001 -> V0 += 1, 0x202: 1200 -> loop
+        machine.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut rewind = RewindBuffer::new(3);
+        for _ in 0..5 {
+            machine.step().unwrap();
+            machine.step().unwrap();
+            rewind.push(&machine.snapshot());
+        }
+        assert_eq!(machine.cpu.registers[0], 5);
+        // only the newest 3 steps back are kept
+        assert_eq!(rewind.len(), 3);
+        for v0 in [4, 3, 2] {
+            let snapshot = rewind.step_back().unwrap();
+            assert_eq!(snapshot.cpu.registers[0], v0);
+        }
+        assert!(rewind.step_back().is_none());
+        // the deltas are tiny next to a whole frame
+        assert!(rewind.memory_used() < 2 * machine.save_state().len());
+    }
+}
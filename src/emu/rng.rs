@@ -0,0 +1,151 @@
+// Where CXNN's random numbers come from. The generator lives in the cpu, so save states,
+// rewind and replays carry on with exactly the same sequence.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What a machine is seeded with unless told otherwise, so runs are repeatable by default.
+pub const DEFAULT_SEED: u64 = 0xC8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RngKind {
+    /// xorshift64*, good enough for games and fast
+    #[default]
+    Xorshift,
+    /// A tiny 8-bit generator: a counter bumped on every call, added into the last result and
+    /// rotated. Streaky, and it repeats after at most 65536 numbers. Files from when it was
+    /// called CosmacVip still load.
+    #[serde(alias = "CosmacVip")]
+    Counter,
+}
+
+impl fmt::Display for RngKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RngKind::Xorshift => "xorshift",
+            RngKind::Counter => "counter",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for RngKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "xorshift" => Ok(RngKind::Xorshift),
+            "counter" => Ok(RngKind::Counter),
+            // asked for, but the real thing can't be done without the VIP's interpreter bytes
+            "vip" | "cosmac" | "cosmac-vip" => Err(format!(
+                "{} isn't available: the COSMAC VIP's CXNN mixes in bytes of its own \
+                 interpreter, which this emulator doesn't have. Use xorshift or counter",
+                s
+            )),
+            _ => Err(format!("unknown rng {}, expected xorshift or counter", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rng {
+    kind: RngKind,
+    /// The whole generator state, the counter one only uses the low 16 bits
+    state: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(RngKind::default(), DEFAULT_SEED)
+    }
+}
+
+impl Rng {
+    /// The same `kind` and `seed` always give the same numbers.
+    pub fn new(kind: RngKind, seed: u64) -> Self {
+        let state = match kind {
+            // xorshift gets stuck on 0, splitmix spreads the seed out and never gives 0 back
+            RngKind::Xorshift => splitmix64(seed).max(1),
+            RngKind::Counter => seed & 0xFFFF,
+        };
+        Self { kind, state }
+    }
+
+    pub fn kind(&self) -> RngKind {
+        self.kind
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        match self.kind {
+            RngKind::Xorshift => {
+                let mut x = self.state;
+                x ^= x >> 12;
+                x ^= x << 25;
+                x ^= x >> 27;
+                self.state = x;
+                (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+            }
+            RngKind::Counter => {
+                let [counter, last, ..] = self.state.to_le_bytes();
+                let counter = counter.wrapping_add(1);
+                let value = last.wrapping_add(counter).rotate_right(1) ^ counter;
+                self.state = u16::from_le_bytes([counter, value]) as u64;
+                value
+            }
+        }
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod rngtests {
+    use super::{Rng, RngKind};
+
+    fn bytes(mut rng: Rng, count: usize) -> Vec<u8> {
+        (0..count).map(|_| rng.next_byte()).collect()
+    }
+
+    #[test]
+    fn test_same_seed_same_numbers() {
+        for kind in [RngKind::Xorshift, RngKind::Counter] {
+            assert_eq!(bytes(Rng::new(kind, 7), 64), bytes(Rng::new(kind, 7), 64));
+            assert_ne!(bytes(Rng::new(kind, 7), 64), bytes(Rng::new(kind, 8), 64));
+        }
+        // a zero seed doesn't get xorshift stuck
+        assert!(bytes(Rng::new(RngKind::Xorshift, 0), 8)
+            .iter()
+            .any(|&b| b != 0));
+    }
+
+    #[test]
+    fn test_spread() {
+        for kind in [RngKind::Xorshift, RngKind::Counter] {
+            let mut counts = [0u32; 256];
+            for byte in bytes(Rng::new(kind, 1), 256 * 64) {
+                counts[byte as usize] += 1;
+            }
+            // every value turns up, none of them hogs the output
+            assert!(counts.iter().all(|&c| c > 0), "{} misses values", kind);
+            assert!(counts.iter().all(|&c| c < 64 * 4), "{} is lopsided", kind);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("counter".parse(), Ok(RngKind::Counter));
+        let error = "vip".parse::<RngKind>().unwrap_err();
+        assert!(error.contains("interpreter"), "{}", error);
+        // what older save states and replays call it
+        let old: RngKind = serde_json::from_str("\"CosmacVip\"").unwrap();
+        assert_eq!(old, RngKind::Counter);
+        assert_eq!(serde_json::to_string(&old).unwrap(), "\"Counter\"");
+        assert_eq!("Xorshift".parse(), Ok(RngKind::Xorshift));
+        assert!("dice".parse::<RngKind>().is_err());
+    }
+}
@@ -0,0 +1,395 @@
+// The framebuffer as a file: plain text, ANSI coloured text, PBM or PNG.
+// Text is one character per pixel at the display's own resolution, that's what the golden
+// images are. PBM and PNG can be scaled up, and PNG and ANSI take a palette.
+use super::gpu::Gpu;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// RGB for colours 0-3
+pub type Palette = [[u8; 3]; 4];
+
+/// The same as the terminal: cyan, magenta and white on black
+pub const PALETTE: Palette = [[0, 0, 0], [0, 255, 255], [255, 0, 255], [255, 255, 255]];
+
+/// Biggest `ImageOptions::scale`, a 128x64 screen comes out at 2048x1024
+pub const MAX_SCALE: usize = 16;
+
+/// Characters for colours 0-3 in the text format
+const ASCII: [char; 4] = ['.', '#', '+', '@'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// One character per pixel, `.` is off, `#` is on. Easy to diff.
+    Ascii,
+    /// Plain (P1) portable bitmap, 1 wherever any plane is lit
+    Pbm,
+    Png,
+    /// Half blocks in 24-bit colour, two pixels to a character. `cat` it in a terminal.
+    Ansi,
+}
+
+/// How PNG, PBM and ANSI screenshots look.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageOptions {
+    /// PNG and ANSI only, PBM is black and white
+    pub palette: Palette,
+    /// Every pixel becomes a `scale` x `scale` square, PNG and PBM only. 1 to MAX_SCALE.
+    pub scale: usize,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            palette: PALETTE,
+            scale: 1,
+        }
+    }
+}
+
+impl ImageFormat {
+    /// Guesses from the extension, text unless it's .pbm, .png or .ans.
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str());
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("pbm") => ImageFormat::Pbm,
+            Some("png") => ImageFormat::Png,
+            Some("ans" | "ansi") => ImageFormat::Ansi,
+            _ => ImageFormat::Ascii,
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ImageFormat::Ascii => "ascii",
+            ImageFormat::Pbm => "pbm",
+            ImageFormat::Png => "png",
+            ImageFormat::Ansi => "ansi",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ascii" | "txt" | "text" => Ok(ImageFormat::Ascii),
+            "pbm" => Ok(ImageFormat::Pbm),
+            "png" => Ok(ImageFormat::Png),
+            "ansi" | "ans" => Ok(ImageFormat::Ansi),
+            _ => Err(format!(
+                "unknown image format {}, expected ascii, pbm, png or ansi",
+                s
+            )),
+        }
+    }
+}
+
+/// Four colours, `rrggbb` each and comma separated, `#` optional. Two colours are off and on,
+/// XO-CHIP's other planes get the on colour too.
+pub fn parse_palette(s: &str) -> Result<Palette, String> {
+    let colours = s
+        .split(',')
+        .map(|colour| {
+            let hex = colour.trim().trim_start_matches('#');
+            match (hex.len(), u32::from_str_radix(hex, 16)) {
+                (6, Ok(rgb)) => Ok([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]),
+                _ => Err(format!("{} isn't an rrggbb colour", colour.trim())),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match colours[..] {
+        [off, on] => Ok([off, on, on, on]),
+        [a, b, c, d] => Ok([a, b, c, d]),
+        _ => Err(format!(
+            "a palette is 2 or 4 colours, not {}",
+            colours.len()
+        )),
+    }
+}
+
+/// The screen in `format` with the default look, ready to be written out.
+pub fn encode(gpu: &Gpu, format: ImageFormat) -> Vec<u8> {
+    encode_with(gpu, format, &ImageOptions::default())
+}
+
+pub fn encode_with(gpu: &Gpu, format: ImageFormat, options: &ImageOptions) -> Vec<u8> {
+    match format {
+        ImageFormat::Ascii => ascii(gpu).into_bytes(),
+        ImageFormat::Pbm => pbm(gpu, options.scale),
+        ImageFormat::Png => png(gpu, options),
+        ImageFormat::Ansi => ansi(gpu, &options.palette).into_bytes(),
+    }
+}
+
+/// One line per row. XO-CHIP's other colours are `+` (second plane) and `@` (both).
+pub fn ascii(gpu: &Gpu) -> String {
+    let width = gpu.width();
+    let mut text = String::with_capacity(gpu.screen.len() + gpu.height());
+    for index in 0..gpu.screen.len() {
+        text.push(ASCII[gpu.colour(index) as usize]);
+        if (index + 1) % width == 0 {
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Colour (plane bits) of every pixel a row at a time, each pixel and row `scale` times over.
+fn scaled_rows(gpu: &Gpu, scale: usize) -> impl Iterator<Item = Vec<u8>> + '_ {
+    let scale = scale.clamp(1, MAX_SCALE);
+    let width = gpu.width();
+    (0..gpu.height() * scale).map(move |y| {
+        let row = y / scale * width;
+        (0..width * scale)
+            .map(|x| gpu.colour(row + x / scale))
+            .collect()
+    })
+}
+
+pub fn pbm(gpu: &Gpu, scale: usize) -> Vec<u8> {
+    let scale = scale.clamp(1, MAX_SCALE);
+    let mut text = format!("P1\n{} {}\n", gpu.width() * scale, gpu.height() * scale);
+    for row in scaled_rows(gpu, scale) {
+        let bits: Vec<&str> = row
+            .iter()
+            .map(|&c| if c != 0 { "1" } else { "0" })
+            .collect();
+        text += &bits.join(" ");
+        text.push('\n');
+    }
+    text.into_bytes()
+}
+
+/// Every row is a top and bottom pixel per character, the top one as the foreground of `▀`.
+/// The colours are only sent when they change.
+pub fn ansi(gpu: &Gpu, palette: &Palette) -> String {
+    let width = gpu.width();
+    let mut text = String::new();
+    for y in (0..gpu.height()).step_by(2) {
+        let mut last = None;
+        for x in 0..width {
+            let top = gpu.colour(y * width + x) as usize;
+            let bottom = gpu.colour((y + 1) * width + x) as usize;
+            if last != Some((top, bottom)) {
+                let ([r, g, b], [br, bg, bb]) = (palette[top], palette[bottom]);
+                text += &format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                    r, g, b, br, bg, bb
+                );
+                last = Some((top, bottom));
+            }
+            text.push('▀');
+        }
+        text += "\x1b[0m\n";
+    }
+    text
+}
+
+/// An indexed colour PNG, 2 bits a pixel. Uncompressed, screens are small even scaled up.
+pub fn png(gpu: &Gpu, options: &ImageOptions) -> Vec<u8> {
+    let scale = options.scale.clamp(1, MAX_SCALE);
+    let (width, height) = (gpu.width() * scale, gpu.height() * scale);
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 2 bits per pixel, palette, default compression, filter and no interlacing
+    header.extend_from_slice(&[2, 3, 0, 0, 0]);
+
+    // every row starts with its filter type, 0 is none, then four pixels to a byte
+    let mut pixels = Vec::with_capacity((width / 4 + 1) * height);
+    for row in scaled_rows(gpu, scale) {
+        pixels.push(0);
+        pixels.extend(row.chunks(4).map(|four| {
+            four.iter()
+                .enumerate()
+                .fold(0, |byte, (i, &colour)| byte | colour << (6 - 2 * i))
+        }));
+    }
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"PLTE", &options.palette.concat());
+    chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32fast::hash(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// A zlib stream made of stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        out.push(last as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod screenshottests {
+    use super::{
+        adler32, ansi, ascii, encode, encode_with, parse_palette, pbm, png, ImageFormat,
+        ImageOptions, PALETTE,
+    };
+    use crate::emu::gpu::Gpu;
+
+    fn test_gpu() -> Gpu {
+        let mut gpu = Gpu::new();
+        gpu.screen[0] = true;
+        gpu.second_plane[1] = true;
+        gpu.screen[65] = true;
+        gpu.second_plane[65] = true;
+        gpu
+    }
+
+    #[test]
+    fn test_ascii() {
+        let text = ascii(&test_gpu());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert!(lines.iter().all(|line| line.len() == 64));
+        assert!(lines[0].starts_with("#+.."));
+        assert!(lines[1].starts_with(".@.."));
+    }
+
+    #[test]
+    fn test_pbm() {
+        let bytes = pbm(&test_gpu(), 1);
+        let text = String::from_utf8(bytes).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("64 32"));
+        assert!(lines.next().unwrap().starts_with("1 1 0 0"));
+        assert!(lines.next().unwrap().starts_with("0 1 0 0"));
+        assert_eq!(lines.count(), 30);
+    }
+
+    #[test]
+    fn test_png() {
+        let mut gpu = test_gpu();
+        gpu.set_hires(true);
+        let bytes = png(&gpu, &ImageOptions::default());
+        assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&bytes[12..16], b"IHDR");
+        assert_eq!(&bytes[16..24], &[0, 0, 0, 128, 0, 0, 0, 64]);
+        // the header chunk's crc, as any PNG reader will check it
+        let crc = crc32fast::hash(&bytes[12..29]);
+        assert_eq!(&bytes[29..33], &crc.to_be_bytes());
+        assert!(bytes.ends_with(b"IEND\xAE\x42\x60\x82"));
+    }
+
+    #[test]
+    fn test_scale() {
+        let gpu = test_gpu();
+        let text = String::from_utf8(pbm(&gpu, 3)).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[1], "192 96");
+        assert_eq!(lines.len(), 2 + 96);
+        assert!(lines[2].starts_with("1 1 1 1 1 1 0 0 0"));
+        assert_eq!(lines[4], lines[2]);
+        assert!(lines[5].starts_with("0 0 0 1 1 1 0 0 0"));
+
+        let options = ImageOptions {
+            palette: [[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12]],
+            scale: 2,
+        };
+        let bytes = png(&gpu, &options);
+        assert_eq!(&bytes[16..25], &[0, 0, 0, 128, 0, 0, 0, 64, 2]);
+        // PLTE straight after the 25 byte IHDR chunk
+        assert_eq!(&bytes[33..41], b"\0\0\0\x0cPLTE");
+        assert_eq!(&bytes[41..53], &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        // after PLTE's crc, IDAT's one stored block: zlib header, block header, then the rows
+        let rows = &bytes[57 + 8 + 2 + 5..];
+        let row = 1 + 128 / 4;
+        // 1 1 2 2 then 0s, each pixel twice, 2 bits each
+        assert_eq!(&rows[..3], &[0, 0b0101_1010, 0]);
+        assert_eq!(&rows[row..row + 3], &[0, 0b0101_1010, 0]);
+        // 0 0 3 3 on the second row
+        assert_eq!(&rows[2 * row..2 * row + 3], &[0, 0b0000_1111, 0]);
+    }
+
+    #[test]
+    fn test_ansi() {
+        let text = ansi(&test_gpu(), &PALETTE);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 16);
+        // colour 1 over 0, 2 over 3, then 0 over 0 for the rest of the row
+        let first = "\x1b[38;2;0;255;255m\x1b[48;2;0;0;0m▀\
+                     \x1b[38;2;255;0;255m\x1b[48;2;255;255;255m▀\
+                     \x1b[38;2;0;0;0m\x1b[48;2;0;0;0m▀";
+        assert!(lines[0].starts_with(first), "{:?}", lines[0]);
+        assert_eq!(lines[0].matches('▀').count(), 64);
+        assert!(lines[0].ends_with("▀\x1b[0m"));
+        assert_eq!(lines[1].matches("\x1b[38").count(), 1);
+    }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(parse_palette("000000,00FFFF,#ff00ff, ffffff"), Ok(PALETTE));
+        let black_on_white = [[255, 255, 255], [0, 0, 0], [0, 0, 0], [0, 0, 0]];
+        assert_eq!(parse_palette("ffffff,000000"), Ok(black_on_white));
+        assert!(parse_palette("ffffff").is_err());
+        assert!(parse_palette("ffffff,000000,ff0000").is_err());
+        assert!(parse_palette("fff,000").is_err());
+        assert!(parse_palette("ffffff,black").is_err());
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(ImageFormat::from_path("out/screen.PNG"), ImageFormat::Png);
+        assert_eq!(ImageFormat::from_path("screen.pbm"), ImageFormat::Pbm);
+        assert_eq!(ImageFormat::from_path("screen.txt"), ImageFormat::Ascii);
+        assert_eq!(ImageFormat::from_path("screen.ans"), ImageFormat::Ansi);
+        assert_eq!("png".parse(), Ok(ImageFormat::Png));
+        assert!("gif".parse::<ImageFormat>().is_err());
+        let gpu = test_gpu();
+        assert_eq!(encode(&gpu, ImageFormat::Ascii), ascii(&gpu).into_bytes());
+        let big = ImageOptions {
+            scale: 4,
+            ..ImageOptions::default()
+        };
+        // text is always one character a pixel, it's what the golden images are
+        assert_eq!(
+            encode_with(&gpu, ImageFormat::Ascii, &big),
+            ascii(&gpu).into_bytes()
+        );
+        assert_eq!(encode_with(&gpu, ImageFormat::Pbm, &big), pbm(&gpu, 4));
+    }
+}
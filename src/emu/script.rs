@@ -0,0 +1,159 @@
+// Scripted input for headless runs. One key transition per line, the frame it happens
+// before, the key and whether it goes down or up:
+//
+//   # start the game, then hold 6 for half a second
+//   10 5 down
+//   12 5 up
+//   60 6 down
+//   90 6 up
+//
+// Keys are the hex digits 0-F, `#` starts a comment.
+use super::{error::Fault, input::KEY_COUNT, machine::Machine};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEvent {
+    pub frame: u32,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    /// 1-based
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    /// Sorted by frame, events on the same frame stay in the order they were written
+    events: Vec<KeyEvent>,
+}
+
+impl KeyScript {
+    pub fn new(mut events: Vec<KeyEvent>) -> Self {
+        events.sort_by_key(|event| event.frame);
+        Self { events }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut events = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| ScriptError {
+                line: index + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let [frame, key, state] = words[..] else {
+                if words.is_empty() {
+                    continue;
+                }
+                return Err(error("expected <frame> <key> down|up".to_string()));
+            };
+            let frame = frame
+                .parse()
+                .map_err(|_| error(format!("{} is not a frame number", frame)))?;
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(key) if (key as usize) < KEY_COUNT => key,
+                _ => return Err(error(format!("{} is not a key, use 0-F", key))),
+            };
+            let pressed = match state.to_ascii_lowercase().as_str() {
+                "down" => true,
+                "up" => false,
+                _ => return Err(error(format!("expected down or up, got {}", state))),
+            };
+            events.push(KeyEvent {
+                frame,
+                key,
+                pressed,
+            });
+        }
+        Ok(Self::new(events))
+    }
+
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+
+    /// The events that happen right before `frame` runs.
+    pub fn at(&self, frame: u32) -> impl Iterator<Item = &KeyEvent> {
+        let start = self.events.partition_point(|event| event.frame < frame);
+        self.events[start..]
+            .iter()
+            .take_while(move |event| event.frame == frame)
+    }
+
+    /// Runs `frames` frames from where the machine is, pressing keys as it goes.
+    /// Frame numbers count from 0 at the start of the run. Stops at the first fault.
+    pub fn run(&self, machine: &mut Machine, frames: u32) -> Result<(), Fault> {
+        for frame in 0..frames {
+            for event in self.at(frame) {
+                machine.set_key(event.key, event.pressed);
+            }
+            machine.run_frame()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod scripttests {
+    use super::{KeyEvent, KeyScript};
+    use crate::emu::{ClockSpeed, Machine};
+
+    #[test]
+    fn test_parse() {
+        let script =
+            KeyScript::parse("# comment\n\n20 f up\n10 5 down # press\n20 A Down\n").unwrap();
+        let event = |frame, key, pressed| KeyEvent {
+            frame,
+            key,
+            pressed,
+        };
+        assert_eq!(
+            script.events(),
+            [
+                event(10, 5, true),
+                event(20, 0xF, false),
+                event(20, 0xA, true)
+            ]
+        );
+        assert_eq!(script.at(20).count(), 2);
+        assert_eq!(script.at(15).count(), 0);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = KeyScript::parse("1 2 down\n3 G up").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(KeyScript::parse("x 1 down").is_err());
+        assert!(KeyScript::parse("1 1 sideways").is_err());
+        assert!(KeyScript::parse("1 1").is_err());
+        assert!(KeyScript::parse("1 10 up").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        // LD V0, K then JP to itself: waits for a key press and release, then spins
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(10));
+        machine.load_rom(&[0xF0, 0x0A, 0x12, 0x02]).unwrap();
+        let script = KeyScript::parse("3 7 down\n5 7 up").unwrap();
+        script.run(&mut machine, 5).unwrap();
+        assert_eq!(machine.cpu.program_counter, 0x200);
+        // frames count from the start of each run, this time round 7 goes up before frame 5
+        script.run(&mut machine, 6).unwrap();
+        assert_eq!(machine.cpu.program_counter, 0x202);
+        assert_eq!(machine.cpu.registers[0], 7);
+    }
+}
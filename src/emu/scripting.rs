@@ -0,0 +1,496 @@
+// Rhai scripts that drive a machine, so QA can write automated playthroughs and checks without
+// recompiling: `chip8 script test.rhai rom.ch8`. Only built with the `scripting` feature.
+//
+//   run(n), run()              n frames, or one
+//   step()                     one instruction, the timers don't move
+//   frame()                    frames run so far
+//   v(x), set_v(x, n)          V0-VF, likewise i, pc, dt and st, plus sp() and stack()
+//   peek(addr), poke(addr, n)  a byte of memory
+//   press(key), release(key)   the keypad, 0-15
+//   screen()                   the screen as text, one line per row, see screenshot::ascii
+//   pixel(x, y)                a pixel's colour, 0 is off
+//   screenshot(path)           PNG, PBM, ANSI or text by the extension, in the run's
+//                              --palette and --scale
+//   fault(), exited()          what halted the machine (or ()), whether it ran 00FD
+//   assert(cond), assert(cond, message), assert_eq(actual, expected)
+//   assert_screen(path)        the screen has to match a screenshot::ascii file. With
+//                              CHIP8_BLESS=1 in the environment it writes the file instead
+//
+// A script can also define hooks for run and step to call:
+//   fn on_frame(frame) { ... }           after every frame
+//   fn on_instruction(pc, opcode) { ... }  before every instruction, a lot slower
+//
+// A fault stops the script with an error, like a failed assert. Rhai's try/catch can catch both.
+use super::{
+    input::KEY_COUNT,
+    machine::Machine,
+    screenshot::{self, ImageFormat, ImageOptions},
+};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, NativeCallContext, AST};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::rc::Rc;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    /// 1-based, where the script was when it went wrong
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "line {}, column {}: {}", line, column, self.message)
+            }
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(mut error: Box<EvalAltResult>) -> Self {
+        let position = error.take_position();
+        let message = match *error {
+            // what the script or one of the functions above threw, as is
+            EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+            error => error.to_string(),
+        };
+        Self {
+            line: position.line(),
+            column: position.position(),
+            message,
+        }
+    }
+}
+
+/// What the script's functions share.
+struct State {
+    machine: Machine,
+    image: ImageOptions,
+    frames: i64,
+    on_frame: bool,
+    on_instruction: bool,
+}
+
+/// A compiled script, ready to run against a machine.
+#[derive(Debug, Clone)]
+pub struct Script {
+    ast: AST,
+}
+
+impl Script {
+    pub fn compile(source: &str) -> Result<Self, ScriptError> {
+        let ast = Engine::new()
+            .compile(source)
+            .map_err(|e| ScriptError::from(Box::new(e.into())))?;
+        Ok(Self { ast })
+    }
+
+    /// Runs the script from the top against `machine`, which is left however the script
+    /// left it, error or not. `screenshot()` draws with `image`.
+    pub fn run(&self, machine: &mut Machine, image: &ImageOptions) -> Result<(), ScriptError> {
+        let hook = |name: &str, params: usize| {
+            self.ast
+                .iter_functions()
+                .any(|f| f.name == name && f.params.len() == params)
+        };
+        let state = Rc::new(RefCell::new(State {
+            machine: std::mem::take(machine),
+            image: *image,
+            frames: 0,
+            on_frame: hook("on_frame", 1),
+            on_instruction: hook("on_instruction", 2),
+        }));
+        let engine = engine(&state);
+        let result = engine.run_ast(&self.ast);
+        drop(engine);
+        let state = Rc::into_inner(state).expect("the engine is gone");
+        *machine = state.into_inner().machine;
+        result.map_err(ScriptError::from)
+    }
+}
+
+/// An engine with the functions at the top of the file, all working on `state`.
+fn engine(state: &Rc<RefCell<State>>) -> Engine {
+    let mut engine = Engine::new();
+
+    let s = state.clone();
+    engine.register_fn("run", move |context: NativeCallContext, frames: i64| {
+        run(&context, &s, frames)
+    });
+    let s = state.clone();
+    engine.register_fn("run", move |context: NativeCallContext| {
+        run(&context, &s, 1)
+    });
+    let s = state.clone();
+    engine.register_fn("step", move |context: NativeCallContext| step(&context, &s));
+    let s = state.clone();
+    engine.register_fn("frame", move || s.borrow().frames);
+
+    let s = state.clone();
+    engine.register_fn("v", move |x: i64| -> ScriptResult<i64> {
+        Ok(s.borrow().machine.cpu.registers[register(x)?] as i64)
+    });
+    let s = state.clone();
+    engine.register_fn("set_v", move |x: i64, value: i64| -> ScriptResult<()> {
+        s.borrow_mut().machine.cpu.registers[register(x)?] = fits(value, 0xFF)? as u8;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("i", move || s.borrow().machine.cpu.index_register as i64);
+    let s = state.clone();
+    engine.register_fn("set_i", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().machine.cpu.index_register = fits(value, 0xFFFF)?;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("pc", move || s.borrow().machine.cpu.program_counter as i64);
+    let s = state.clone();
+    engine.register_fn("set_pc", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().machine.cpu.program_counter = fits(value, 0xFFFF)?;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("dt", move || s.borrow().machine.timers.delay_timer as i64);
+    let s = state.clone();
+    engine.register_fn("set_dt", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().machine.timers.delay_timer = fits(value, 0xFF)? as u8;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("st", move || s.borrow().machine.timers.sound_timer as i64);
+    let s = state.clone();
+    engine.register_fn("set_st", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().machine.timers.sound_timer = fits(value, 0xFF)? as u8;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("sp", move || s.borrow().machine.cpu.stack_pointer as i64);
+    let s = state.clone();
+    engine.register_fn("stack", move || -> Array {
+        let cpu = &s.borrow().machine.cpu;
+        let depth = cpu.stack_pointer.min(cpu.stack.len());
+        cpu.stack[..depth]
+            .iter()
+            .map(|&a| Dynamic::from(a as i64))
+            .collect()
+    });
+
+    let s = state.clone();
+    engine.register_fn("peek", move |addr: i64| -> ScriptResult<i64> {
+        let state = s.borrow();
+        let bytes = state.machine.memory.slice(addr.max(0) as usize, 1);
+        let bytes = bytes.map_err(|_| format!("{:#x} is outside memory", addr))?;
+        Ok(bytes[0] as i64)
+    });
+    let s = state.clone();
+    engine.register_fn("poke", move |addr: i64, value: i64| -> ScriptResult<()> {
+        let value = fits(value, 0xFF)? as u8;
+        let mut state = s.borrow_mut();
+        let bytes = state.machine.memory.slice_mut(addr.max(0) as usize, 1);
+        bytes.map_err(|_| format!("{:#x} is outside memory", addr))?[0] = value;
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("press", move |key: i64| -> ScriptResult<()> {
+        s.borrow_mut().machine.set_key(keypad_key(key)?, true);
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("release", move |key: i64| -> ScriptResult<()> {
+        s.borrow_mut().machine.set_key(keypad_key(key)?, false);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("screen", move || screenshot::ascii(&s.borrow().machine.gpu));
+    let s = state.clone();
+    engine.register_fn("pixel", move |x: i64, y: i64| -> ScriptResult<i64> {
+        let gpu = &s.borrow().machine.gpu;
+        let (width, height) = (gpu.width() as i64, gpu.height() as i64);
+        if !(0..width).contains(&x) || !(0..height).contains(&y) {
+            return Err(format!("({}, {}) is off the {}x{} screen", x, y, width, height).into());
+        }
+        Ok(gpu.colour((y * width + x) as usize) as i64)
+    });
+    let s = state.clone();
+    engine.register_fn("screenshot", move |path: &str| -> ScriptResult<()> {
+        let state = s.borrow();
+        let format = ImageFormat::from_path(path);
+        let image = screenshot::encode_with(&state.machine.gpu, format, &state.image);
+        fs::write(path, image).map_err(|e| format!("{}: {}", path, e).into())
+    });
+    let s = state.clone();
+    engine.register_fn("fault", move || -> Dynamic {
+        match s.borrow().machine.fault() {
+            Some(fault) => fault.to_string().into(),
+            None => Dynamic::UNIT,
+        }
+    });
+    let s = state.clone();
+    engine.register_fn("exited", move || s.borrow().machine.has_exited());
+
+    engine.register_fn("assert", |cond: bool| -> ScriptResult<()> {
+        match cond {
+            true => Ok(()),
+            false => Err("assertion failed".into()),
+        }
+    });
+    engine.register_fn("assert", |cond: bool, message: &str| -> ScriptResult<()> {
+        match cond {
+            true => Ok(()),
+            false => Err(format!("assertion failed: {}", message).into()),
+        }
+    });
+    engine.register_fn(
+        "assert_eq",
+        |actual: Dynamic, expected: Dynamic| -> ScriptResult<()> {
+            let same = actual.type_name() == expected.type_name()
+                && actual.to_string() == expected.to_string();
+            match same {
+                true => Ok(()),
+                false => Err(format!("assertion failed: {} != {}", actual, expected).into()),
+            }
+        },
+    );
+    let s = state.clone();
+    engine.register_fn("assert_screen", move |path: &str| -> ScriptResult<()> {
+        let actual = screenshot::ascii(&s.borrow().machine.gpu);
+        if std::env::var_os("CHIP8_BLESS").is_some() {
+            return fs::write(path, &actual).map_err(|e| format!("{}: {}", path, e).into());
+        }
+        let expected = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        match expected == actual {
+            true => Ok(()),
+            false => Err(format!("the screen doesn't match {}, it's\n{}", path, actual).into()),
+        }
+    });
+
+    engine
+}
+
+/// `frames` frames, calling the hooks the script has.
+fn run(context: &NativeCallContext, state: &Rc<RefCell<State>>, frames: i64) -> ScriptResult<()> {
+    for _ in 0..frames {
+        let (on_frame, on_instruction) = {
+            let state = state.borrow();
+            (state.on_frame, state.on_instruction)
+        };
+        if on_instruction {
+            // the hook can look at the machine, so it can't be borrowed while it runs
+            let count = state.borrow_mut().machine.begin_frame();
+            for _ in 0..count {
+                step(context, state)?;
+            }
+            state.borrow_mut().machine.end_frame();
+        } else {
+            let result = state.borrow_mut().machine.run_frame();
+            result.map_err(|fault| format!("machine halted: {}", fault))?;
+        }
+        let frame = {
+            let mut state = state.borrow_mut();
+            state.frames += 1;
+            state.frames
+        };
+        if on_frame {
+            let _: Dynamic = context.call_fn("on_frame", (frame,))?;
+        }
+    }
+    Ok(())
+}
+
+/// One instruction, on_instruction first.
+fn step(context: &NativeCallContext, state: &Rc<RefCell<State>>) -> ScriptResult<()> {
+    if state.borrow().on_instruction {
+        let (pc, opcode) = {
+            let machine = &state.borrow().machine;
+            let pc = machine.cpu.program_counter;
+            let opcode = machine
+                .memory
+                .slice(pc as usize, 2)
+                .map_or(0, |bytes| (bytes[0] as i64) << 8 | bytes[1] as i64);
+            (pc as i64, opcode)
+        };
+        let _: Dynamic = context.call_fn("on_instruction", (pc, opcode))?;
+    }
+    let result = state.borrow_mut().machine.step();
+    result.map_err(|fault| format!("machine halted: {}", fault).into())
+}
+
+fn register(x: i64) -> ScriptResult<usize> {
+    match x {
+        0..=15 => Ok(x as usize),
+        _ => Err(format!("there's no register V{}", x).into()),
+    }
+}
+
+fn keypad_key(key: i64) -> ScriptResult<u8> {
+    match key {
+        0..=0xF => Ok(key as u8),
+        _ => Err(format!("the keypad has keys 0 to {}, not {}", KEY_COUNT - 1, key).into()),
+    }
+}
+
+fn fits(value: i64, max: u16) -> ScriptResult<u16> {
+    match u16::try_from(value) {
+        Ok(value) if value <= max => Ok(value),
+        _ => Err(format!("{} doesn't fit in 0..={:#x}", value, max).into()),
+    }
+}
+
+#[cfg(test)]
+mod scriptingtests {
+    use super::{Script, ScriptError};
+    use crate::emu::{asm::assemble, machine::Machine, screenshot::ImageOptions};
+
+    /// V0 = 5, then counts up forever
+    const COUNTER: &str = "
+        LD V0, 5
+    loop:
+        ADD V0, 1
+        JP loop
+    ";
+
+    /// Waits for a key and draws its digit at 0, 0
+    const DIGIT: &str = "
+        LD V1, K
+        LD F, V1
+        DRW V2, V2, 5
+    wait:
+        JP wait
+    ";
+
+    fn machine(source: &str) -> Machine {
+        let mut machine = Machine::new();
+        machine.memory.load_font();
+        machine.load_rom(&assemble(source).unwrap()).unwrap();
+        machine
+    }
+
+    fn run(source: &str, script: &str) -> Result<Machine, ScriptError> {
+        let mut machine = machine(source);
+        Script::compile(script)?.run(&mut machine, &ImageOptions::default())?;
+        Ok(machine)
+    }
+
+    #[test]
+    fn test_registers_and_memory() {
+        let script = "
+            step();
+            assert_eq(v(0), 5);
+            assert_eq(pc(), 0x202);
+            step(); step();
+            assert_eq(v(0), 6);
+            set_v(0, 0xFE);
+            poke(0x203, 0x10);
+            step();
+            assert_eq(v(0), 0x0E);
+            assert_eq(peek(0x203), 0x10);
+            set_i(0x300);
+            set_dt(2);
+            run();
+            assert_eq(dt(), 1);
+            assert_eq(frame(), 1);
+            assert(fault() == (), \"still going\");
+            assert(!exited());
+        ";
+        let machine = run(COUNTER, script).unwrap();
+        assert_eq!(machine.cpu.index_register, 0x300);
+        assert_eq!(machine.memory.ram[0x203], 0x10);
+
+        let error = run(COUNTER, "set_v(16, 1);").unwrap_err();
+        assert_eq!(error.message, "there's no register V16");
+        assert!(run(COUNTER, "set_v(0, 256);").is_err());
+        assert!(run(COUNTER, "peek(0x10000);").is_err());
+    }
+
+    #[test]
+    fn test_keys_and_screen() {
+        let script = "
+            run(2);
+            assert_eq(pixel(0, 0), 0);
+            press(8);
+            run(2);
+            release(8);
+            run(2);
+            assert_eq(pixel(0, 0), 1);
+            let rows = screen().split('\\n');
+            assert_eq(rows[0].sub_string(0, 4), \"####\");
+            assert_eq(rows[1].sub_string(0, 4), \"#..#\");
+        ";
+        let machine = run(DIGIT, script).unwrap();
+        assert_eq!(machine.cpu.registers[1], 8);
+        assert!(run(DIGIT, "press(16);").is_err());
+        assert!(run(DIGIT, "pixel(64, 0);").is_err());
+    }
+
+    #[test]
+    fn test_screenshot_options() {
+        let path = std::env::temp_dir().join(format!("chip8-script-{}.pbm", std::process::id()));
+        let script = format!("screenshot({:?});", path.to_str().unwrap());
+        let image = ImageOptions {
+            scale: 3,
+            ..ImageOptions::default()
+        };
+        let mut machine = machine(DIGIT);
+        Script::compile(&script)
+            .unwrap()
+            .run(&mut machine, &image)
+            .unwrap();
+        let pbm = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(pbm.starts_with("P1\n192 96\n"), "{}", &pbm[..20]);
+    }
+
+    #[test]
+    fn test_hooks() {
+        let script = "
+            let steps = 0;
+            fn on_instruction(pc, opcode) {
+                if pc == 0x200 { assert_eq(opcode, 0x6005); }
+                if v(0) == 10 { set_v(0, 100); }
+            }
+            fn on_frame(frame) {
+                print(`frame ${frame}`);
+            }
+            run(3);
+            assert_eq(frame(), 3);
+            assert(v(0) > 100, `V0 is ${v(0)}`);
+        ";
+        run(COUNTER, script).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = run(COUNTER, "run(2);\nassert(v(0) == 0, \"V0 is zero\");").unwrap_err();
+        assert_eq!(error.line, Some(2));
+        assert!(error.message.contains("V0 is zero"), "{}", error);
+        assert!(error.to_string().starts_with("line 2, column "));
+
+        let error = run(COUNTER, "assert_eq(v(0), \"0\");").unwrap_err();
+        assert!(error.message.contains("0 != 0"), "{}", error);
+
+        let error = Script::compile("run(").unwrap_err();
+        assert_eq!(error.line, Some(1));
+
+        // a fault ends the script, and the machine comes back the way it faulted
+        let mut machine = machine("DW 0xFFFF");
+        let script = Script::compile("run(10);").unwrap();
+        let error = script
+            .run(&mut machine, &ImageOptions::default())
+            .unwrap_err();
+        assert!(error.message.contains("machine halted"), "{}", error);
+        assert!(machine.fault().is_some());
+        let script = "try { run(); } catch { } assert(fault() != ());";
+        run("DW 0xFFFF", script).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+// Turns the sound timer into PCM samples and hands them to whatever plays or records them.
+// The synth is a plain counter like the frame clock, so the same ROM and inputs always
+// produce the same samples, which is what lets CI check audio through a WAV file.
+use super::audio::{Audio, PATTERN_SIZE};
+use super::clock::FRAME_RATE;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+#[cfg(feature = "live-audio")]
+mod live;
+#[cfg(feature = "live-audio")]
+pub use live::LiveSink;
+
+/// Mono, 16 bit samples at CD rate. 44100 / 60 = 735 samples per frame.
+pub const SAMPLE_RATE: u32 = 44100;
+/// The tone plain CHIP-8 programs get, XO-CHIP ones until they load a pattern
+pub const BEEP_FREQUENCY: f64 = 440.0;
+/// Loud enough to hear, quiet enough not to hurt
+pub const AMPLITUDE: i16 = 8000;
+
+/// Generates one frame of samples at a time.
+#[derive(Debug)]
+pub struct Synth {
+    sample_rate: u32,
+    /// Samples left over when the rate isn't a multiple of FRAME_RATE, carried into the next frame
+    leftover_samples: u32,
+    /// Position in the waveform: cycles of the beep, or pattern bits
+    phase: f64,
+}
+
+impl Default for Synth {
+    fn default() -> Self {
+        Self::new(SAMPLE_RATE)
+    }
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            leftover_samples: 0,
+            phase: 0.0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The samples for one 60 Hz frame. Silence unless `active`, which is the sound timer
+    /// running. Every beep starts at the beginning of the waveform.
+    pub fn render_frame(&mut self, audio: &Audio, active: bool) -> Vec<i16> {
+        let samples = self.sample_rate + self.leftover_samples;
+        self.leftover_samples = samples % FRAME_RATE;
+        let count = (samples / FRAME_RATE) as usize;
+
+        if !active {
+            self.phase = 0.0;
+            return vec![0; count];
+        }
+        let (step, period) = match audio.pattern {
+            Some(_) => (audio.playback_rate(), (PATTERN_SIZE * 8) as f64),
+            None => (BEEP_FREQUENCY, 1.0),
+        };
+        let step = step / self.sample_rate as f64;
+        (0..count)
+            .map(|_| {
+                let high = match audio.pattern {
+                    Some(_) => audio.sample(self.phase as usize),
+                    None => self.phase < 0.5,
+                };
+                self.phase = (self.phase + step) % period;
+                if high {
+                    AMPLITUDE
+                } else {
+                    -AMPLITUDE
+                }
+            })
+            .collect()
+    }
+}
+
+/// Somewhere to send the samples, one frame's worth per `write`.
+pub trait AudioSink: fmt::Debug {
+    fn write(&mut self, samples: &[i16]) -> io::Result<()>;
+
+    /// Called once the machine is done, e.g. to patch up a file header.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Throws everything away. The default, and what headless runs want.
+#[derive(Debug, Default)]
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn write(&mut self, _samples: &[i16]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Records to a 16 bit mono PCM .wav file.
+/// The header is written up front with empty sizes and fixed up by `finish` (or on drop).
+#[derive(Debug)]
+pub struct WavSink<W: Write + Seek> {
+    /// None once `into_inner` took it
+    writer: Option<W>,
+    data_bytes: u32,
+}
+
+const WAV_HEADER_SIZE: u32 = 44;
+
+impl WavSink<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, sample_rate: u32) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        let block_align = 2u16; // one channel, two bytes
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WAV_HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&1u16.to_le_bytes())?; // channels
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?; // byte rate
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer: Some(writer),
+            data_bytes: 0,
+        })
+    }
+
+    /// Finishes the file and hands back the writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        let data_bytes = self.data_bytes;
+        patch_wav_header(self.writer(), data_bytes)?;
+        Ok(self.writer.take().expect("WavSink writer already taken"))
+    }
+
+    fn writer(&mut self) -> &mut W {
+        self.writer.as_mut().expect("WavSink writer already taken")
+    }
+}
+
+impl<W: Write + Seek + fmt::Debug> AudioSink for WavSink<W> {
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        self.writer().write_all(&bytes)?;
+        self.data_bytes += bytes.len() as u32;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let data_bytes = self.data_bytes;
+        patch_wav_header(self.writer(), data_bytes)
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        // best effort, call finish() yourself to see the error
+        if let Some(writer) = self.writer.as_mut() {
+            let _ = patch_wav_header(writer, self.data_bytes);
+        }
+    }
+}
+
+/// Fills in the RIFF and data chunk sizes, leaving the writer at the end of the file.
+fn patch_wav_header(writer: &mut (impl Write + Seek), data_bytes: u32) -> io::Result<()> {
+    writer.seek(SeekFrom::Start(4))?;
+    writer.write_all(&(WAV_HEADER_SIZE - 8 + data_bytes).to_le_bytes())?;
+    writer.seek(SeekFrom::Start(WAV_HEADER_SIZE as u64 - 4))?;
+    writer.write_all(&data_bytes.to_le_bytes())?;
+    writer.seek(SeekFrom::End(0))?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod soundtests {
+    use super::{AudioSink, Synth, WavSink, AMPLITUDE, SAMPLE_RATE};
+    use crate::emu::audio::Audio;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_silent_frame() {
+        let mut synth = Synth::default();
+        let samples = synth.render_frame(&Audio::new(), false);
+        assert_eq!(samples.len(), 735);
+        assert!(samples.iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn test_frame_length_carries_over() {
+        // 8000 / 60 = 133.33
+        let mut synth = Synth::new(8000);
+        let lengths: Vec<_> = (0..3)
+            .map(|_| synth.render_frame(&Audio::new(), false).len())
+            .collect();
+        assert_eq!(lengths, [133, 133, 134]);
+    }
+
+    #[test]
+    fn test_beep_is_a_square_wave() {
+        let mut synth = Synth::new(SAMPLE_RATE);
+        let samples = synth.render_frame(&Audio::new(), true);
+        // 44100 / 440 ~ 100 samples per cycle, half of them high
+        assert!(samples[..50].iter().all(|&s| s == AMPLITUDE));
+        assert!(samples[51..100].iter().all(|&s| s == -AMPLITUDE));
+        assert_eq!(samples[101], AMPLITUDE);
+    }
+
+    #[test]
+    fn test_pattern_playback() {
+        let mut audio = Audio::new();
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1100_0000;
+        audio.pattern = Some(pattern);
+        // pitch 112 plays 8000 bits a second, one per sample at an 8000 Hz sample rate
+        audio.pitch = 112;
+        let mut synth = Synth::new(8000);
+        let samples = synth.render_frame(&audio, true);
+        assert_eq!(samples[..3], [AMPLITUDE, AMPLITUDE, -AMPLITUDE]);
+        // 128 bits later it loops
+        assert_eq!(samples[128..131], [AMPLITUDE, AMPLITUDE, -AMPLITUDE]);
+    }
+
+    #[test]
+    fn test_wav_sink() {
+        let mut sink = WavSink::new(Cursor::new(Vec::new()), 8000).unwrap();
+        sink.write(&[1, -2]).unwrap();
+        sink.write(&[0x0304]).unwrap();
+        let bytes = sink.into_inner().unwrap().into_inner();
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes[4..8], 42u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(bytes[24..28], 8000u32.to_le_bytes());
+        assert_eq!(bytes[28..32], 16000u32.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(bytes[40..44], 6u32.to_le_bytes());
+        assert_eq!(bytes[44..], [1, 0, 0xFE, 0xFF, 0x04, 0x03]);
+    }
+}
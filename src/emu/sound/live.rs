@@ -0,0 +1,100 @@
+// Plays the samples on the default output device through cpal.
+// The emulator pushes a frame at a time into a queue that the device callback drains,
+// resampling on the fly since few sound cards will run at exactly SAMPLE_RATE.
+use super::{AudioSink, SAMPLE_RATE};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// More than this and we're running ahead of the sound card, the oldest samples get dropped
+/// so the beep doesn't lag further and further behind the picture.
+const MAX_QUEUED_SAMPLES: usize = SAMPLE_RATE as usize / 10;
+
+pub struct LiveSink {
+    queue: Arc<Mutex<VecDeque<i16>>>,
+    /// Playback stops when this is dropped
+    _stream: cpal::Stream,
+}
+
+impl fmt::Debug for LiveSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LiveSink")
+            .field("queued", &self.queue.lock().map(|q| q.len()).unwrap_or(0))
+            .finish()
+    }
+}
+
+impl LiveSink {
+    /// Opens the default output device. Fails on machines without one.
+    pub fn new() -> io::Result<Self> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| io::Error::other("no audio output device"))?;
+        let config = device.default_output_config().map_err(io::Error::other)?;
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config.into(), &queue),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config.into(), &queue),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config.into(), &queue),
+            format => {
+                return Err(io::Error::other(format!(
+                    "unsupported sample format {}",
+                    format
+                )))
+            }
+        }?;
+        stream.play().map_err(io::Error::other)?;
+        Ok(Self {
+            queue,
+            _stream: stream,
+        })
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<i16>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    queue: &Arc<Mutex<VecDeque<i16>>>,
+) -> io::Result<cpal::Stream> {
+    let queue = Arc::clone(queue);
+    let channels = config.channels as usize;
+    // how far one device sample moves through ours
+    let step = SAMPLE_RATE as f64 / config.sample_rate.0 as f64;
+    let mut position = 0.0;
+    let mut current = 0i16;
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut queue = queue.lock().unwrap();
+                for frame in data.chunks_mut(channels) {
+                    position += step;
+                    while position >= 1.0 {
+                        position -= 1.0;
+                        // run dry and it goes quiet rather than repeating the last sample
+                        current = queue.pop_front().unwrap_or(0);
+                    }
+                    frame.fill(T::from_sample(current));
+                }
+            },
+            |e| error!(target: "audio", "audio stream: {}", e),
+            None,
+        )
+        .map_err(io::Error::other)
+}
+
+impl AudioSink for LiveSink {
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        let mut queue = self
+            .queue
+            .lock()
+            .map_err(|_| io::Error::other("audio thread panicked"))?;
+        queue.extend(samples);
+        let excess = queue.len().saturating_sub(MAX_QUEUED_SAMPLES);
+        queue.drain(..excess);
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+// Save states: everything needed to put a machine back exactly where it was.
+// The binary format is what the slots use, the JSON one is for people (and bug reports).
+//
+// Binary layout, little endian:
+//   0  "C8ST"
+//   4  u16 format version
+//   6  u32 CRC-32 of the payload
+//  10  payload, the Snapshot encoded with bincode
+use super::{audio::Audio, cpu::Cpu, gpu::Gpu, input::Keypad, mem::Memory, timer::Timer};
+use super::{
+    gpu::{HIRES_HEIGHT, HIRES_WIDTH, SCREEN_HEIGHT, SCREEN_WIDTH},
+    mem::{RAM_SIZE, XO_RAM_SIZE},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Bump this whenever a field is added to or changed in anything a Snapshot holds.
+pub const STATE_VERSION: u16 = 2;
+const MAGIC: &[u8; 4] = b"C8ST";
+const HEADER_SIZE: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub cpu: Cpu,
+    pub memory: Memory,
+    pub gpu: Gpu,
+    pub timers: Timer,
+    pub keypad: Keypad,
+    pub audio: Audio,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// Doesn't start with "C8ST", not a save state at all
+    BadMagic,
+    /// Written by a build with a different STATE_VERSION
+    UnsupportedVersion(u16),
+    /// The payload doesn't match its checksum, the file got damaged
+    ChecksumMismatch,
+    /// Decoded, but not into a machine that could exist
+    Corrupt(String),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported, expected {}",
+                version, STATE_VERSION
+            ),
+            StateError::ChecksumMismatch => write!(f, "save state checksum mismatch"),
+            StateError::Corrupt(reason) => write!(f, "corrupt save state: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// The JSON form carries its version next to the machine state.
+/// Replays are stored the same way.
+#[derive(Serialize, Deserialize)]
+pub(super) struct Versioned<T> {
+    pub(super) version: u16,
+    #[serde(flatten)]
+    pub(super) state: T,
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let payload = bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .expect("snapshots always encode");
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StateError> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let checksum = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+        let payload = &bytes[HEADER_SIZE..];
+        if crc32fast::hash(payload) != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+        let (snapshot, _): (Snapshot, _) =
+            bincode::serde::decode_from_slice(payload, bincode::config::standard())
+                .map_err(|e| StateError::Corrupt(e.to_string()))?;
+        snapshot.validate()
+    }
+
+    pub fn to_json(&self) -> String {
+        let versioned = Versioned {
+            version: STATE_VERSION,
+            state: self,
+        };
+        serde_json::to_string_pretty(&versioned).expect("snapshots always encode")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, StateError> {
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| StateError::Corrupt(e.to_string()))?;
+        // check the version first, an old layout would only give a confusing serde error
+        match value.get("version").and_then(|v| v.as_u64()) {
+            Some(version) if version == STATE_VERSION as u64 => {}
+            Some(version) => return Err(StateError::UnsupportedVersion(version as u16)),
+            None => return Err(StateError::BadMagic),
+        }
+        let versioned: Versioned<Snapshot> =
+            serde_json::from_value(value).map_err(|e| StateError::Corrupt(e.to_string()))?;
+        versioned.state.validate()
+    }
+
+    /// A hand-edited JSON file can say anything, catch what would make the machine panic.
+    pub(super) fn validate(self) -> Result<Self, StateError> {
+        let ram = self.memory.ram.len();
+        if ram != RAM_SIZE && ram != XO_RAM_SIZE {
+            return Err(StateError::Corrupt(format!("{} bytes of RAM", ram)));
+        }
+        let pixels = self.gpu.width() * self.gpu.height();
+        if ![SCREEN_WIDTH * SCREEN_HEIGHT, HIRES_WIDTH * HIRES_HEIGHT].contains(&pixels)
+            || self.gpu.screen.len() != pixels
+            || self.gpu.second_plane.len() != pixels
+        {
+            return Err(StateError::Corrupt(
+                "screen doesn't match the resolution".to_string(),
+            ));
+        }
+        if self.cpu.stack_pointer > self.cpu.stack.len() {
+            return Err(StateError::Corrupt(format!(
+                "stack pointer {}",
+                self.cpu.stack_pointer
+            )));
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod statetests {
+    use super::{Snapshot, StateError, STATE_VERSION};
+    use crate::emu::rng::{Rng, RngKind};
+    use crate::emu::{audio::Audio, cpu::Cpu, gpu::Gpu, input::Keypad, mem::Memory, timer::Timer};
+
+    fn snapshot() -> Snapshot {
+        let mut snapshot = Snapshot {
+            cpu: Cpu::new(),
+            memory: Memory::new(),
+            gpu: Gpu::new(),
+            timers: Timer::new(0),
+            keypad: Keypad::new(),
+            audio: Audio::new(),
+        };
+        snapshot.cpu.registers[3] = 0x42;
+        snapshot.cpu.stack[0] = 0x246;
+        snapshot.cpu.stack_pointer = 1;
+        snapshot.memory.ram[0x300] = 0xAB;
+        snapshot.gpu.screen[10] = true;
+        snapshot.timers.sound_timer = 7;
+        snapshot.keypad.press(0xB);
+        snapshot.cpu.rng = Rng::new(RngKind::Counter, 5);
+        snapshot.cpu.rng.next_byte();
+        snapshot
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let snapshot = snapshot();
+        let bytes = snapshot.to_bytes();
+        assert_eq!(&bytes[0..4], b"C8ST");
+        assert_eq!(bytes[4..6], STATE_VERSION.to_le_bytes());
+        assert_eq!(Snapshot::from_bytes(&bytes), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let snapshot = snapshot();
+        let json = snapshot.to_json();
+        assert!(json.contains("\"version\": 2"));
+        assert!(json.contains("\"program_counter\": 512"));
+        assert_eq!(Snapshot::from_json(&json), Ok(snapshot));
+    }
+
+    #[test]
+    fn test_damaged_states() {
+        let mut bytes = snapshot().to_bytes();
+        assert_eq!(Snapshot::from_bytes(b"PNG"), Err(StateError::BadMagic));
+
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        assert_eq!(
+            Snapshot::from_bytes(&bytes),
+            Err(StateError::ChecksumMismatch)
+        );
+
+        bytes[4] = 99;
+        assert_eq!(
+            Snapshot::from_bytes(&bytes),
+            Err(StateError::UnsupportedVersion(99))
+        );
+        assert_eq!(
+            Snapshot::from_json("{\"version\": 99}"),
+            Err(StateError::UnsupportedVersion(99))
+        );
+    }
+
+    #[test]
+    fn test_impossible_machine() {
+        let mut snapshot = snapshot();
+        snapshot.memory.ram.truncate(100);
+        assert!(matches!(
+            Snapshot::from_json(&snapshot.to_json()),
+            Err(StateError::Corrupt(_))
+        ));
+    }
+}
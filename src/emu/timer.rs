@@ -1,4 +1,6 @@
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Timer {
     pub delay_timer: u8,
     pub sound_timer: u8,
@@ -10,6 +12,7 @@ impl Timer {
             sound_timer: ticks * 2,
         }
     }
+    /// Counts both timers down by one, call this at 60 Hz.
     pub fn tick(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
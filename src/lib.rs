@@ -0,0 +1,23 @@
+// Copyright (c) 2024-2025 galus. All Rights Reserved.
+//! Headless CHIP-8 emulator core.
+//!
+//! ```no_run
+//! use chip8::Machine;
+//!
+//! let mut machine = Machine::new();
+//! machine.load_rom(&std::fs::read("roms/2-ibm-logo.ch8").unwrap()).unwrap();
+//! for _ in 0..60 {
+//!     machine.run_frame().unwrap();
+//! }
+//! let (width, height) = machine.screen_size();
+//! assert_eq!(machine.framebuffer().len(), width * height);
+//! ```
+//!
+//! The terminal frontend in `main.rs` is just one consumer of this crate.
+
+#[macro_use]
+extern crate log;
+
+pub mod emu;
+
+pub use emu::Machine;
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+#![allow(clippy::empty_line_after_doc_comments)] // the banner's `///_/` isn't a doc comment
 // Copyright (c) 2024-2025 galus. All Rights Reserved.
 //    __                        _                                __
 //   / /_/\__        __ _  __ _| |_   _ ___             __/\__  / /
@@ -26,19 +27,43 @@ use tui_logger::{
 
 use color_eyre::{eyre::eyre, Result};
 
+mod cli;
 mod emojis;
-mod emu;
+mod tui;
 
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use cli::{Command, Headless, Options, DEFAULT_HEADLESS_FRAMES};
+
+use chip8::emu::asm::assemble;
+use chip8::emu::dap;
+use chip8::emu::disasm::{listing, Variant};
+use chip8::emu::gdb::GdbServer;
+use chip8::emu::mem::ROM_START_ADDRESS;
+use chip8::emu::quirks::{Quirks, QuirksProfile};
+use chip8::emu::replay::{Player, Recorder, Replay};
+use chip8::emu::rewind::DEFAULT_REWIND_FRAMES;
+use chip8::emu::rng::{Rng, DEFAULT_SEED};
+use chip8::emu::screenshot::{self, ImageFormat};
+use chip8::emu::script::KeyScript;
+use chip8::emu::sound::{WavSink, SAMPLE_RATE};
+use chip8::emu::Machine;
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
 use crossterm::execute;
 use crossterm::terminal::{
-    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+    disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+    LeaveAlternateScreen,
 };
-use emu::Emulator;
+use tui::{load_state_file, Emulator, KeypadInput};
 
 use emojis::EMOJIS as E;
-use std::fs::read;
-use std::io::stdout;
+use std::fs::{read, read_to_string, write};
+use std::io::{stdin, stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set once the terminal agreed to report key releases, so we know to undo it on exit.
+static KEY_RELEASE_EVENTS: AtomicBool = AtomicBool::new(false);
 
 fn setup_logging() -> Result<()> {
     init_logger(log::LevelFilter::Trace)?;
@@ -76,9 +101,25 @@ fn init_terminal() -> Result<DefaultTerminal> {
     Ok(terminal)
 }
 
+/// Ask the terminal to report key release events (kitty keyboard protocol).
+/// Returns false on terminals that can't, the keypad then falls back to timed releases.
+fn enable_key_release_events() -> bool {
+    if !supports_keyboard_enhancement().unwrap_or(false) {
+        warn!(target:"tui", "Terminal can't report key releases, faking them");
+        return false;
+    }
+    let flags = KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+    let enabled = execute!(stdout(), PushKeyboardEnhancementFlags(flags)).is_ok();
+    KEY_RELEASE_EVENTS.store(enabled, Ordering::SeqCst);
+    enabled
+}
+
 /// Restore the terminal to its original state
 fn restore_terminal() -> Result<()> {
     trace!(target:"tui", "Restoring terminal");
+    if KEY_RELEASE_EVENTS.swap(false, Ordering::SeqCst) {
+        execute!(stdout(), PopKeyboardEnhancementFlags)?;
+    }
     disable_raw_mode()?;
     execute!(stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
     ratatui::restore();
@@ -103,7 +144,7 @@ impl LogFormatter for MyLogFormatter {
     fn min_width(&self) -> u16 {
         4
     }
-    fn format(&self, _width: usize, evt: &ExtLogRecord) -> Vec<Line> {
+    fn format(&self, _width: usize, evt: &ExtLogRecord) -> Vec<Line<'_>> {
         let mut lines = vec![];
         match evt.level {
             log::Level::Error => {
@@ -122,44 +163,248 @@ impl LogFormatter for MyLogFormatter {
             }
         };
 
-        match evt.level {
-            log::Level::Error => {
-                let st = Style::new(); //.blue().bold();
-                let sp = Span::styled("======", st);
-                let mayday = Span::from(" MAYDAY SEEN ? ".to_string());
-                let sp2 = Span::styled("======", st);
-                lines.push(Line::from(vec![sp, mayday, sp2]).alignment(Alignment::Center));
-            }
-            _ => {}
-        };
+        if evt.level == log::Level::Error {
+            let st = Style::new(); //.blue().bold();
+            let sp = Span::styled("======", st);
+            let mayday = Span::from(" MAYDAY SEEN ? ".to_string());
+            let sp2 = Span::styled("======", st);
+            lines.push(Line::from(vec![sp, mayday, sp2]).alignment(Alignment::Center));
+        }
         lines
     }
 }
 
-fn main() -> Result<()> {
-    color_eyre::install()?; // error hooks
-    setup_logging()?;
+/// `--quirks` wins, then `<rom>.quirks` next to the ROM, then the default profile.
+fn load_quirks(
+    rom_path: &str,
+    profile: Option<QuirksProfile>,
+    default_profile: Option<QuirksProfile>,
+) -> Result<Quirks> {
+    let default = default_profile.unwrap_or_default().quirks();
+    if let Some(profile) = profile {
+        return Ok(profile.quirks());
+    }
+    let quirks_path = format!("{}.quirks", rom_path);
+    match read_to_string(&quirks_path) {
+        Ok(config) => {
+            info!("\t{} Using quirks from {}", E["pen"], quirks_path);
+            default
+                .with_overrides(&config)
+                .map_err(|e| eyre!("{}: {}", quirks_path, e))
+        }
+        Err(_) => Ok(default),
+    }
+}
 
-    info!("{} Initializing emulator", E["dynamite"]);
-    let mut emu: Emulator = Emulator::new();
+/// `--wav` records to a file, otherwise the speakers when built with `live-audio`.
+/// No sound card is not worth failing over, the game just stays quiet.
+fn setup_audio(emu: &mut Emulator, wav_path: Option<&str>) -> Result<()> {
+    if let Some(path) = wav_path {
+        info!("\t{} Recording audio to {}", E["pen"], path);
+        let sink = WavSink::create(path, SAMPLE_RATE).map_err(|e| eyre!("{}: {}", path, e))?;
+        emu.machine.set_audio_sink(Box::new(sink));
+        return Ok(());
+    }
+    #[cfg(feature = "live-audio")]
+    match chip8::emu::sound::LiveSink::new() {
+        Ok(sink) => emu.machine.set_audio_sink(Box::new(sink)),
+        Err(e) => warn!("No audio: {}", e),
+    }
+    Ok(())
+}
 
-    info!("\t{} Loading fonts into emulator...", E["pen"]);
-    let _ = emu.memory.load_font();
+/// `chip8 disasm`: the listing goes to stdout, no terminal UI.
+fn disassemble(rom_path: &str, variant: Variant) -> Result<()> {
+    let rom = read(rom_path).map_err(|e| eyre!("{}: {}", rom_path, e))?;
+    print!("{}", listing(&rom, ROM_START_ADDRESS as u16, variant));
+    Ok(())
+}
 
-    let rom_path: String = args()
-        .nth(1)
-        .ok_or(eyre!("Please provide a path to a ROM file"))?;
+/// `chip8 asm`: errors point at the line and column, like a compiler's.
+fn assemble_file(source_path: &str, rom_path: &str) -> Result<()> {
+    let source = read_to_string(source_path).map_err(|e| eyre!("{}: {}", source_path, e))?;
+    let rom = assemble(&source)
+        .map_err(|e| eyre!("{}:{}:{}: {}", source_path, e.line, e.column, e.message))?;
+    write(rom_path, &rom).map_err(|e| eyre!("{}: {}", rom_path, e))?;
+    println!("{} bytes written to {}", rom.len(), rom_path);
+    Ok(())
+}
+
+/// Loads the ROM, set up the way the options (or the replay) say: speed, memory, quirks, the
+/// seed and a state. Returns the ROM's bytes.
+fn setup_machine(
+    machine: &mut Machine,
+    options: &Options,
+    replay: Option<&Replay>,
+) -> Result<Vec<u8>> {
+    info!("\t{} Loading fonts into emulator...", E["pen"]);
+    machine.memory.load_font();
 
+    let rom_path = &options.rom_path;
     info!("\t{} Reading rom {}...", E["eye"], rom_path);
-    let rom_data = read(&rom_path).expect(&format!("Could not read ROM file from: {}", rom_path));
+    let rom_data = read(rom_path).map_err(|e| eyre!("Could not read ROM {}: {}", rom_path, e))?;
+    if let Some(replay) = replay {
+        info!("\t{} Loading rom as the replay says...", E["joystick"]);
+        replay.setup(machine, &rom_data).map_err(|e| eyre!("{}: {}", rom_path, e))?;
+        return Ok(rom_data);
+    }
+
+    machine.set_speed(options.speed);
+    machine.set_xo_chip(options.xo_chip);
+    let default_profile = options.xo_chip.then_some(QuirksProfile::XoChip);
+    let quirks = load_quirks(rom_path, options.quirks, default_profile)?;
+    machine.set_quirks(quirks);
+    let seed = options.seed.unwrap_or(DEFAULT_SEED);
+    info!("\t{} Rolling CXNN with {} seed {:#x}", E["dice"], options.rng, seed);
+    machine.cpu.rng = Rng::new(options.rng, seed);
 
     info!("\t{} Loading rom into emulator...", E["joystick"]);
-    let _ = emu.load_rom(&rom_data);
+    machine.load_rom(&rom_data)?;
+    if let Some(state_path) = &options.state_path {
+        info!("\t{} Loading save state {}...", E["joystick"], state_path);
+        load_state_file(machine, state_path)?;
+    }
+    Ok(rom_data)
+}
+
+fn read_replay(path: &str) -> Result<Replay> {
+    let json = read_to_string(path).map_err(|e| eyre!("{}: {}", path, e))?;
+    Replay::from_json(&json).map_err(|e| eyre!("{}: {}", path, e))
+}
+
+/// `chip8 run --headless`: no terminal and no logging, stdout is for the results.
+/// They are written even when the ROM faults, the fault is the error afterwards.
+fn run_headless(options: &Options, headless: &Headless) -> Result<()> {
+    let replay = options.replay_path.as_deref().map(read_replay).transpose()?;
+    let mut machine = Machine::new();
+    setup_machine(&mut machine, options, replay.as_ref())?;
+    #[cfg(feature = "jit")]
+    machine.set_jit(true);
+    #[cfg(not(feature = "jit"))]
+    machine.set_decode_cache(true);
+    if let Some(path) = &options.wav_path {
+        let sink = WavSink::create(path, SAMPLE_RATE).map_err(|e| eyre!("{}: {}", path, e))?;
+        machine.set_audio_sink(Box::new(sink));
+    }
+    let (script, length) = match (&replay, &headless.keys_path) {
+        (Some(replay), _) => (replay.script(), replay.frames),
+        (None, Some(path)) => {
+            let text = read_to_string(path).map_err(|e| eyre!("{}: {}", path, e))?;
+            let script =
+                KeyScript::parse(&text).map_err(|e| eyre!("{}:{}: {}", path, e.line, e.message))?;
+            (script, DEFAULT_HEADLESS_FRAMES)
+        }
+        (None, None) => (KeyScript::default(), DEFAULT_HEADLESS_FRAMES),
+    };
+
+    let result = script.run(&mut machine, headless.frames.unwrap_or(length));
+    machine.finish_audio()?;
+
+    let screen_path = headless.screen_path.as_deref();
+    let format = headless
+        .format
+        .or(screen_path.map(ImageFormat::from_path))
+        .unwrap_or(ImageFormat::Ascii);
+    let screen = screenshot::encode_with(&machine.gpu, format, &options.image);
+    let state = machine.snapshot().to_json();
+    let mut out = stdout().lock();
+    match screen_path {
+        Some(path) => write(path, &screen).map_err(|e| eyre!("{}: {}", path, e))?,
+        None => out.write_all(&screen)?,
+    }
+    match &headless.json_path {
+        Some(path) => write(path, &state).map_err(|e| eyre!("{}: {}", path, e))?,
+        None => writeln!(out, "{}", state)?,
+    }
+    out.flush()?;
+    result.map_err(|fault| eyre!("{}", fault))
+}
+
+/// `chip8 script`: the script's `print`s go to stdout, a failed assert or a fault is the error.
+#[cfg(feature = "scripting")]
+fn run_script(script_path: &str, options: &Options) -> Result<()> {
+    use chip8::emu::scripting::Script;
+    let source = read_to_string(script_path).map_err(|e| eyre!("{}: {}", script_path, e))?;
+    let script = Script::compile(&source).map_err(|e| eyre!("{}: {}", script_path, e))?;
+    let mut machine = Machine::new();
+    setup_machine(&mut machine, options, None)?;
+    if let Some(path) = &options.wav_path {
+        let sink = WavSink::create(path, SAMPLE_RATE).map_err(|e| eyre!("{}: {}", path, e))?;
+        machine.set_audio_sink(Box::new(sink));
+    }
+    let result = script.run(&mut machine, &options.image);
+    machine.finish_audio()?;
+    result.map_err(|e| eyre!("{}: {}", script_path, e))
+}
+
+#[cfg(not(feature = "scripting"))]
+fn run_script(script_path: &str, options: &Options) -> Result<()> {
+    Err(eyre!("chip8 script needs chip8 built with --features scripting"))
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?; // error hooks
+    let mut options = match cli::parse_command(args().skip(1))? {
+        Command::Run(options) => *options,
+        Command::Disasm { rom_path, variant } => return disassemble(&rom_path, variant),
+        Command::Asm {
+            source_path,
+            rom_path,
+        } => return assemble_file(&source_path, &rom_path),
+        Command::Dap => return dap::serve(stdin(), stdout()).map_err(|e| eyre!("dap: {}", e)),
+        Command::Script {
+            script_path,
+            options,
+        } => return run_script(&script_path, &options),
+    };
+    if let Some(headless) = &options.headless {
+        return run_headless(&options, headless);
+    }
+    // headless runs should come out the same every time, games should not
+    options.seed.get_or_insert_with(rand::random);
+    setup_logging()?;
+
+    info!("{} Initializing emulator", E["dynamite"]);
+    let mut emu: Emulator = Emulator::new();
+    let replay = options.replay_path.as_deref().map(read_replay).transpose()?;
+    let rom = setup_machine(&mut emu.machine, &options, replay.as_ref())?;
+    if let Some(replay) = &replay {
+        info!("\t{} Playing a {} frame replay...", E["joystick"], replay.frames);
+        emu.input = KeypadInput::Replay(Player::new(replay));
+    }
+    if options.record_path.is_some() {
+        let seed = options.seed.unwrap_or(DEFAULT_SEED);
+        let mut recorder = Recorder::new(Replay::new(&emu.machine, &rom, seed));
+        if options.state_path.is_some() {
+            recorder.restart(&emu.machine);
+        }
+        emu.recorder = Some(recorder);
+    }
+    if let Some(addr) = &options.gdb {
+        let server = GdbServer::bind(addr).map_err(|e| eyre!("--gdb {}: {}", addr, e))?;
+        info!("\t{} GDB stub listening on {}...", E["bug"], addr);
+        emu.gdb = Some(server);
+    }
+    emu.image = options.image;
+    emu.rom_path = options.rom_path;
+    emu.machine.enable_rewind(DEFAULT_REWIND_FRAMES);
+
+    setup_audio(&mut emu, options.wav_path.as_deref())?;
 
     info!("\t{} Running app...", E["runner"]);
     let mut terminal = init_terminal().unwrap();
+    emu.key_release_fallback = !enable_key_release_events();
     let _ = emu.run(&mut terminal);
     let _ = terminal.clear();
+    if let Err(e) = emu.machine.finish_audio() {
+        error!("Couldn't finish the audio: {}", e);
+    }
+    if let (Some(path), Some(recorder)) = (&options.record_path, &emu.recorder) {
+        match write(path, recorder.replay().to_json()) {
+            Ok(()) => info!("{} Saved the replay to {}", E["pen"], path),
+            Err(e) => error!("Couldn't save the replay to {}: {}", path, e),
+        }
+    }
 
     info!("{} Exiting...", E["handwave"]);
     let _ = restore_terminal();
@@ -0,0 +1,166 @@
+// Ratatui widget for the debugger: a disassembly around the cursor and the cpu registers.
+use chip8::emu::debugger::Debugger;
+use chip8::emu::disasm::{decode_at, Variant};
+use chip8::Machine;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Widget},
+};
+
+pub struct DebugView<'a> {
+    machine: &'a Machine,
+    debugger: &'a Debugger,
+    /// Address highlighted in the listing, where breakpoints and run to cursor go
+    cursor: u16,
+    /// Watchpoint being typed in
+    prompt: Option<&'a str>,
+}
+
+impl<'a> DebugView<'a> {
+    pub fn new(machine: &'a Machine, debugger: &'a Debugger, cursor: u16) -> Self {
+        Self {
+            machine,
+            debugger,
+            cursor,
+            prompt: None,
+        }
+    }
+
+    pub fn prompt(mut self, prompt: Option<&'a str>) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    fn listing(&self, rows: usize) -> Vec<Line<'a>> {
+        let pc = self.machine.cpu.program_counter;
+        let ram = &self.machine.memory.ram;
+        // keep the cursor in the middle, stepping back whole instructions
+        let start = self.cursor.saturating_sub((rows / 2) as u16 * 2);
+        let mut next = start;
+        std::iter::from_fn(|| {
+            let line = decode_at(ram, next, Variant::default());
+            next = next.wrapping_add(line.bytes.len() as u16);
+            (line.bytes.len() >= 2).then_some(line)
+        })
+        .take(rows)
+        .map(|line| {
+            let addr = line.addr;
+            let op: String = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let marker = match (self.debugger.has_breakpoint(addr), addr == pc) {
+                (true, true) => "●▶".red(),
+                (true, false) => "● ".red(),
+                (false, true) => " ▶".yellow(),
+                (false, false) => "  ".into(),
+            };
+            let mut line = Line::from(vec![
+                marker,
+                format!(" {:03X}  {:<8} ", addr, op).dark_gray(),
+                Span::raw(line.text),
+            ]);
+            if addr == self.cursor {
+                line = line.style(Style::default().add_modifier(Modifier::REVERSED));
+            }
+            line
+        })
+        .collect()
+    }
+
+    fn registers(&self) -> Vec<Line<'a>> {
+        let cpu = &self.machine.cpu;
+        let mut lines: Vec<Line> = cpu
+            .registers
+            .chunks(4)
+            .enumerate()
+            .map(|(row, values)| {
+                let cells: Vec<String> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| format!("V{:X} {:02X}", row * 4 + i, value))
+                    .collect();
+                Line::from(cells.join("  "))
+            })
+            .collect();
+        lines.push(Line::default());
+        lines.push(Line::from(format!(
+            "PC {:03X}  I {:03X}  SP {}",
+            cpu.program_counter, cpu.index_register, cpu.stack_pointer
+        )));
+        lines.push(Line::from(format!(
+            "DT {:02X}  ST {:02X}",
+            self.machine.timers.delay_timer, self.machine.timers.sound_timer
+        )));
+        let stack: Vec<String> = cpu.stack[..cpu.stack_pointer.min(cpu.stack.len())]
+            .iter()
+            .map(|addr| format!("{:03X}", addr))
+            .collect();
+        lines.push(Line::from(format!("Stack {}", stack.join(" "))));
+        let watchpoints = self.debugger.watchpoints();
+        if !watchpoints.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::from("Watch".bold()));
+        }
+        for (i, watchpoint) in watchpoints.iter().enumerate() {
+            lines.push(Line::from(format!("{} {}", i + 1, watchpoint)));
+        }
+        lines
+    }
+}
+
+impl Widget for DebugView<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let status = match self.debugger.last_stop() {
+            _ if !self.debugger.is_paused() => " Running ".green().bold(),
+            Some(reason) => format!(" Paused: {} ", reason).yellow().bold(),
+            None => " Paused ".yellow().bold(),
+        };
+        let keys = Line::from(vec![
+            " p".blue().bold(),
+            " run/pause ".into(),
+            "n".blue().bold(),
+            " step ".into(),
+            "o".blue().bold(),
+            " over ".into(),
+            "u".blue().bold(),
+            " out ".into(),
+            "b".blue().bold(),
+            " break ".into(),
+            "t".blue().bold(),
+            " run to ".into(),
+            "m".blue().bold(),
+            " watch ".into(),
+        ]);
+        let block = Block::bordered()
+            .title_top(Line::from(vec![" Debugger ".bold(), status]))
+            .title_bottom(keys)
+            .border_style(Style::default().fg(Color::DarkGray));
+
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+            .split(block.inner(area));
+        let rows = chunks[0].height as usize;
+
+        Paragraph::new(self.listing(rows)).render(chunks[0], buf);
+        let mut side = chunks[1];
+        if let Some(prompt) = self.prompt {
+            // the last row of the side panel becomes the input line
+            let input = Rect {
+                y: side.bottom().saturating_sub(1),
+                height: side.height.min(1),
+                ..side
+            };
+            side.height = side.height.saturating_sub(1);
+            Line::from(vec![
+                "watch> ".blue().bold(),
+                Span::raw(prompt),
+                "_".slow_blink(),
+            ])
+            .render(input, buf);
+        }
+        Paragraph::new(self.registers()).render(side, buf);
+        block.render(area, buf);
+    }
+}
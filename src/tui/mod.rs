@@ -0,0 +1,864 @@
+// use ratatui::layout::Position;
+// use ratatui::text::Text;
+use ratatui::widgets::{BorderType, Paragraph};
+use ratatui::{layout::Alignment, style::Stylize};
+mod debugger;
+mod screen;
+
+use crate::emojis::EMOJIS as E; // Avoid Emoji Nightmares
+use chip8::emu::clock::FramePacer;
+use chip8::emu::debugger::{Debugger, StopReason, Watchpoint};
+use chip8::emu::gdb::GdbServer;
+use chip8::emu::input::KEY_COUNT;
+use chip8::emu::replay::{Player, Recorder};
+use chip8::emu::screenshot::{self, ImageFormat, ImageOptions};
+use chip8::emu::state::Snapshot;
+use chip8::Machine;
+use debugger::DebugView;
+use screen::Screen;
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    // Report,
+    Result,
+};
+use std::fs;
+use std::time::{self, Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+use tui_logger::{
+    LevelFilter, TuiLoggerLevelOutput, TuiLoggerSmartWidget, TuiWidgetEvent, TuiWidgetState,
+};
+
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+use ratatui::{
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Style},
+    // text::Line,
+    widgets::{
+        // block::Title,
+        Block,
+        Widget,
+    },
+    DefaultTerminal,
+    Frame,
+};
+
+/// Terminals that can't report key releases only send presses (and key repeats),
+/// so keypad keys get released this long after their last press instead.
+const KEY_RELEASE_FALLBACK: Duration = Duration::from_millis(200);
+
+/// Save state slots 1-9, picked with [ and ]
+const STATE_SLOTS: u8 = 9;
+
+/// Maps the keyboard onto the Chip8 keypad, see the help screen for the layout.
+fn keypad_key(code: KeyCode) -> Option<u8> {
+    match code {
+        KeyCode::Char('1') => Some(0x1),
+        KeyCode::Char('2') => Some(0x2),
+        KeyCode::Char('3') => Some(0x3),
+        KeyCode::Char('4') => Some(0xC),
+        KeyCode::Char('q') => Some(0x4),
+        KeyCode::Char('w') => Some(0x5),
+        KeyCode::Char('e') => Some(0x6),
+        KeyCode::Char('r') => Some(0xD),
+        KeyCode::Char('a') => Some(0x7),
+        KeyCode::Char('s') => Some(0x8),
+        KeyCode::Char('d') => Some(0x9),
+        KeyCode::Char('f') => Some(0xE),
+        KeyCode::Char('z') => Some(0xA),
+        KeyCode::Char('x') => Some(0x0),
+        KeyCode::Char('c') => Some(0xB),
+        KeyCode::Char('v') => Some(0xF),
+        _ => None,
+    }
+}
+
+/// Where the keypad's presses come from. Everything else is always the keyboard.
+pub enum KeypadInput {
+    /// The keyboard, through io_thread and AppEvent::KeyEvent
+    Terminal,
+    /// A replay, one frame at a time, until it runs out
+    Replay(Player),
+}
+
+#[derive(Debug)]
+enum AppEvent {
+    KeyEvent(KeyEvent),
+    CounterChanged(Option<u16>),
+}
+
+/// The terminal frontend: a `Machine` plus everything needed to show it in ratatui.
+//#[derive(Debug)]
+pub struct Emulator {
+    pub machine: Machine,
+    /// Set when the terminal doesn't send key release events, see KEY_RELEASE_FALLBACK
+    pub key_release_fallback: bool,
+    /// Save states go next to the ROM, `<rom>.state<slot>`
+    pub rom_path: String,
+    pub state_slot: u8,
+    /// How F12's screenshots look, set with --palette and --scale
+    pub image: ImageOptions,
+    /// Backspace is held, frames run backwards instead of forwards
+    rewinding: bool,
+    /// Last Backspace press, for terminals that don't report its release
+    rewind_pressed_at: Option<Instant>,
+    key_pressed_at: [Option<Instant>; KEY_COUNT],
+    pub input: KeypadInput,
+    /// Set with --record, gets every keypad change and frame
+    pub recorder: Option<Recorder>,
+    pub should_quit: bool,
+    pub show_help: bool,
+    pub show_logs: bool,
+    pub debugger: Debugger,
+    /// Set with --gdb, a remote debugger can drive `debugger` through it
+    pub gdb: Option<GdbServer>,
+    /// The debugger panel takes the log panel's place while it's open
+    pub show_debugger: bool,
+    /// Address selected in the debugger's listing
+    cursor: u16,
+    /// A watchpoint being typed in, takes every key until Enter or Esc
+    watch_input: Option<String>,
+    pub progress_counter: Option<u16>,
+    pub states: Vec<TuiWidgetState>,
+    pub tab_names: Vec<&'static str>,
+    pub selected_tab: usize,
+}
+
+impl Emulator {
+    pub fn new() -> Self {
+        Self {
+            machine: Machine::new(),
+            key_release_fallback: false,
+            rom_path: String::new(),
+            state_slot: 1,
+            image: ImageOptions::default(),
+            rewinding: false,
+            rewind_pressed_at: None,
+            key_pressed_at: [None; KEY_COUNT],
+            input: KeypadInput::Terminal,
+            recorder: None,
+            should_quit: false,
+            show_help: false,
+            show_logs: true,
+            debugger: Debugger::new(),
+            gdb: None,
+            show_debugger: false,
+            cursor: 0x200,
+            watch_input: None,
+            progress_counter: None,
+            states: vec![
+                TuiWidgetState::new().set_default_display_level(LevelFilter::Info),
+                TuiWidgetState::new().set_default_display_level(LevelFilter::Info),
+                TuiWidgetState::new().set_default_display_level(LevelFilter::Info),
+                TuiWidgetState::new().set_default_display_level(LevelFilter::Info),
+            ],
+            tab_names: vec!["State 1", "State 2", "State 3", "State 4"],
+            selected_tab: 0,
+        }
+    }
+
+    /// Renders the Gpu on the left
+    /// Renders the Logs on the right
+    fn draw(&self, frame: &mut Frame) {
+        if !self.show_help {
+            if self.show_logs || self.show_debugger {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(frame.area());
+
+                Screen::new(&self.machine.gpu)
+                    .fault(self.machine.fault())
+                    .exited(self.machine.has_exited())
+                    .slot(self.state_slot)
+                    .render(chunks[0], frame.buffer_mut());
+
+                //let log_block = Block::bordered().title("Log Output");
+                //frame.render_widget(log_block, chunks[1]);
+
+                //let log_content = log_block.inner(chunks[1]);
+
+                if self.show_debugger {
+                    DebugView::new(&self.machine, &self.debugger, self.cursor)
+                        .prompt(self.watch_input.as_deref())
+                        .render(chunks[1], frame.buffer_mut());
+                    return;
+                }
+                let current_state = self.selected_state();
+                TuiLoggerSmartWidget::default()
+                    .style_error(Style::default().fg(Color::Red))
+                    .style_debug(Style::default().fg(Color::Green))
+                    .style_warn(Style::default().fg(Color::Yellow))
+                    .style_trace(Style::default().fg(Color::Magenta))
+                    .style_info(Style::default().fg(Color::Cyan))
+                    .output_separator(':')
+                    .output_timestamp(Some("%H:%M:%S".to_string()))
+                    .output_level(Some(TuiLoggerLevelOutput::Abbreviated))
+                    .output_target(true)
+                    .output_file(true)
+                    .output_line(true)
+                    .state(current_state)
+                    .render(chunks[1], frame.buffer_mut());
+            } else {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Percentage(100)])
+                    .split(frame.area());
+
+                Screen::new(&self.machine.gpu)
+                    .fault(self.machine.fault())
+                    .exited(self.machine.has_exited())
+                    .slot(self.state_slot)
+                    .render(chunks[0], frame.buffer_mut());
+            }
+        } else {
+            let title = vec![" Help".bold(), " ?".red().bold()];
+            let instructions = vec![" Close Help ".into(), "Press any key.".blue().bold()];
+            let help_text = "\
+  Help
+
+  This is an interactive terminal application.
+  Use the following keybindings to control the emulator and interact with the interface.
+
+  General Controls
+  - ?: Toggle the help screen.
+  - Ctrl-C or Ctrl-Q: Quit the application.
+
+  Save States
+  - [ / ]: Pick the previous or next slot (1-9).
+  - F5: Save to the slot, F6 saves it as JSON.
+  - F9: Load the slot.
+  - F12: Screenshot, a PNG next to the ROM.
+  - Backspace (hold): Rewind, one frame back per frame.
+
+  Debugger
+  - F2: Show/hide the debugger in place of the logs.
+  - p: Pause/resume.
+  - ↑ / ↓, Page Up / Page Down: Move the cursor through the listing.
+  - n: Step one instruction.
+  - o: Step over a CALL, u: step out of the current subroutine.
+  - b: Toggle a breakpoint at the cursor.
+  - t: Run to the cursor.
+  - m: Add a watchpoint, e.g. `write 0x300-0x30F`, `change V3`, `I in 0x300-0x3FF`,
+    `depth 4` or a condition like `V3 == 0x10 && I > 0x300`. M clears them all.
+
+  Chip-8 Keypad Mapping
+  The emulator maps your keyboard to a standard Chip-8 keypad.
+  - 1: 1
+  - 2: 2
+  - 3: 3
+  - 4: C
+  - q: 4
+  - w: 5
+  - e: 6
+  - r: D
+  - a: 7
+  - s: 8
+  - d: 9
+  - f: E
+  - z: A
+  - x: 0
+  - c: B
+  - v: F
+
+  Log Panel Controls
+  These controls are active when the log panel is focused.
+  - l: Toggle Log Panel on/off
+  - Arrow Keys (↑, ↓, ←, →): Navigate through log messages.
+  - Page Up / Page Down: Jump to the previous or next page of logs.
+  - + / -: Increase or decrease the log verbosity level.
+  - h: Hide the log target selector.
+  - g: Focus on the log target selector.
+  - Tab: Switch between the different log states.
+  - Escape: Exit the log focus mode.
+  ";
+
+            let block = Block::bordered()
+                .title_top(title)
+                .title_bottom(instructions)
+                .border_type(BorderType::Rounded);
+
+            let paragraph = Paragraph::new(help_text)
+                .alignment(Alignment::Left)
+                .block(block);
+
+            frame.render_widget(paragraph, frame.area());
+        }
+    }
+
+    pub fn handle_key_event(&mut self, key_event: KeyEvent) -> Result<(), String> {
+        let is_release = key_event.kind == KeyEventKind::Release;
+        // If help window is showing, pressing any key removes it.
+        if self.show_help && !is_release {
+            self.show_help = false;
+            return Ok(());
+        }
+
+        if self.watch_input.is_some() {
+            if !is_release {
+                self.edit_watch_input(key_event.code);
+            }
+            return Ok(());
+        }
+
+        // Chip8 valid 16 keys, these are tracked on press and on release.
+        if !key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            if let Some(key) = keypad_key(key_event.code) {
+                if matches!(self.input, KeypadInput::Replay(_)) {
+                    // hands off, the replay is playing
+                } else if is_release {
+                    self.release_key(key);
+                } else {
+                    self.press_key(key);
+                }
+                return Ok(());
+            }
+        }
+        if key_event.code == KeyCode::Backspace {
+            self.rewinding = !is_release;
+            if self.key_release_fallback && !is_release {
+                self.rewind_pressed_at = Some(Instant::now());
+            }
+            return Ok(());
+        }
+        if is_release {
+            return Ok(());
+        }
+
+        if self.show_debugger && self.handle_debugger_key(key_event.code) {
+            return Ok(());
+        }
+
+        let state = self.selected_state();
+        match key_event.code {
+            KeyCode::Char('?') => {
+                self.show_help = !self.show_help;
+                Ok(())
+            }
+
+            KeyCode::Char('[') => {
+                self.state_slot = (self.state_slot + STATE_SLOTS - 2) % STATE_SLOTS + 1;
+                Ok(())
+            }
+            KeyCode::Char(']') => {
+                self.state_slot = self.state_slot % STATE_SLOTS + 1;
+                Ok(())
+            }
+            KeyCode::F(2) => {
+                self.show_debugger = !self.show_debugger;
+                self.cursor = self.machine.cpu.program_counter;
+                Ok(())
+            }
+            KeyCode::Char('p') => {
+                if self.debugger.is_paused() {
+                    self.debugger.resume();
+                } else {
+                    self.debugger.pause();
+                    self.cursor = self.machine.cpu.program_counter;
+                }
+                Ok(())
+            }
+            KeyCode::F(5) => {
+                self.save_slot(false);
+                Ok(())
+            }
+            KeyCode::F(6) => {
+                self.save_slot(true);
+                Ok(())
+            }
+            KeyCode::F(9) => {
+                self.load_slot();
+                Ok(())
+            }
+            KeyCode::F(12) => {
+                self.save_screenshot();
+                Ok(())
+            }
+
+            // Tui Logger Smart Widget Keys
+            KeyCode::Char('l') => {
+                self.show_logs = !self.show_logs;
+                Ok(())
+            }
+            KeyCode::Char('\t') | KeyCode::Tab => {
+                self.next_tab();
+                Ok(())
+            }
+            KeyCode::Char(' ') => {
+                state.transition(TuiWidgetEvent::SpaceKey);
+                Ok(())
+            }
+            KeyCode::Esc => {
+                state.transition(TuiWidgetEvent::EscapeKey);
+                Ok(())
+            }
+            KeyCode::PageUp => {
+                state.transition(TuiWidgetEvent::PrevPageKey);
+                Ok(())
+            }
+            KeyCode::PageDown => {
+                state.transition(TuiWidgetEvent::NextPageKey);
+                Ok(())
+            }
+            KeyCode::Up => {
+                state.transition(TuiWidgetEvent::UpKey);
+                Ok(())
+            }
+            KeyCode::Down => {
+                state.transition(TuiWidgetEvent::DownKey);
+                Ok(())
+            }
+            KeyCode::Left => {
+                state.transition(TuiWidgetEvent::LeftKey);
+                Ok(())
+            }
+            KeyCode::Right => {
+                state.transition(TuiWidgetEvent::RightKey);
+                Ok(())
+            }
+            KeyCode::Char('+') => {
+                state.transition(TuiWidgetEvent::PlusKey);
+                Ok(())
+            }
+            KeyCode::Char('-') => {
+                state.transition(TuiWidgetEvent::MinusKey);
+                Ok(())
+            }
+            KeyCode::Char('h') => {
+                state.transition(TuiWidgetEvent::HideKey);
+                Ok(())
+            }
+            KeyCode::Char('g') => {
+                state.transition(TuiWidgetEvent::FocusKey);
+                Ok(())
+            }
+
+            // Ctrl-C and Ctrl-Q, plain c and q are keypad keys
+            KeyCode::Char('c' | 'q') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.should_quit = true;
+                Ok(())
+            }
+
+            _ => Ok(()),
+        }
+    }
+
+    /// Debugger keys, only while its panel is open. Returns false for keys it doesn't use.
+    fn handle_debugger_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(2),
+            KeyCode::Down => self.cursor = self.cursor.saturating_add(2),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(16),
+            KeyCode::PageDown => self.cursor = self.cursor.saturating_add(16),
+            KeyCode::Char('n') => {
+                let reason = self.debugger.step(&mut self.machine);
+                self.stopped(reason);
+                self.restart_recording();
+            }
+            KeyCode::Char('o') => {
+                self.debugger.step_over(&mut self.machine);
+                self.cursor = self.machine.cpu.program_counter;
+                self.restart_recording();
+            }
+            KeyCode::Char('u') => {
+                self.debugger.step_out(&mut self.machine);
+                self.cursor = self.machine.cpu.program_counter;
+                self.restart_recording();
+            }
+            KeyCode::Char('b') => {
+                let action = match self.debugger.toggle_breakpoint(self.cursor) {
+                    true => "set",
+                    false => "cleared",
+                };
+                info!(target: "debugger", "Breakpoint at {:#05x} {}", self.cursor, action);
+            }
+            KeyCode::Char('t') => self.debugger.run_to(self.cursor),
+            KeyCode::Char('m') => self.watch_input = Some(String::new()),
+            KeyCode::Char('M') => {
+                self.debugger.clear_watchpoints();
+                info!(target: "debugger", "Watchpoints cleared");
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn edit_watch_input(&mut self, code: KeyCode) {
+        let Some(input) = self.watch_input.as_mut() else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Esc => self.watch_input = None,
+            KeyCode::Enter => match input.parse::<Watchpoint>() {
+                Ok(watchpoint) => {
+                    info!(target: "debugger", "Watching {}", watchpoint);
+                    self.debugger.add_watchpoint(watchpoint);
+                    self.watch_input = None;
+                }
+                // keep the text around to fix it
+                Err(e) => error!(target: "debugger", "Bad watchpoint: {}", e),
+            },
+            _ => {}
+        }
+    }
+
+    /// The debugger stopped the machine: say why and show where.
+    fn stopped(&mut self, reason: StopReason) {
+        match reason {
+            StopReason::Fault(fault) => error!("machine halted: {}", fault),
+            StopReason::Step => {
+                debug!(target: "debugger", "Stepped to {:#05x}", self.machine.cpu.program_counter)
+            }
+            StopReason::Watchpoint { index, .. } => info!(
+                target: "debugger",
+                "Stopped: {} ({})",
+                reason,
+                self.debugger.watchpoints()[index]
+            ),
+            reason => info!(target: "debugger", "Stopped: {}", reason),
+        }
+        self.cursor = self.machine.cpu.program_counter;
+        if !matches!(reason, StopReason::Fault(_)) {
+            self.show_debugger = true;
+        }
+    }
+
+    fn press_key(&mut self, key: u8) {
+        self.set_key(key, true);
+        if self.key_release_fallback {
+            self.key_pressed_at[key as usize] = Some(Instant::now());
+        }
+    }
+
+    fn release_key(&mut self, key: u8) {
+        self.set_key(key, false);
+        self.key_pressed_at[key as usize] = None;
+    }
+
+    fn set_key(&mut self, key: u8, pressed: bool) {
+        self.machine.set_key(key, pressed);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.key(key, pressed);
+        }
+    }
+
+    /// Feeds the replay's keys in before a frame, and gives the keypad back once it's over.
+    fn start_frame(&mut self) {
+        if let KeypadInput::Replay(player) = &self.input {
+            if !player.start_frame(&mut self.machine) {
+                let frames = player.frame();
+                info!(target: "replay", "Replay over after {} frames, keypad's yours", frames);
+                self.input = KeypadInput::Terminal;
+            }
+        }
+    }
+
+    fn end_frame(&mut self) {
+        if let KeypadInput::Replay(player) = &mut self.input {
+            player.end_frame();
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.end_frame();
+        }
+    }
+
+    /// The debugger moved the machine on outside of whole frames, which a replay can't
+    /// reproduce, so the recording carries on from here instead.
+    fn restart_recording(&mut self) {
+        if let Some(recorder) = &mut self.recorder {
+            info!(target: "replay", "Recording starts over where the debugger left off");
+            recorder.restart(&self.machine);
+        }
+    }
+
+    fn rewind_frame(&mut self) {
+        if !self.machine.rewind_frame() {
+            return;
+        }
+        if let KeypadInput::Replay(player) = &mut self.input {
+            player.rewind_frame();
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.rewind_frame();
+        }
+    }
+
+    /// Releases keys that haven't seen a press/repeat within KEY_RELEASE_FALLBACK.
+    /// Only does anything when the terminal can't report releases itself.
+    fn release_stale_keys(&mut self) {
+        if let Some(pressed_at) = self.rewind_pressed_at {
+            if pressed_at.elapsed() >= KEY_RELEASE_FALLBACK {
+                self.rewinding = false;
+                self.rewind_pressed_at = None;
+            }
+        }
+        for key in 0..KEY_COUNT {
+            if let Some(pressed_at) = self.key_pressed_at[key] {
+                if pressed_at.elapsed() >= KEY_RELEASE_FALLBACK {
+                    self.release_key(key as u8);
+                }
+            }
+        }
+    }
+
+    fn slot_path(&self, json: bool) -> String {
+        let extension = if json { ".json" } else { "" };
+        format!("{}.state{}{}", self.rom_path, self.state_slot, extension)
+    }
+
+    fn save_slot(&self, json: bool) {
+        let path = self.slot_path(json);
+        let result = if json {
+            fs::write(&path, self.machine.snapshot().to_json())
+        } else {
+            fs::write(&path, self.machine.save_state())
+        };
+        match result {
+            Ok(()) => info!(target: "state", "Saved slot {} to {}", self.state_slot, path),
+            Err(e) => error!(target: "state", "Couldn't save {}: {}", path, e),
+        }
+    }
+
+    /// Loads the slot's binary state, or its JSON one if that's all there is.
+    fn load_slot(&mut self) {
+        let binary = self.slot_path(false);
+        let path = if fs::metadata(&binary).is_ok() {
+            binary
+        } else {
+            self.slot_path(true)
+        };
+        self.debugger.machine_changed();
+        match load_state_file(&mut self.machine, &path) {
+            Ok(()) => info!(target: "state", "Loaded slot {} from {}", self.state_slot, path),
+            Err(e) => {
+                error!(target: "state", "Couldn't load slot {}: {}", self.state_slot, e);
+                return;
+            }
+        }
+        // the session carries on from somewhere else entirely
+        if let Some(recorder) = &mut self.recorder {
+            info!(target: "replay", "Recording starts over from slot {}", self.state_slot);
+            recorder.restart(&self.machine);
+        }
+        if matches!(self.input, KeypadInput::Replay(_)) {
+            info!(target: "replay", "Replay stopped, keypad's yours");
+            self.input = KeypadInput::Terminal;
+        }
+    }
+
+    /// `<rom>.<n>.png`, the first n that isn't taken.
+    fn save_screenshot(&self) {
+        let path = (1..)
+            .map(|n| format!("{}.{}.png", self.rom_path, n))
+            .find(|path| fs::metadata(path).is_err())
+            .expect("a free name");
+        let png = screenshot::encode_with(&self.machine.gpu, ImageFormat::Png, &self.image);
+        match fs::write(&path, png) {
+            Ok(()) => info!(target: "screenshot", "Saved the screen to {}", path),
+            Err(e) => error!(target: "screenshot", "Couldn't save {}: {}", path, e),
+        }
+    }
+
+    fn selected_state(&self) -> &TuiWidgetState {
+        &self.states[self.selected_tab]
+    }
+
+    fn next_tab(&mut self) {
+        self.selected_tab = (self.selected_tab + 1) % self.tab_names.len();
+    }
+
+    // galus: There is an overflow bug here left for educational porpoises 🎓 🐬
+    fn increment_counter(&mut self) -> Result<()> {
+        match self.progress_counter {
+            Some(value) => {
+                if value >= 2 {
+                    bail!("counter overflow");
+                }
+                self.progress_counter = Some(value + 1);
+            }
+            None => self.progress_counter = Some(1),
+        }
+        Ok(())
+    }
+
+    fn decrement_counter(&mut self) -> Result<()> {
+        match self.progress_counter {
+            Some(value) if value > 0 => {
+                self.progress_counter = Some(value - 1);
+            }
+            _ => {
+                // nothing
+            }
+        }
+        Ok(())
+    }
+
+    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        info!("\t{} Running Emulator...", E["computer"]);
+        let (tx, rx) = mpsc::channel();
+        let event_tx = tx.clone();
+        let progress_tx = tx.clone();
+        info!("spawning io thread");
+        thread::spawn(move || io_thread(event_tx));
+
+        // for testing right now...
+        info!("spawning progress bar thread");
+        thread::spawn(move || progress_task(progress_tx));
+
+        info!("spawning other background tasks");
+        thread::spawn(background_task);
+        thread::spawn(background_task2);
+
+        let mut pacer = FramePacer::new();
+        info!("clock speed {:?}", self.machine.speed());
+        while !self.should_quit {
+            // Input first so this frame's instructions see the latest keypad state.
+            if !self.drain_events(&rx) {
+                error!("Core thread Sender disconnected. Exitting.");
+                break;
+            }
+            self.release_stale_keys();
+            if let Some(gdb) = &mut self.gdb {
+                gdb.poll(&mut self.machine, &mut self.debugger);
+            }
+
+            if self.rewinding {
+                self.rewind_frame();
+                self.debugger.machine_changed();
+            } else if !self.debugger.is_paused() {
+                self.start_frame();
+                let stop = self.debugger.run_frame(&mut self.machine);
+                self.end_frame();
+                if let Some(reason) = stop {
+                    self.stopped(reason);
+                    // the frame was cut short, a replay would run all of it
+                    self.restart_recording();
+                }
+            }
+
+            terminal.draw(|frame| {
+                self.draw(frame);
+            })?;
+
+            pacer.wait_for_next_frame();
+        }
+
+        Ok(())
+    }
+
+    /// Handles every event that arrived since the last frame without blocking.
+    /// Returns false once all the senders are gone.
+    fn drain_events(&mut self, rx: &mpsc::Receiver<AppEvent>) -> bool {
+        loop {
+            match rx.try_recv() {
+                Ok(AppEvent::KeyEvent(key_event)) => {
+                    info!(
+                        "rx.recv got KeyCode {:?} {:?}",
+                        key_event.code, key_event.kind
+                    );
+                    let _ = self.handle_key_event(key_event);
+                }
+                Ok(AppEvent::CounterChanged(x)) => {
+                    info!("counter changed {:?}", x);
+                    self.progress_counter = x;
+                }
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+}
+
+/// Loads a save state file, JSON when it ends in .json and the binary format otherwise.
+pub fn load_state_file(machine: &mut Machine, path: &str) -> Result<()> {
+    let snapshot = if path.ends_with(".json") {
+        Snapshot::from_json(&fs::read_to_string(path)?)
+    } else {
+        Snapshot::from_bytes(&fs::read(path)?)
+    };
+    machine.restore(snapshot.map_err(|e| eyre!("{}: {}", path, e))?);
+    Ok(())
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+} // end Impl Emulator
+
+// -------------------------------------------
+// Threads
+// Separate threads for background tasks.
+// -------------------------------------------
+
+/// Responsible for handling user input.
+fn io_thread(tx: mpsc::Sender<AppEvent>) -> Result<()> {
+    loop {
+        let event = match event::read() {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("Error reading event: {}", e);
+                break;
+            }
+        };
+        // Presses, repeats and (on terminals that report them) releases all go through,
+        // the keypad needs to know when keys are let go.
+        if let Event::Key(key_event) = event {
+            if tx.send(AppEvent::KeyEvent(key_event)).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends AppEvent::CounterChanged events at constant intervals
+fn progress_task(tx: mpsc::Sender<AppEvent>) -> Result<()> {
+    info!(target: "progress-task", "Starting progress task...");
+    for progress in 0..100 {
+        tx.send(AppEvent::CounterChanged(Some(progress)))?;
+        thread::sleep(Duration::from_millis(3000));
+    }
+    info!(target: "progress-task", "Progress task finished!");
+    tx.send(AppEvent::CounterChanged(None))?;
+    Ok(())
+}
+
+/// Spams a bunch of logs every second
+fn background_task() {
+    loop {
+        error!(target: "background-task", "an error");
+        warn!(target: "background-task", "an warning");
+        info!(target: "background-task", "an two line info\nsecond line");
+        debug!(target: "background-task", "an debug");
+        trace!(target: "background-task", "an trace");
+        error!(target: "background-task", "an error");
+        thread::sleep(Duration::from_millis(1000));
+    }
+}
+
+/// Spams a long log message every second
+fn background_task2() {
+    loop {
+        info!(target: "background-task2", "This is a very long message, blah di blah di blah, lets wrap this up with some screen size magic.");
+        thread::sleep(Duration::from_millis(1000));
+    }
+}
+
+/// Spams hearts <3 every 1.5 seconds
+fn heart_task() {
+    let mut line = "<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3<3".to_string();
+    loop {
+        info!(target: "heart-task", "{}", line);
+        line = format!(".{}", line);
+        thread::sleep(time::Duration::from_millis(1500));
+    }
+}
@@ -0,0 +1,133 @@
+// Ratatui widget that draws the Chip8 display.
+use chip8::emu::{error::Fault, gpu::Gpu};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Stylize},
+    symbols::{border, Marker},
+    text::{Line, Text},
+    widgets::{
+        canvas::{Canvas, Rectangle},
+        Block, Paragraph, Widget,
+    },
+};
+
+/// Colours for the XO-CHIP plane combinations 1-3, 0 is left blank.
+/// Plain CHIP-8 only ever uses the first one.
+const PALETTE: [Color; 3] = [Color::Cyan, Color::Magenta, Color::White];
+
+pub struct Screen<'a> {
+    gpu: &'a Gpu,
+    fault: Option<Fault>,
+    exited: bool,
+    slot: u8,
+}
+
+impl<'a> Screen<'a> {
+    pub fn new(gpu: &'a Gpu) -> Self {
+        Self {
+            gpu,
+            fault: None,
+            exited: false,
+            slot: 1,
+        }
+    }
+
+    /// Shows why the machine halted in the status line.
+    pub fn fault(mut self, fault: Option<Fault>) -> Self {
+        self.fault = fault;
+        self
+    }
+
+    /// Tells the player the program quit (SUPER-CHIP 00FD).
+    pub fn exited(mut self, exited: bool) -> Self {
+        self.exited = exited;
+        self
+    }
+
+    /// The save state slot F5/F9 use.
+    pub fn slot(mut self, slot: u8) -> Self {
+        self.slot = slot;
+        self
+    }
+
+    fn content(&self) -> impl Widget + 'a {
+        let gpu = self.gpu;
+        let (width, height) = (self.gpu.width(), self.gpu.height());
+        // hires pixels are half the size so the picture keeps its place on screen
+        let (pixel_width, pixel_height) = if self.gpu.is_hires() {
+            (0.4, 0.2)
+        } else {
+            (0.8, 0.4)
+        };
+        let scale = 64.0 / width as f64;
+
+        Canvas::default()
+            .marker(Marker::Block)
+            .block(Block::bordered().title("Canvas"))
+            .x_bounds([0.0, 64.0])
+            .y_bounds([0.0, 32.0])
+            .paint(move |ctx| {
+                for y in 0..height {
+                    for x in 0..width {
+                        let colour = gpu.colour(y * width + x);
+                        if colour != 0 {
+                            let flipped_y = (height - 1 - y) as f64;
+                            ctx.draw(&Rectangle {
+                                x: x as f64 * scale,
+                                y: flipped_y * scale,
+                                width: pixel_width,
+                                height: pixel_height,
+                                color: PALETTE[colour as usize - 1],
+                            })
+                        }
+                    }
+                }
+            })
+    }
+}
+
+/// I like to say Ratatui
+impl Widget for Screen<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = vec![" GPU".bold(), "<3".red().bold(), " Galus ".bold()];
+
+        let instructions = vec![
+            " Help ".into(),
+            "<?> ".blue().bold(),
+            " Quit ".into(),
+            "<Ctrl-C> ".blue().bold(),
+        ];
+
+        let block = Block::bordered()
+            .title_top(title)
+            .title_bottom(instructions)
+            .border_set(border::THICK);
+
+        let status = match self.fault {
+            Some(fault) => Line::from(vec![
+                " Halted: ".red().bold(),
+                fault.to_string().red(),
+                " ".into(),
+            ]),
+            None if self.exited => Line::from(vec![" Program exited ".yellow().bold()]),
+            None => Line::from(vec![
+                " Slot: ".into(),
+                self.slot.to_string().yellow(),
+                " ".into(),
+            ]),
+        };
+        let counter_text = Text::from(vec![status]);
+
+        let paragraph = Paragraph::new(counter_text).alignment(Alignment::Center);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(block.inner(area));
+
+        block.render(area, buf);
+        paragraph.render(chunks[0], buf);
+        self.content().render(chunks[1], buf);
+    }
+}
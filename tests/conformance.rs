@@ -0,0 +1,190 @@
+// Golden-image conformance tests. Every ROM in tests/roms (a .ch8/.xo8, or a .8o source that
+// gets assembled first) runs headless for FRAMES frames under each quirks profile, and the
+// screen has to match tests/golden/<profile>/<rom>.txt character for character.
+// A <rom>.keys file next to the ROM is played as a key script, see emu/script.rs. ROMs that
+// aren't .8o sources themselves can keep their source in tests/roms/src.
+//
+// Every ROM runs a second time through the decode cache that headless runs use, and with
+// `--features jit` a third time through compiled blocks, against the same golden images.
+//
+// `CHIP8_BLESS=1 cargo test --test conformance` writes the golden images from what the
+// interpreter does now. Look at the diff before checking them in.
+use chip8::emu::{asm::assemble, screenshot, KeyScript, Machine, QuirksProfile};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Two seconds, plenty for the test ROMs to get to their final screen
+const FRAMES: u32 = 120;
+
+/// The ways a machine can run code, by name, every one has to draw the golden images.
+/// The first is the plain interpreter, the one that writes them.
+type Backend = (&'static str, fn(&mut Machine));
+
+const BACKENDS: &[Backend] = &[
+    ("interpreter", |_| {}),
+    ("decode cache", |machine| machine.set_decode_cache(true)),
+    #[cfg(feature = "jit")]
+    ("jit", |machine| machine.set_jit(true)),
+];
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// `path` relative to the crate, for messages.
+fn shown(path: &Path) -> String {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// The ROMs in tests/roms, sorted so failures come out in a stable order.
+fn roms() -> Vec<PathBuf> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(tests_dir().join("roms"))
+        .expect("tests/roms exists")
+        .map(|entry| entry.expect("readable tests/roms").path())
+        .filter(|path| {
+            let extension = path.extension().and_then(|e| e.to_str());
+            matches!(extension, Some("ch8" | "xo8" | "8o"))
+        })
+        .collect();
+    roms.sort();
+    roms
+}
+
+fn load(path: &Path) -> Vec<u8> {
+    if path.extension().is_some_and(|e| e == "8o") {
+        let source = fs::read_to_string(path).expect("readable source");
+        assemble(&source).unwrap_or_else(|e| panic!("{}: {}", shown(path), e))
+    } else {
+        fs::read(path).expect("readable rom")
+    }
+}
+
+/// The screen after FRAMES frames, as screenshot::ascii text, or why there isn't one.
+fn run(
+    path: &Path,
+    rom: &[u8],
+    profile: QuirksProfile,
+    backend: Backend,
+) -> Result<String, String> {
+    let mut machine = Machine::new();
+    machine.set_xo_chip(path.extension().is_some_and(|e| e == "xo8"));
+    (backend.1)(&mut machine);
+    machine.set_quirks(profile.quirks());
+    machine.load_rom(rom).map_err(|e| e.to_string())?;
+    let script = match fs::read_to_string(path.with_extension("keys")) {
+        Ok(text) => KeyScript::parse(&text).map_err(|e| format!("keys: {}", e))?,
+        Err(_) => KeyScript::default(),
+    };
+    script
+        .run(&mut machine, FRAMES)
+        .map_err(|fault| format!("faulted: {}", fault))?;
+    Ok(screenshot::ascii(&machine.gpu))
+}
+
+/// Only the rows that differ, expected above actual, with `^` under the changed pixels.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut out = String::new();
+    if expected.len() != actual.len() {
+        out += &format!("  {} rows expected, got {}\n", expected.len(), actual.len());
+    }
+    for (row, (want, got)) in expected.iter().zip(&actual).enumerate() {
+        if want == got {
+            continue;
+        }
+        let marks: String = want
+            .chars()
+            .zip(got.chars())
+            .map(|(a, b)| if a == b { ' ' } else { '^' })
+            .collect();
+        out += &format!("  row {:2} expected {}\n", row, want);
+        out += &format!("         actual   {}\n", got);
+        out += &format!("                  {}\n", marks);
+    }
+    out
+}
+
+#[test]
+fn test_golden_images() {
+    let bless = std::env::var_os("CHIP8_BLESS").is_some();
+    let roms = roms();
+    assert!(!roms.is_empty(), "no ROMs in tests/roms");
+    let mut failures = Vec::new();
+
+    for path in &roms {
+        let rom = load(path);
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .expect("utf-8 name");
+        let runs = QuirksProfile::ALL
+            .into_iter()
+            .flat_map(|profile| BACKENDS.iter().map(move |&backend| (profile, backend)));
+        for (profile, backend) in runs {
+            let golden = tests_dir()
+                .join("golden")
+                .join(profile.name())
+                .join(format!("{}.txt", name));
+            let interpreter = backend.0 == BACKENDS[0].0;
+            let label = match interpreter {
+                true => format!("{} ({})", shown(path), profile.name()),
+                false => format!("{} ({}, {})", shown(path), profile.name(), backend.0),
+            };
+            let screen = match run(path, &rom, profile, backend) {
+                Ok(screen) => screen,
+                Err(error) => {
+                    failures.push(format!("{}: {}", label, error));
+                    continue;
+                }
+            };
+            // the interpreter writes the images, the others still have to match them
+            if bless && interpreter {
+                fs::create_dir_all(golden.parent().expect("has a parent")).unwrap();
+                fs::write(&golden, &screen).unwrap();
+                continue;
+            }
+            match fs::read_to_string(&golden) {
+                Ok(expected) if expected == screen => {}
+                Ok(expected) => failures.push(format!(
+                    "{}: screen doesn't match {}\n{}",
+                    label,
+                    shown(&golden),
+                    diff(&expected, &screen)
+                )),
+                Err(_) => failures.push(format!(
+                    "{}: no {}, run with CHIP8_BLESS=1 to create it. The screen was:\n{}",
+                    label,
+                    shown(&golden),
+                    screen
+                )),
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+/// ROMs that can't be .8o sources, like the .xo8 ones, keep theirs in tests/roms/src.
+#[test]
+fn test_roms_match_their_sources() {
+    let sources = fs::read_dir(tests_dir().join("roms").join("src")).expect("tests/roms/src");
+    for entry in sources {
+        let source = entry.expect("readable tests/roms/src").path();
+        let name = source.file_stem().expect("a file name");
+        let rom = roms()
+            .into_iter()
+            .find(|rom| rom.file_stem() == Some(name) && rom.extension().is_some_and(|e| e != "8o"))
+            .unwrap_or_else(|| panic!("{} has no ROM in tests/roms", shown(&source)));
+        assert!(
+            load(&source) == load(&rom),
+            "{} is out of date, assemble {} again",
+            shown(&rom),
+            shown(&source)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+// `chip8 dap` the way an editor uses it: the real binary over stdio, debugging a ROM by the
+// lines of its assembler source.
+#![cfg(feature = "tui")]
+use chip8::emu::dap::{read_message, write_message};
+use serde_json::{json, Value};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Adds 3 + 2 + 1 into V0 in a subroutine and exits.
+const SOURCE: &str = "\
+; the test program
+start:
+    LD V0, 0
+    LD V1, 3
+loop:
+    CALL add
+    SE V1, 0
+    JP loop
+    EXIT
+add:
+    ADD V0, V1
+    ADD V1, 0xFF
+    RET
+";
+
+struct Editor {
+    adapter: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    seq: u64,
+}
+
+impl Editor {
+    fn start() -> Self {
+        let mut adapter = Command::new(env!("CARGO_BIN_EXE_chip8"))
+            .arg("dap")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        Self {
+            stdin: adapter.stdin.take().unwrap(),
+            stdout: BufReader::new(adapter.stdout.take().unwrap()),
+            adapter,
+            seq: 0,
+        }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) {
+        self.seq += 1;
+        let request = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        write_message(&mut self.stdin, &request).unwrap();
+    }
+
+    /// The next message that isn't a screen update.
+    fn next(&mut self) -> Value {
+        loop {
+            let message = read_message(&mut self.stdout)
+                .unwrap()
+                .expect("the adapter answers");
+            if message["event"] != "chip8/screen" {
+                return message;
+            }
+        }
+    }
+
+    /// The body of the response, which has to have worked.
+    fn ask(&mut self, command: &str, arguments: Value) -> Value {
+        self.send(command, arguments);
+        let response = self.next();
+        assert_eq!(response["type"], "response");
+        assert_eq!(response["request_seq"], self.seq);
+        assert_eq!(response["success"], true, "{}", response);
+        response["body"].clone()
+    }
+
+    fn event(&mut self, name: &str) -> Value {
+        let event = self.next();
+        assert_eq!(event["event"], name, "{}", event);
+        event["body"].clone()
+    }
+
+    /// (line, name) of every frame
+    fn stack(&mut self) -> Vec<(u64, String)> {
+        let trace = self.ask("stackTrace", json!({"threadId": 1}));
+        let frames = trace["stackFrames"].as_array().unwrap();
+        let frame = |f: &Value| (f["line"].as_u64().unwrap(), f["name"].to_string());
+        frames.iter().map(frame).collect()
+    }
+}
+
+/// Somewhere to put a source for the adapter to read.
+fn source_file(name: &str, text: &str) -> PathBuf {
+    let name = format!("chip8-dap-{}-{}.8o", std::process::id(), name);
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+#[test]
+fn test_debug_by_source_line() {
+    let source = source_file("sum", SOURCE);
+    let mut editor = Editor::start();
+    let capabilities = editor.ask("initialize", json!({"adapterID": "chip8"}));
+    assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+    editor.ask("launch", json!({"source": source, "ipf": 10}));
+    editor.event("initialized");
+
+    // the label line moves down to the instruction under it
+    let body = editor.ask(
+        "setBreakpoints",
+        json!({"source": {"path": source}, "breakpoints": [{"line": 4}, {"line": 10}]}),
+    );
+    assert_eq!(body["breakpoints"][0]["line"], 4);
+    assert_eq!(body["breakpoints"][1]["line"], 11);
+    editor.ask("configurationDone", json!({}));
+    assert_eq!(editor.event("stopped")["reason"], "breakpoint");
+    assert_eq!(editor.stack()[0].0, 4);
+
+    editor.ask("continue", json!({"threadId": 1}));
+    assert_eq!(editor.event("stopped")["reason"], "breakpoint");
+    let stack = editor.stack();
+    assert_eq!(stack.iter().map(|f| f.0).collect::<Vec<_>>(), [11, 6]);
+    assert!(stack[1].1.contains("CALL 0x20C"), "{:?}", stack);
+    let registers = editor.ask("variables", json!({"variablesReference": 1}));
+    assert_eq!(registers["variables"][1]["value"], "0x03");
+
+    editor.ask("stepOut", json!({"threadId": 1}));
+    assert_eq!(editor.event("stopped")["reason"], "step");
+    assert_eq!(editor.stack().len(), 1);
+
+    // the rest of the way to EXIT
+    let body = editor.ask(
+        "setBreakpoints",
+        json!({"source": {"path": source}, "breakpoints": []}),
+    );
+    assert_eq!(body["breakpoints"], json!([]));
+    editor.ask("continue", json!({"threadId": 1}));
+    assert_eq!(editor.event("exited")["exitCode"], 0);
+    editor.event("terminated");
+    let result = editor.ask("evaluate", json!({"expression": "V0"}));
+    assert_eq!(result["result"], "6 (0x6)");
+
+    editor.ask("disconnect", json!({}));
+    assert!(editor.adapter.wait().unwrap().success());
+    std::fs::remove_file(source).unwrap();
+}
+
+#[test]
+fn test_fault() {
+    let source = source_file("fault", "LD V0, 1\nDW 0xFFFF\n");
+    let mut editor = Editor::start();
+    editor.ask("initialize", json!({"adapterID": "chip8"}));
+    editor.ask("launch", json!({"source": source, "stopOnEntry": true}));
+    editor.event("initialized");
+    editor.ask("configurationDone", json!({}));
+    assert_eq!(editor.event("stopped")["reason"], "entry");
+
+    editor.ask("continue", json!({"threadId": 1}));
+    let stopped = editor.event("stopped");
+    assert_eq!(stopped["reason"], "exception");
+    assert!(
+        stopped["text"].as_str().unwrap().contains("ffff"),
+        "{}",
+        stopped
+    );
+    // DW is data, so it has no line to show
+    assert_eq!(editor.stack()[0], (0, "\"0x202: DW 0xFFFF\"".to_string()));
+
+    // a launch that can't work says why
+    editor.send("launch", json!({"source": source}));
+    let response = editor.next();
+    assert_eq!(response["success"], false);
+    assert_eq!(response["message"], "already launched");
+
+    // an editor going away ends it as well
+    drop(editor.stdin);
+    assert!(editor.adapter.wait().unwrap().success());
+    std::fs::remove_file(source).unwrap();
+}
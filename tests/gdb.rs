@@ -0,0 +1,142 @@
+// The GDB stub over a real socket: a server thread runs the machine the way the terminal
+// frontend does, poll then a frame, and the test talks RSP to it like gdb would.
+use chip8::emu::{ClockSpeed, Debugger, Fault, GdbServer, Machine, Snapshot};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// 0x200: 6005 -> V0 = 5, 0x202: 7001 -> V0 += 1, 0x204: 1202 -> loop, 0x206: FFFF
+const ROM: [u8; 8] = [0x60, 0x05, 0x70, 0x01, 0x12, 0x02, 0xFF, 0xFF];
+
+/// Starts a machine with a stub on a free port. The thread hands back how the machine was
+/// left once gdb has been and gone.
+fn serve() -> (SocketAddr, JoinHandle<(Snapshot, Option<Fault>)>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let mut machine = Machine::with_speed(ClockSpeed::Ipf(10));
+        machine.load_rom(&ROM).unwrap();
+        let mut debugger = Debugger::new();
+        // held at the first instruction until gdb is there
+        debugger.pause();
+        let mut server = GdbServer::bind("127.0.0.1:0").unwrap();
+        tx.send(server.local_addr().unwrap()).unwrap();
+        let mut connected = false;
+        loop {
+            server.poll(&mut machine, &mut debugger);
+            if server.is_connected() {
+                connected = true;
+            } else if connected {
+                return (machine.snapshot(), machine.fault());
+            }
+            debugger.run_frame(&mut machine);
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+    (rx.recv().unwrap(), handle)
+}
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        Self { stream }
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).expect("the stub answers");
+        byte[0]
+    }
+
+    fn send(&mut self, body: &str) {
+        let checksum = body.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", body, checksum).unwrap();
+        assert_eq!(self.byte(), b'+', "{} wasn't acked", body);
+    }
+
+    /// The next packet's body, acked.
+    fn reply(&mut self) -> String {
+        while self.byte() != b'$' {}
+        let mut body = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => body.push(byte),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(
+            checksum,
+            body.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+        );
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    fn ask(&mut self, body: &str) -> String {
+        self.send(body);
+        self.reply()
+    }
+}
+
+#[test]
+fn test_session() {
+    let (addr, server) = serve();
+    let mut gdb = Client::connect(addr);
+
+    assert!(gdb
+        .ask("qSupported:multiprocess+;swbreak+")
+        .contains("swbreak+"));
+    let xml = gdb.ask("qXfer:features:read:target.xml:0,fff");
+    assert!(xml.starts_with('l') && xml.contains(r#"<reg name="pc" bitsize="16""#));
+    // nothing has run yet
+    assert_eq!(gdb.ask("?"), "S05");
+    assert_eq!(gdb.ask("p11"), "0002");
+
+    // break on the jump, run to it and look around
+    assert_eq!(gdb.ask("Z0,204,2"), "OK");
+    gdb.send("c");
+    assert_eq!(gdb.reply(), "T05swbreak:;");
+    assert_eq!(gdb.ask("p11"), "0402");
+    assert_eq!(gdb.ask("p0"), "06");
+    assert_eq!(gdb.ask("m200,6"), "600570011202");
+    // addresses that wrap around or run off the end are errors, not a crash
+    assert_eq!(gdb.ask("mffffffffffffffff,2"), "E01");
+    assert_eq!(gdb.ask("Mffffffffffffffff,1:00"), "E01");
+    assert_eq!(gdb.ask("mfff,ffffffffffffffff"), "E01");
+    assert_eq!(gdb.ask("m1000,1"), "E01");
+
+    // patch the loop to add 3, step through it
+    assert_eq!(gdb.ask("M203,1:03"), "OK");
+    assert_eq!(gdb.ask("s"), "S05");
+    assert_eq!(gdb.ask("s"), "S05");
+    assert_eq!(gdb.ask("p0"), "09");
+    assert_eq!(gdb.ask("P0=40"), "OK");
+
+    // ^C stops a continue that would never end by itself
+    assert_eq!(gdb.ask("z0,204,2"), "OK");
+    gdb.send("c");
+    thread::sleep(Duration::from_millis(20));
+    gdb.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(gdb.reply(), "S02");
+    assert!(["0202", "0402"].contains(&gdb.ask("p11").as_str()));
+
+    // jumping into the FFFF faults, an illegal instruction
+    gdb.send("c206");
+    assert_eq!(gdb.reply(), "S04");
+
+    assert_eq!(gdb.ask("D"), "OK");
+    let (machine, fault) = server.join().unwrap();
+    assert_eq!(machine.memory.ram[0x203], 0x03);
+    assert_eq!(machine.cpu.program_counter, 0x206);
+    assert!(fault.is_some());
+}
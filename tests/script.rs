@@ -0,0 +1,31 @@
+// `chip8 script` end to end: the real binary running the scripts in tests/scripts.
+#![cfg(all(feature = "tui", feature = "scripting"))]
+use std::process::{Command, Output};
+
+fn chip8_script(script: &str, rom: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["script", script, rom])
+        .env_remove("CHIP8_BLESS")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_ibm_logo() {
+    let output = chip8_script("tests/scripts/ibm-logo.rhai", "tests/roms/2-ibm-logo.ch8");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 6, "{}", stdout);
+    assert!(stdout.starts_with("sprite at 0x208, I = 0x22a\n"), "{}", stdout);
+}
+
+#[test]
+fn test_failing_script() {
+    // the logo isn't what the maze draws
+    let output = chip8_script("tests/scripts/ibm-logo.rhai", "roms/maze.ch8");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ibm-logo.rhai: line "), "{}", stderr);
+}